@@ -1,4 +1,8 @@
 use clap::{Args, Parser, Subcommand};
+use whamm::common::diagnostics::DiagnosticsFormat;
+use whamm::emitter::text::EmitFormat;
+use whamm::generator::report::ReportFormat;
+use whamm::parser::catalog::InfoFormat;
 
 /// `whamm` instruments a Wasm application with the Probes defined in the specified Script.
 #[derive(Debug, Parser)]
@@ -32,10 +36,44 @@ pub enum Cmd {
         /// Show the functions in-scope when using the probe specification.
         #[arg(long, short, action, default_value = "false")]
         functions: bool,
+
+        /// The path to an application's Wasm module, to list every site in it that the
+        /// probe specification matches (and the static data bound at each site). With
+        /// `--format json` or `markdown`, the sites are part of the export.
+        #[arg(long, value_parser)]
+        app: Option<String>,
+
+        /// How to write the information (to stdout for `json` and `markdown`). The exports
+        /// hold the whole provider -> package -> event -> mode tree that the spec matches,
+        /// with every global and function.
+        #[arg(long, value_enum, default_value_t = InfoFormat::Text)]
+        format: InfoFormat,
+
+        /// How to write errors and warnings (to stderr).
+        #[arg(long, value_enum, default_value_t = DiagnosticsFormat::Text)]
+        diagnostics_format: DiagnosticsFormat,
     },
 
     /// To instrument a Wasm application.
     Instr(InstrArgs),
+
+    /// To run a language server for `.mm` scripts, speaking LSP over stdin/stdout.
+    Lsp,
+
+    /// To format Scripts in place with canonical indentation and spacing (comments are kept).
+    Fmt {
+        /// The paths to the Scripts to format.
+        #[arg(value_parser, required = true)]
+        scripts: Vec<String>,
+
+        /// Don't write anything, exit with an error if any of the Scripts isn't formatted.
+        #[arg(long, action, default_value = "false")]
+        check: bool,
+
+        /// How to write errors and warnings (to stderr).
+        #[arg(long, value_enum, default_value_t = DiagnosticsFormat::Text)]
+        diagnostics_format: DiagnosticsFormat,
+    },
 }
 
 // #[derive(Debug, Args)]
@@ -45,19 +83,57 @@ pub enum Cmd {
 
 #[derive(Debug, Args)]
 pub struct InstrArgs {
-    /// The path to the application's Wasm module we want to instrument.
+    /// The path to the application's Wasm module we want to instrument (in the binary or text
+    /// format). This can also be a component, its core modules are instrumented.
     #[arg(short, long, value_parser)]
     pub app: String,
-    /// The path to the Script containing the instrumentation Probe definitions.
+    /// If the application is a component, only instrument the core module at this index (in
+    /// the order they appear in the binary). By default, every core module is instrumented.
+    #[arg(long, value_parser)]
+    pub core_module: Option<u32>,
+    /// The path to a Script containing the instrumentation Probe definitions. Pass this more
+    /// than once to instrument with several scripts, their probes are injected in the order
+    /// the scripts are passed.
+    #[arg(short, long, value_parser, required_unless_present = "script_list")]
+    pub script: Vec<String>,
+    /// The path to a file listing Scripts to instrument with, one path per line (blank lines
+    /// and lines starting with `#` are skipped). These come after any passed with `--script`.
+    #[arg(long, value_parser)]
+    pub script_list: Option<String>,
+    /// Only instrument the functions whose name or ID matches this glob pattern, e.g. `app_*`
+    /// or `12`. Pass this more than once for several patterns. By default, every function is
+    /// instrumented.
+    #[arg(long, value_parser)]
+    pub include_func: Vec<String>,
+    /// Don't instrument the functions whose name or ID matches this glob pattern, e.g. `malloc`
+    /// or `__*`. Pass this more than once for several patterns, these win over `--include-func`.
+    #[arg(long, value_parser)]
+    pub exclude_func: Vec<String>,
+    /// Don't instrument the calls to functions imported from this module, e.g.
+    /// `wasi_snapshot_preview1`. Pass this more than once for several modules.
+    #[arg(long, value_parser)]
+    pub skip_imports_of: Vec<String>,
+    /// The path that the instrumented version of the Wasm app should be output to. Defaults to
+    /// `./output/output.wasm`, or `./output/output.wat` with `--emit wat`.
     #[arg(short, long, value_parser)]
-    pub script: String,
-    /// The path that the instrumented version of the Wasm app should be output to.
-    #[arg(short, long, value_parser, default_value = "./output/output.wasm")]
-    pub output_path: String,
+    pub output_path: Option<String>,
+
+    /// The format to write the instrumented module in, `wat` marks the injected code with
+    /// comments.
+    #[arg(long, value_enum, default_value_t = EmitFormat::Wasm)]
+    pub emit: EmitFormat,
 
     /// Whether to emit Virgil code as the instrumentation code
     #[arg(short, long, action, default_value = "false")]
     pub virgil: bool,
+
+    /// Print where each probe was injected (to stdout), as a table unless `json` is passed.
+    #[arg(long, value_enum, num_args = 0..=1, default_missing_value = "table")]
+    pub report: Option<ReportFormat>,
+
+    /// How to write errors and warnings (to stderr).
+    #[arg(long, value_enum, default_value_t = DiagnosticsFormat::Text)]
+    pub diagnostics_format: DiagnosticsFormat,
 }
 
 // pub fn print_completion<G: Generator>(gen: G, app: &mut App) {
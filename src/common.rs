@@ -1,2 +1,7 @@
+pub mod diagnostics;
 pub mod error;
+pub mod suggest;
 pub mod terminal;
+
+#[cfg(test)]
+pub mod tests;
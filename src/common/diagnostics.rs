@@ -0,0 +1,261 @@
+use crate::common::error::{CodeLocation, Diagnostics};
+use pest::error::LineColLocation;
+use serde_json::{json, Value};
+
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+const SARIF_VERSION: &str = "2.1.0";
+
+/// How diagnostics are written out.
+#[derive(Clone, Copy, Debug, Default, PartialEq, clap::ValueEnum)]
+pub enum DiagnosticsFormat {
+    /// Colored text for a terminal
+    #[default]
+    Text,
+    /// A JSON object listing each diagnostic
+    Json,
+    /// A SARIF 2.1.0 log, e.g. to annotate code reviews in CI
+    Sarif,
+}
+
+/// A single error or warning, flattened to the parts shared by all formats.
+struct Entry<'a> {
+    severity: &'static str,
+    fatal: bool,
+    ty: &'a str,
+    message: String,
+    err_loc: &'a Option<CodeLocation>,
+    info_loc: &'a Option<CodeLocation>,
+}
+
+/// Write the diagnostics of several scripts to stderr in the passed format, as a single
+/// document: the JSON object lists the diagnostics of every script, and the SARIF log has a run
+/// for each script.
+pub fn emit_all(all: &mut [Diagnostics], format: DiagnosticsFormat) {
+    match format {
+        DiagnosticsFormat::Text => all.iter_mut().for_each(|diagnostics| diagnostics.report()),
+        DiagnosticsFormat::Json => eprintln!("{:#}", all_to_json(all)),
+        DiagnosticsFormat::Sarif => eprintln!("{:#}", all_to_sarif(all)),
+    }
+}
+
+pub fn all_to_json(all: &[Diagnostics]) -> Value {
+    let diagnostics: Vec<Value> = all
+        .iter()
+        .flat_map(|diagnostics| diagnostics.json_entries())
+        .collect();
+    json!({ "diagnostics": diagnostics })
+}
+
+pub fn all_to_sarif(all: &[Diagnostics]) -> Value {
+    let runs: Vec<Value> = all
+        .iter()
+        .map(|diagnostics| diagnostics.sarif_run())
+        .collect();
+    json!({
+        "$schema": SARIF_SCHEMA,
+        "version": SARIF_VERSION,
+        "runs": runs,
+    })
+}
+
+impl Diagnostics {
+    /// Write the diagnostics to stderr in the passed format. Machine-readable formats are
+    /// written even if there is nothing to report, so that tools can always consume the output.
+    pub fn emit(&mut self, format: DiagnosticsFormat) {
+        emit_all(std::slice::from_mut(self), format)
+    }
+
+    fn entries(&self) -> Vec<Entry> {
+        let warnings = self.warnings.iter().map(|warning| Entry {
+            severity: "warning",
+            fatal: false,
+            ty: warning.ty.name(),
+            message: warning.ty.message().to_string(),
+            err_loc: &warning.warn_loc,
+            info_loc: &warning.info_loc,
+        });
+        let errors = self.errors.iter().map(|error| Entry {
+            severity: "error",
+            fatal: error.fatal,
+            ty: error.ty.name(),
+            message: error.ty.message().to_string(),
+            err_loc: &error.err_loc,
+            info_loc: &error.info_loc,
+        });
+        warnings.chain(errors).collect()
+    }
+
+    pub fn to_json(&self) -> Value {
+        all_to_json(std::slice::from_ref(self))
+    }
+
+    fn json_entries(&self) -> Vec<Value> {
+        self.entries()
+            .iter()
+            .map(|entry| {
+                json!({
+                    "severity": entry.severity,
+                    "fatal": entry.fatal,
+                    "type": entry.ty,
+                    "message": entry.message,
+                    "script_path": self.script_path,
+                    "err_span": entry.err_loc.as_ref().map(span_to_json),
+                    "info_span": entry.info_loc.as_ref().map(span_to_json),
+                })
+            })
+            .collect()
+    }
+
+    pub fn to_sarif(&self) -> Value {
+        all_to_sarif(std::slice::from_ref(self))
+    }
+
+    /// The SARIF run for this script's diagnostics.
+    fn sarif_run(&self) -> Value {
+        let entries = self.entries();
+
+        let mut rules: Vec<&str> = entries.iter().map(|entry| entry.ty).collect();
+        rules.sort();
+        rules.dedup();
+        let rules: Vec<Value> = rules.iter().map(|id| json!({ "id": id })).collect();
+
+        let results: Vec<Value> = entries
+            .iter()
+            .map(|entry| {
+                let mut result = json!({
+                    "ruleId": entry.ty,
+                    "level": entry.severity,
+                    "message": { "text": entry.message },
+                    "locations": [self.sarif_location(entry.err_loc.as_ref())],
+                });
+                if let Some(info_loc) = entry.info_loc {
+                    let mut related = self.sarif_location(Some(info_loc));
+                    related["id"] = json!(0);
+                    if let Some(message) = &info_loc.message {
+                        related["message"] = json!({ "text": message });
+                    }
+                    result["relatedLocations"] = json!([related]);
+                }
+                result
+            })
+            .collect();
+
+        json!({
+            "tool": {
+                "driver": {
+                    "name": "whamm",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": rules,
+                }
+            },
+            "results": results,
+        })
+    }
+
+    /// The `Diagnostic` objects of an LSP `textDocument/publishDiagnostics` notification for
+    /// the document at `uri`.
+    pub fn to_lsp(&self, uri: &str) -> Vec<Value> {
+        self.entries()
+            .iter()
+            .map(|entry| {
+                let mut diagnostic = json!({
+                    "range": lsp_range(
+                        &self.script_text,
+                        entry.err_loc.as_ref().map(|loc| &loc.line_col),
+                    ),
+                    // 1 is an Error and 2 is a Warning
+                    "severity": if entry.severity == "error" { 1 } else { 2 },
+                    "code": entry.ty,
+                    "source": "whamm",
+                    "message": entry.message,
+                });
+                if let Some(info_loc) = entry.info_loc {
+                    diagnostic["relatedInformation"] = json!([{
+                        "location": {
+                            "uri": uri,
+                            "range": lsp_range(&self.script_text, Some(&info_loc.line_col)),
+                        },
+                        "message": info_loc.message.clone().unwrap_or_default(),
+                    }]);
+                }
+                diagnostic
+            })
+            .collect()
+    }
+
+    /// A diagnostic without a code location still points at the script it's about.
+    fn sarif_location(&self, loc: Option<&CodeLocation>) -> Value {
+        let mut physical = json!({
+            "artifactLocation": { "uri": self.script_path },
+        });
+        if let Some(loc) = loc {
+            let ((start_line, start_col), (end_line, end_col)) = bounds(&loc.line_col);
+            physical["region"] = json!({
+                "startLine": start_line,
+                "startColumn": start_col,
+                "endLine": end_line,
+                "endColumn": end_col,
+            });
+        }
+        json!({ "physicalLocation": physical })
+    }
+}
+
+fn bounds(line_col: &LineColLocation) -> ((usize, usize), (usize, usize)) {
+    match line_col {
+        LineColLocation::Pos(pos) => (*pos, *pos),
+        LineColLocation::Span(start, end) => (*start, *end),
+    }
+}
+
+fn span_to_json(loc: &CodeLocation) -> Value {
+    let ((start_line, start_col), (end_line, end_col)) = bounds(&loc.line_col);
+    json!({
+        "start": { "line": start_line, "col": start_col },
+        "end": { "line": end_line, "col": end_col },
+        "message": loc.message,
+    })
+}
+
+/// The range of `line_col` in the `text` of the document. LSP positions are zero-based and
+/// count UTF-16 code units, pest's are one-based and count chars. Anything without a location is
+/// put at the start of the document.
+pub(crate) fn lsp_range(text: &str, line_col: Option<&LineColLocation>) -> Value {
+    let ((start_line, start_col), (end_line, end_col)) = match line_col {
+        Some(line_col) => bounds(line_col),
+        None => ((1, 1), (1, 1)),
+    };
+    json!({
+        "start": lsp_position(text, start_line, start_col),
+        "end": lsp_position(text, end_line, end_col),
+    })
+}
+
+fn lsp_position(text: &str, line: usize, col: usize) -> Value {
+    let line = line.saturating_sub(1);
+    let line_text = text.lines().nth(line).unwrap_or_default();
+    json!({ "line": line, "character": utf16_col(line_text, col.saturating_sub(1)) })
+}
+
+/// The (zero-based) column of the char at `col` in the `line`, in UTF-16 code units. Columns
+/// past the end of the line are one unit per char.
+pub(crate) fn utf16_col(line: &str, col: usize) -> usize {
+    line.chars()
+        .chain(std::iter::repeat(' '))
+        .take(col)
+        .map(char::len_utf16)
+        .sum()
+}
+
+/// The (zero-based) column of the char at `character` UTF-16 code units into the `line`, the
+/// inverse of `utf16_col`. A `character` in the middle of a char is put after the char.
+pub(crate) fn char_col(line: &str, character: usize) -> usize {
+    let mut units = 0;
+    for (col, c) in line.chars().enumerate() {
+        if units >= character {
+            return col;
+        }
+        units += c.len_utf16();
+    }
+    line.chars().count() + character.saturating_sub(units)
+}
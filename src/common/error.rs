@@ -18,6 +18,8 @@ pub struct ErrorGen {
     num_errors: i32,
     pub too_many: bool,
     pub has_errors: bool,
+    /// Whether an error was reported that keeps the current phase's output from being used
+    pub has_fatal: bool,
     pub has_warnings: bool,
 }
 impl ErrorGen {
@@ -31,26 +33,35 @@ impl ErrorGen {
             num_errors: 0,
             too_many: false,
             has_errors: false,
+            has_fatal: false,
             has_warnings: false,
         }
     }
 
+    /// Errors are only collected here, whoever drives the phases decides when to stop
+    /// (see `check_fatal` and `check_has_errors`).
     pub fn add_error(&mut self, error: WhammError) {
-        let fatal = error.fatal;
+        if error.fatal {
+            self.has_fatal = true;
+        }
         self.errors.push(error);
         self.inc_errors();
-
-        if fatal {
-            self.fatal_report("Fatal");
-        }
     }
 
     pub fn add_errors(&mut self, errors: Vec<WhammError>) {
         for error in errors {
             self.add_error(error);
         }
+    }
 
-        self.check_too_many();
+    /// Take back the errors collected so far, to hand them to the `ErrorGen` of the script
+    /// they're about (see `add_errors`). This generator no longer counts them.
+    pub fn take_errors(&mut self) -> Vec<WhammError> {
+        self.num_errors = 0;
+        self.too_many = false;
+        self.has_errors = false;
+        self.has_fatal = false;
+        mem::take(&mut self.errors)
     }
 
     pub fn set_script_text(&mut self, script_text: String) {
@@ -68,25 +79,32 @@ impl ErrorGen {
         self.errors.clear();
     }
 
-    pub fn fatal_report(&mut self, context: &str) {
-        if !&self.has_errors {
-            return;
+    /// Hand off the errors and warnings collected so far, e.g. to return them to a library user.
+    pub fn take_diagnostics(&mut self) -> Diagnostics {
+        Diagnostics {
+            script_path: self.script_path.clone(),
+            script_text: self.script_text.clone(),
+            errors: mem::take(&mut self.errors),
+            warnings: mem::take(&mut self.warnings),
         }
-        self.report();
-        panic!("{context}: Reached a fatal error or warning. Exiting...");
     }
 
-    pub fn check_has_errors(&mut self) {
-        if self.has_errors {
-            self.report();
-            panic!("Has errors. Exiting...");
+    /// Hands back the collected diagnostics if the next phase can't run: there was a fatal
+    /// error or there have been too many errors.
+    pub fn check_fatal(&mut self) -> Result<(), Diagnostics> {
+        if self.has_fatal || self.too_many {
+            Err(self.take_diagnostics())
+        } else {
+            Ok(())
         }
     }
 
-    pub fn check_too_many(&mut self) {
-        if self.too_many {
-            self.report();
-            panic!("Too many errors. Exiting...");
+    /// Hands back the collected diagnostics if there were any errors.
+    pub fn check_has_errors(&mut self) -> Result<(), Diagnostics> {
+        if self.has_errors {
+            Err(self.take_diagnostics())
+        } else {
+            Ok(())
         }
     }
 
@@ -103,13 +121,25 @@ impl ErrorGen {
         }
     }
 
-    pub fn multiple_alt_matches(&mut self, instr_name: &str) {
-        let msg = format!(
-            "Multiple `alt` probes matched same bytecode location for instr_name: {}",
-            instr_name
-        );
-        let err = Self::get_instrumentation_error(true, msg);
-        self.add_error(err);
+    /// An instrumentation error that points at the probe that caused it.
+    pub fn get_instrumentation_error_from_loc(
+        fatal: bool,
+        message: String,
+        loc: &Option<Location>,
+    ) -> WhammError {
+        let err_loc = loc.as_ref().map(|loc| CodeLocation {
+            is_err: true,
+            message: Some(message.clone()),
+            line_col: loc.line_col.clone(),
+            line_str: None,
+            line2_str: None,
+        });
+        WhammError {
+            fatal,
+            ty: ErrorType::InstrumentationError { message },
+            err_loc,
+            info_loc: None,
+        }
     }
 
     pub fn get_parse_error(
@@ -422,6 +452,60 @@ impl ErrorGen {
         };
         self.add_warn(warn);
     }
+    pub fn get_lint_warn(message: String, loc: Option<LineColLocation>) -> WhammWarning {
+        let loc = loc.map(|loc| CodeLocation {
+            is_err: false,
+            message: Some(message.clone()),
+            line_col: loc,
+            line_str: None,
+            line2_str: None,
+        });
+        WhammWarning {
+            ty: WarnType::LintWarning { message },
+            warn_loc: loc,
+            info_loc: None,
+        }
+    }
+
+    pub fn get_instr_warn(message: String, loc: Option<LineColLocation>) -> WhammWarning {
+        let loc = loc.map(|loc| CodeLocation {
+            is_err: false,
+            message: Some(message.clone()),
+            line_col: loc,
+            line_str: None,
+            line2_str: None,
+        });
+        WhammWarning {
+            ty: WarnType::InstrumentationWarning { message },
+            warn_loc: loc,
+            info_loc: None,
+        }
+    }
+}
+
+/// The errors and warnings collected while processing a script, along with the script
+/// they point into.
+#[derive(Default)]
+pub struct Diagnostics {
+    pub script_path: String,
+    pub script_text: String,
+    pub errors: Vec<WhammError>,
+    pub warnings: Vec<WhammWarning>,
+}
+impl Diagnostics {
+    pub fn has_errors(&self) -> bool {
+        !self.errors.is_empty()
+    }
+
+    /// report the diagnostics to the console, the same way as `ErrorGen::report`
+    pub fn report(&mut self) {
+        self.warnings.iter_mut().for_each(|warning| {
+            warning.report(&self.script_text, &self.script_path);
+        });
+        self.errors.iter_mut().for_each(|error| {
+            error.report(&self.script_text, &self.script_path);
+        });
+    }
 }
 
 pub struct CodeLocation {
@@ -711,6 +795,20 @@ impl WhammError {
         self.fatal
     }
 
+    /// Attach a "did you mean" note for the misspelled name at this error's location.
+    pub fn with_suggestion(mut self, suggestion: Option<&String>) -> Self {
+        if let (Some(suggestion), Some(err_loc)) = (suggestion, &self.err_loc) {
+            self.info_loc = Some(CodeLocation {
+                is_err: false,
+                message: Some(format!("did you mean `{}`?", suggestion)),
+                line_col: err_loc.line_col.clone(),
+                line_str: None,
+                line2_str: None,
+            });
+        }
+        self
+    }
+
     /// report this error to the console, including color highlighting
     pub fn report(&mut self, script: &str, script_path: &String) {
         let spacing = self.spacing();
@@ -805,18 +903,24 @@ impl WhammError {
 }
 pub enum WarnType {
     TypeCheckWarning { message: String },
+    LintWarning { message: String },
+    InstrumentationWarning { message: String },
     Warning { message: Option<String> },
 }
 impl WarnType {
     pub fn name(&self) -> &str {
         match self {
             WarnType::TypeCheckWarning { .. } => "TypeCheckWarning",
+            WarnType::LintWarning { .. } => "LintWarning",
+            WarnType::InstrumentationWarning { .. } => "InstrumentationWarning",
             WarnType::Warning { .. } => "GeneralWarning",
         }
     }
     pub fn message(&self) -> Cow<'_, str> {
         match self {
-            WarnType::TypeCheckWarning { ref message } => Cow::Borrowed(message),
+            WarnType::TypeCheckWarning { ref message }
+            | WarnType::LintWarning { ref message }
+            | WarnType::InstrumentationWarning { ref message } => Cow::Borrowed(message),
             WarnType::Warning { ref message } => {
                 if let Some(msg) = message {
                     Cow::Borrowed(msg)
@@ -0,0 +1,54 @@
+/// Find the option closest to a misspelled `name`, if any is close enough to be what was meant.
+/// An option is close enough when it's within about a third of the name's length in edits.
+pub fn closest_match<'a>(
+    name: &str,
+    options: impl IntoIterator<Item = &'a String>,
+) -> Option<&'a String> {
+    let max_dist = (name.chars().count() / 3).max(1);
+    let mut best: Option<(usize, &String)> = None;
+    for option in options {
+        if option == name {
+            continue;
+        }
+        let dist = edit_distance(name, option);
+        let is_better = match best {
+            None => true,
+            // break ties alphabetically so the suggestion doesn't depend on the options' order
+            Some((best_dist, best_option)) => {
+                dist < best_dist || (dist == best_dist && option < best_option)
+            }
+        };
+        if dist <= max_dist && is_better {
+            best = Some((dist, option));
+        }
+    }
+    best.map(|(_, option)| option)
+}
+
+/// The number of single character insertions, deletions, substitutions and swaps of adjacent
+/// characters it takes to turn `a` into `b` (the optimal string alignment distance).
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // dist[i][j] is the distance between the first i chars of `a` and the first j chars of `b`
+    let mut dist = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in dist.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, d) in dist[0].iter_mut().enumerate() {
+        *d = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut d = (dist[i - 1][j - 1] + cost)
+                .min(dist[i - 1][j] + 1)
+                .min(dist[i][j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d = d.min(dist[i - 2][j - 2] + 1);
+            }
+            dist[i][j] = d;
+        }
+    }
+    dist[a.len()][b.len()]
+}
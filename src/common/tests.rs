@@ -0,0 +1,120 @@
+use crate::common::diagnostics::{all_to_json, all_to_sarif};
+use crate::common::error::ErrorGen;
+use crate::common::suggest::{closest_match, edit_distance};
+use crate::parser::whamm_parser::parse_script;
+use crate::verifier::verifier::{build_symbol_table, type_check};
+use serde_json::json;
+
+use log::error;
+
+pub fn setup_logger() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+const SCRIPT_PATH: &str = "monitor.mm";
+
+fn get_diagnostics_err(script: &str) -> ErrorGen {
+    let mut err = ErrorGen::new(SCRIPT_PATH.to_string(), "".to_string(), 15);
+    if let Some(mut ast) = parse_script(&script.to_string(), &mut err) {
+        let mut table = build_symbol_table(&mut ast, &mut err);
+        type_check(&mut ast, &mut table, &mut err);
+    }
+    if !err.has_errors {
+        error!("script = '{}' should have errors", script);
+    }
+    assert!(err.has_errors);
+    err
+}
+
+// =============
+// = The Tests =
+// =============
+
+#[test]
+pub fn test_diagnostics_json() {
+    setup_logger();
+    let mut err = get_diagnostics_err(r#"wasm:opcode:br:before / "i" <= 1 / { }"#);
+    let json = err.take_diagnostics().to_json();
+
+    let diagnostics = json["diagnostics"].as_array().unwrap();
+    assert!(!diagnostics.is_empty());
+    let first = &diagnostics[0];
+    assert_eq!(first["severity"], json!("error"));
+    assert_eq!(first["type"], json!("TypeCheckError"));
+    assert_eq!(first["script_path"], json!(SCRIPT_PATH));
+    assert_eq!(first["err_span"]["start"]["line"], json!(1));
+    assert!(first["message"].is_string());
+}
+
+#[test]
+pub fn test_diagnostics_sarif() {
+    setup_logger();
+    let mut err = get_diagnostics_err("wasm:opcode:br:before { i32 a; i32 a; }");
+    let sarif = err.take_diagnostics().to_sarif();
+
+    assert_eq!(sarif["version"], json!("2.1.0"));
+    let run = &sarif["runs"][0];
+    assert_eq!(run["tool"]["driver"]["name"], json!("whamm"));
+
+    let result = &run["results"][0];
+    assert_eq!(result["ruleId"], json!("DuplicateIdentifierError"));
+    assert_eq!(result["level"], json!("error"));
+    let location = &result["locations"][0]["physicalLocation"];
+    assert_eq!(location["artifactLocation"]["uri"], json!(SCRIPT_PATH));
+    assert_eq!(location["region"]["startLine"], json!(1));
+    // points at the other definition
+    assert!(result["relatedLocations"][0]["physicalLocation"]["region"].is_object());
+    assert!(run["tool"]["driver"]["rules"]
+        .as_array()
+        .unwrap()
+        .contains(&json!({ "id": "DuplicateIdentifierError" })));
+}
+
+#[test]
+pub fn test_diagnostics_without_errors() {
+    setup_logger();
+    let mut err = ErrorGen::new(SCRIPT_PATH.to_string(), "".to_string(), 15);
+    let diagnostics = err.take_diagnostics();
+    assert_eq!(diagnostics.to_json(), json!({ "diagnostics": [] }));
+    assert_eq!(diagnostics.to_sarif()["runs"][0]["results"], json!([]));
+}
+
+#[test]
+pub fn test_diagnostics_of_several_scripts() {
+    setup_logger();
+    let mut first = get_diagnostics_err(r#"wasm:opcode:br:before / "i" <= 1 / { }"#);
+    let mut second = ErrorGen::new("other.mm".to_string(), "".to_string(), 15);
+    let all = [first.take_diagnostics(), second.take_diagnostics()];
+
+    // A single document, the SARIF log has a run for each script
+    let sarif = all_to_sarif(&all);
+    let runs = sarif["runs"].as_array().unwrap();
+    assert_eq!(runs.len(), 2);
+    assert!(!runs[0]["results"].as_array().unwrap().is_empty());
+    assert_eq!(runs[1]["results"], json!([]));
+
+    let json = all_to_json(&all);
+    let diagnostics = json["diagnostics"].as_array().unwrap();
+    assert!(diagnostics
+        .iter()
+        .all(|diagnostic| diagnostic["script_path"] == json!(SCRIPT_PATH)));
+}
+
+#[test]
+pub fn test_closest_match() {
+    setup_logger();
+    assert_eq!(edit_distance("opcode", "opcode"), 0);
+    assert_eq!(edit_distance("opcod", "opcode"), 1);
+    assert_eq!(edit_distance("cuont", "count"), 1);
+    assert_eq!(edit_distance("br_fi", "br_if"), 1);
+    assert_eq!(edit_distance("", "abc"), 3);
+
+    let options: Vec<String> = ["br", "br_if", "br_table", "call"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+    assert_eq!(closest_match("br_fi", &options), Some(&options[1]));
+    assert_eq!(closest_match("cal", &options), Some(&options[3]));
+    // too far from any of them to be a typo
+    assert_eq!(closest_match("local_get", &options), None);
+}
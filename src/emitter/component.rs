@@ -0,0 +1,128 @@
+/// The version and layer fields of a component's preamble (the magic number is the same as
+/// for a core module).
+const COMPONENT_VERSION: [u8; 4] = [0x0d, 0x00, 0x01, 0x00];
+const PREAMBLE_LEN: usize = 8;
+
+/// The ID of the section that holds an embedded core module
+const CORE_MODULE_SECTION: u8 = 1;
+/// The ID of the section that holds a nested component
+const COMPONENT_SECTION: u8 = 4;
+
+/// Whether the passed binary is a Wasm component rather than a core module.
+pub fn is_component(app: &[u8]) -> bool {
+    app.len() >= PREAMBLE_LEN && app[..4] == *b"\0asm" && app[4..PREAMBLE_LEN] == COMPONENT_VERSION
+}
+
+/// Call `instrument` on each core module embedded in the `component` (including those in
+/// nested components), then re-encode the component with the modules it returns. Modules
+/// are numbered in the order they appear in the binary, `instrument` is passed that index
+/// and returns `None` to leave a module as is. Every other section is copied verbatim.
+///
+/// The component's imports, exports and instantiations refer to the items of a core module by
+/// index, so a module can only be replaced by one that keeps its imports and exports (which
+/// is what the instrumentation does).
+pub fn map_core_modules<E>(
+    component: &[u8],
+    instrument: &mut impl FnMut(u32, &[u8]) -> Result<Option<Vec<u8>>, E>,
+) -> Result<Vec<u8>, ComponentError<E>> {
+    let mut next_module = 0;
+    map_sections(component, &mut next_module, instrument)
+}
+
+/// Count the core modules embedded in the `component` (including those in nested components).
+pub fn num_core_modules(component: &[u8]) -> Result<u32, String> {
+    let mut num_modules = 0;
+    let counted = map_core_modules(component, &mut |_, _| {
+        num_modules += 1;
+        Ok::<_, ()>(None)
+    });
+    match counted {
+        Ok(_) => Ok(num_modules),
+        Err(ComponentError::Malformed(e)) => Err(e),
+        Err(ComponentError::Instrument(())) => unreachable!(),
+    }
+}
+
+#[derive(Debug)]
+pub enum ComponentError<E> {
+    /// The component's sections couldn't be read
+    Malformed(String),
+    /// Instrumenting one of its core modules failed
+    Instrument(E),
+}
+
+fn map_sections<E>(
+    component: &[u8],
+    next_module: &mut u32,
+    instrument: &mut impl FnMut(u32, &[u8]) -> Result<Option<Vec<u8>>, E>,
+) -> Result<Vec<u8>, ComponentError<E>> {
+    if !is_component(component) {
+        return Err(ComponentError::Malformed(
+            "expected the preamble of a component".to_string(),
+        ));
+    }
+    let mut encoded = component[..PREAMBLE_LEN].to_vec();
+    let mut offset = PREAMBLE_LEN;
+    while offset < component.len() {
+        let id = component[offset];
+        let (size, size_len) =
+            read_u32(component, offset + 1).map_err(ComponentError::Malformed)?;
+        let start = offset + 1 + size_len;
+        let end = start + size as usize;
+        if end > component.len() {
+            return Err(ComponentError::Malformed(format!(
+                "section {} at offset {:#x} runs past the end of the component",
+                id, offset
+            )));
+        }
+        let contents = &component[start..end];
+
+        let replaced = match id {
+            CORE_MODULE_SECTION => {
+                let idx = *next_module;
+                *next_module += 1;
+                instrument(idx, contents).map_err(ComponentError::Instrument)?
+            }
+            COMPONENT_SECTION => Some(map_sections(contents, next_module, instrument)?),
+            _ => None,
+        };
+        match replaced {
+            Some(contents) => {
+                encoded.push(id);
+                write_u32(contents.len() as u32, &mut encoded);
+                encoded.extend_from_slice(&contents);
+            }
+            None => encoded.extend_from_slice(&component[offset..end]),
+        }
+        offset = end;
+    }
+    Ok(encoded)
+}
+
+/// Read the LEB128-encoded u32 at `offset`, returns it along with its length in bytes.
+pub(crate) fn read_u32(bytes: &[u8], offset: usize) -> Result<(u32, usize), String> {
+    let mut val: u32 = 0;
+    for i in 0..5 {
+        let byte = match bytes.get(offset + i) {
+            Some(byte) => *byte,
+            None => return Err(format!("unexpected end of the binary at {:#x}", offset)),
+        };
+        val |= ((byte & 0x7f) as u32) << (i * 7);
+        if byte & 0x80 == 0 {
+            return Ok((val, i + 1));
+        }
+    }
+    Err(format!("invalid section size at {:#x}", offset))
+}
+
+pub(crate) fn write_u32(mut val: u32, bytes: &mut Vec<u8>) {
+    loop {
+        let byte = (val & 0x7f) as u8;
+        val >>= 7;
+        if val == 0 {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
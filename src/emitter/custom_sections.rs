@@ -0,0 +1,279 @@
+use std::collections::{BTreeMap, BTreeSet};
+use wasm_encoder::{
+    IndirectNameMap, Module, NameMap, NameSection, ProducersField, ProducersSection, RawSection,
+};
+use wasmparser::{KnownCustom, Name, NameSectionReader, Parser, Payload, ProducersSectionReader};
+
+/// The IDs of the subsections of the `name` section that whamm adds names to, the others
+/// (module, labels, types, ...) are copied from the application.
+const FUNCTION_NAMES: u8 = 1;
+const LOCAL_NAMES: u8 = 2;
+const GLOBAL_NAMES: u8 = 7;
+
+/// The field of the `producers` section that lists the tools that processed the module
+const PROCESSED_BY: &str = "processed-by";
+/// The prefix of the names of the DWARF sections
+const DWARF_PREFIX: &str = ".debug_";
+
+/// Names for the functions, globals and locals that the instrumentation injects into a module,
+/// these are written to its `name` section (see `update`).
+#[derive(Default, Debug)]
+pub struct InjectedNames {
+    /// Function ID -> name
+    pub funcs: BTreeMap<u32, String>,
+    /// Global ID -> name
+    pub globals: BTreeMap<u32, String>,
+    /// Function ID -> local ID -> name
+    pub locals: BTreeMap<u32, BTreeMap<u32, String>>,
+}
+impl InjectedNames {
+    pub fn name_local(&mut self, fid: u32, local_id: u32, name: String) {
+        self.locals.entry(fid).or_default().insert(local_id, name);
+    }
+
+    pub fn extend(&mut self, other: InjectedNames) {
+        self.funcs.extend(other.funcs);
+        self.globals.extend(other.globals);
+        for (fid, locals) in other.locals {
+            self.locals.entry(fid).or_default().extend(locals);
+        }
+    }
+}
+
+/// The instrumented module, with its custom sections updated (see `update`).
+pub struct Updated {
+    pub wasm: Vec<u8>,
+    /// The names of the DWARF sections that were dropped
+    pub dropped_dwarf: Vec<String>,
+}
+
+/// Update the custom sections of the `instrumented` version of the `app` module:
+/// - The `name` section is the application's, with the `injected` names added.
+/// - The `producers` section is the application's, with whamm added to the tools that
+///   processed the module.
+/// - The DWARF (`.debug_*`) sections are dropped, their code offsets refer to the original
+///   code and would be wrong for the instrumented one.
+///
+/// The instrumentation only appends functions, globals and locals, so the application's names
+/// still refer to the same items. Every other section is copied as is.
+pub fn update(
+    app: &[u8],
+    instrumented: &[u8],
+    injected: &InjectedNames,
+) -> wasmparser::Result<Updated> {
+    let mut app_names = None;
+    let mut app_producers = None;
+    for payload in Parser::new(0).parse_all(app) {
+        if let Payload::CustomSection(reader) = payload? {
+            match reader.as_known() {
+                KnownCustom::Name(names) => app_names = Some(names),
+                KnownCustom::Producers(producers) => app_producers = Some(producers),
+                _ => {}
+            }
+        }
+    }
+
+    let mut module = Module::new();
+    let mut dropped_dwarf = vec![];
+    for payload in Parser::new(0).parse_all(instrumented) {
+        let payload = payload?;
+        if let Payload::CustomSection(reader) = &payload {
+            // The name and producers sections are replaced by the updated ones, at the end
+            if matches!(reader.name(), "name" | "producers") {
+                continue;
+            }
+            if reader.name().starts_with(DWARF_PREFIX) {
+                dropped_dwarf.push(reader.name().to_string());
+                continue;
+            }
+        }
+        if let Some((id, range)) = payload.as_section() {
+            module.section(&RawSection {
+                id,
+                data: &instrumented[range],
+            });
+        }
+    }
+
+    module.section(&name_section(app_names, injected)?);
+    module.section(&producers_section(app_producers)?);
+    Ok(Updated {
+        wasm: module.finish(),
+        dropped_dwarf,
+    })
+}
+
+/// The `name` section: the application's subsections, with the `injected` names merged into
+/// the function, local and global names. Subsections must be in order of their IDs, and the
+/// names in each one in order of their indices. Subsections that `wasmparser` doesn't know
+/// are dropped.
+fn name_section(
+    app_names: Option<NameSectionReader>,
+    injected: &InjectedNames,
+) -> wasmparser::Result<NameSection> {
+    let mut funcs = BTreeMap::new();
+    let mut locals: BTreeMap<u32, BTreeMap<u32, String>> = BTreeMap::new();
+    let mut globals = BTreeMap::new();
+    let mut others = BTreeMap::new();
+    for name in app_names.into_iter().flatten() {
+        match name? {
+            Name::Function(map) => read_name_map(map, &mut funcs)?,
+            Name::Global(map) => read_name_map(map, &mut globals)?,
+            Name::Local(map) => {
+                for fn_locals in map {
+                    let fn_locals = fn_locals?;
+                    read_name_map(fn_locals.names, locals.entry(fn_locals.index).or_default())?;
+                }
+            }
+            Name::Unknown { .. } => {}
+            name => {
+                others.insert(subsection_id(&name), name);
+            }
+        }
+    }
+
+    funcs.extend(injected.funcs.clone());
+    globals.extend(injected.globals.clone());
+    for (fid, fn_locals) in injected.locals.iter() {
+        locals.entry(*fid).or_default().extend(fn_locals.clone());
+    }
+
+    let mut ids: BTreeSet<u8> = others.keys().copied().collect();
+    for (id, is_empty) in [
+        (FUNCTION_NAMES, funcs.is_empty()),
+        (LOCAL_NAMES, locals.is_empty()),
+        (GLOBAL_NAMES, globals.is_empty()),
+    ] {
+        if !is_empty {
+            ids.insert(id);
+        }
+    }
+
+    let mut names = NameSection::new();
+    for id in ids {
+        match id {
+            FUNCTION_NAMES => names.functions(&name_map(&funcs)),
+            LOCAL_NAMES => {
+                let mut map = IndirectNameMap::new();
+                for (fid, fn_locals) in locals.iter() {
+                    map.append(*fid, &name_map(fn_locals));
+                }
+                names.locals(&map);
+            }
+            GLOBAL_NAMES => names.globals(&name_map(&globals)),
+            _ => {
+                if let Some(name) = others.remove(&id) {
+                    copy_subsection(name, &mut names)?;
+                }
+            }
+        }
+    }
+    Ok(names)
+}
+
+/// The ID of a subsection of the `name` section.
+fn subsection_id(name: &Name) -> u8 {
+    match name {
+        Name::Module { .. } => 0,
+        Name::Function(_) => FUNCTION_NAMES,
+        Name::Local(_) => LOCAL_NAMES,
+        Name::Label(_) => 3,
+        Name::Type(_) => 4,
+        Name::Table(_) => 5,
+        Name::Memory(_) => 6,
+        Name::Global(_) => GLOBAL_NAMES,
+        Name::Element(_) => 8,
+        Name::Data(_) => 9,
+        Name::Field(_) => 10,
+        Name::Tag(_) => 11,
+        Name::Unknown { ty, .. } => *ty,
+    }
+}
+
+/// Copy a subsection of the application's `name` section that whamm doesn't add names to.
+fn copy_subsection(name: Name, names: &mut NameSection) -> wasmparser::Result<()> {
+    match name {
+        Name::Module { name, .. } => names.module(name),
+        Name::Label(map) => names.labels(&read_indirect_name_map(map)?),
+        Name::Type(map) => names.types(&copy_name_map(map)?),
+        Name::Table(map) => names.tables(&copy_name_map(map)?),
+        Name::Memory(map) => names.memories(&copy_name_map(map)?),
+        Name::Element(map) => names.elements(&copy_name_map(map)?),
+        Name::Data(map) => names.data(&copy_name_map(map)?),
+        Name::Field(map) => names.fields(&read_indirect_name_map(map)?),
+        Name::Tag(map) => names.tag(&copy_name_map(map)?),
+        // merged with the injected names in `name_section`
+        Name::Function(_) | Name::Local(_) | Name::Global(_) | Name::Unknown { .. } => {}
+    }
+    Ok(())
+}
+
+fn read_name_map(
+    map: wasmparser::NameMap,
+    names: &mut BTreeMap<u32, String>,
+) -> wasmparser::Result<()> {
+    for naming in map {
+        let naming = naming?;
+        names.insert(naming.index, naming.name.to_string());
+    }
+    Ok(())
+}
+
+fn name_map(names: &BTreeMap<u32, String>) -> NameMap {
+    let mut map = NameMap::new();
+    for (idx, name) in names.iter() {
+        map.append(*idx, name);
+    }
+    map
+}
+
+fn copy_name_map(map: wasmparser::NameMap) -> wasmparser::Result<NameMap> {
+    let mut names = BTreeMap::new();
+    read_name_map(map, &mut names)?;
+    Ok(name_map(&names))
+}
+
+fn read_indirect_name_map(map: wasmparser::IndirectNameMap) -> wasmparser::Result<IndirectNameMap> {
+    let mut indirect = IndirectNameMap::new();
+    for naming in map {
+        let naming = naming?;
+        indirect.append(naming.index, &copy_name_map(naming.names)?);
+    }
+    Ok(indirect)
+}
+
+/// The `producers` section: the application's fields, with whamm (and its version) added to
+/// the `processed-by` field.
+fn producers_section(
+    app_producers: Option<ProducersSectionReader>,
+) -> wasmparser::Result<ProducersSection> {
+    let mut fields: Vec<(&str, Vec<(&str, &str)>)> = vec![];
+    for field in app_producers.into_iter().flatten() {
+        let field = field?;
+        let mut values = vec![];
+        for value in field.values {
+            let value = value?;
+            values.push((value.name, value.version));
+        }
+        fields.push((field.name, values));
+    }
+
+    let whamm = ("whamm", env!("CARGO_PKG_VERSION"));
+    match fields.iter_mut().find(|(name, _)| *name == PROCESSED_BY) {
+        Some((_, values)) => {
+            values.retain(|(name, _)| *name != "whamm");
+            values.push(whamm);
+        }
+        None => fields.push((PROCESSED_BY, vec![whamm])),
+    }
+
+    let mut producers = ProducersSection::new();
+    for (name, values) in fields.iter() {
+        let mut field = ProducersField::new();
+        for (name, version) in values.iter() {
+            field.value(name, version);
+        }
+        producers.field(name, &field);
+    }
+    Ok(producers)
+}
@@ -1,4 +1,8 @@
+pub mod component;
+pub mod custom_sections;
 pub mod rewriting;
+pub mod text;
+pub mod validation;
 
 #[cfg(test)]
 pub mod tests;
@@ -0,0 +1,233 @@
+use crate::emitter::rewriting::debug_line::{SrcLines, SrcLoc};
+use log::warn;
+use std::collections::HashMap;
+use wasmparser::{
+    ExternalKind, FuncValidator, FuncValidatorAllocations, KnownCustom, Name, Parser, Payload,
+    TypeRef, ValType, ValidPayload, Validator, ValidatorResources, WasmFeatures,
+};
+
+/// Information about the application that `orca` does not retain after parsing,
+/// pulled directly from the encoded bytes of the module.
+///
+/// This is what lets us fold location-specific data (e.g. the byte offset of an
+/// instruction) into constants at each probed site and type the values that live
+/// on the operand stack or in the locals at that site.
+#[derive(Default, Debug)]
+pub struct AppInfo {
+    num_imported_fns: u32,
+    /// Function ID -> function name (from the `name` section, falling back to export names)
+    fn_names: HashMap<u32, String>,
+    /// (module, name) of an imported function -> its function ID
+    fn_imports: HashMap<(String, String), u32>,
+    /// The name a function is exported under -> its function ID
+    fn_exports: HashMap<String, u32>,
+    /// Local function index -> the types of the function's locals (params first)
+    locals: Vec<Vec<ValType>>,
+    /// Local function index -> information about each instruction in its body
+    instrs: Vec<Vec<InstrInfo>>,
+    /// The source location of each instruction, from the DWARF `.debug_line` section
+    src_lines: SrcLines,
+}
+
+#[derive(Debug)]
+struct InstrInfo {
+    /// The offset of the instruction relative to the start of the code section
+    pc: u32,
+    /// The type of the value on top of the operand stack before the instruction executes
+    tos_before: Option<ValType>,
+    /// The type of the value on top of the operand stack after the instruction executes
+    tos_after: Option<ValType>,
+}
+/// Function ID -> the (first) name the function is exported under in the passed module.
+pub fn fn_exports(app_bytes: &[u8]) -> HashMap<u32, String> {
+    let mut exports = HashMap::new();
+    for payload in Parser::new(0).parse_all(app_bytes) {
+        if let Ok(Payload::ExportSection(reader)) = payload {
+            for export in reader.into_iter().flatten() {
+                if let ExternalKind::Func = export.kind {
+                    exports
+                        .entry(export.index)
+                        .or_insert(export.name.to_string());
+                }
+            }
+        }
+    }
+    exports
+}
+
+impl AppInfo {
+    pub fn new(app_bytes: &[u8]) -> Self {
+        let mut info = Self::default();
+        if let Err(e) = info.parse(app_bytes) {
+            warn!("Unable to pull location information from the application: {e}");
+        }
+        info
+    }
+
+    fn parse(&mut self, app_bytes: &[u8]) -> wasmparser::Result<()> {
+        let mut export_names: HashMap<u32, String> = HashMap::new();
+        let mut code_start = 0;
+        let mut debug_sections = HashMap::new();
+        // The validator is what tells us the types on the operand stack at each instruction
+        let mut validator = Validator::new_with_features(WasmFeatures::all());
+        for payload in Parser::new(0).parse_all(app_bytes) {
+            let payload = payload?;
+            if let ValidPayload::Func(to_validate, body) = validator.payload(&payload)? {
+                let mut func = to_validate.into_validator(FuncValidatorAllocations::default());
+                let mut reader = body.get_binary_reader();
+                func.read_locals(&mut reader)?;
+                self.locals.push(
+                    (0..func.len_locals())
+                        .filter_map(|idx| func.get_local_type(idx))
+                        .collect(),
+                );
+
+                let mut instrs = vec![];
+                while !reader.eof() {
+                    let offset = reader.original_position();
+                    let op = reader.read_operator()?;
+                    let tos_before = Self::tos(&func);
+                    func.op(offset, &op)?;
+                    instrs.push(InstrInfo {
+                        pc: (offset - code_start) as u32,
+                        tos_before,
+                        tos_after: Self::tos(&func),
+                    });
+                }
+                func.finish(reader.original_position())?;
+                self.instrs.push(instrs);
+            }
+
+            match payload {
+                Payload::ImportSection(reader) => {
+                    for import in reader {
+                        let import = import?;
+                        if let TypeRef::Func(..) = import.ty {
+                            self.fn_imports.insert(
+                                (import.module.to_string(), import.name.to_string()),
+                                self.num_imported_fns,
+                            );
+                            self.num_imported_fns += 1;
+                        }
+                    }
+                }
+                Payload::ExportSection(reader) => {
+                    for export in reader {
+                        let export = export?;
+                        if let ExternalKind::Func = export.kind {
+                            export_names
+                                .entry(export.index)
+                                .or_insert(export.name.to_string());
+                            self.fn_exports
+                                .insert(export.name.to_string(), export.index);
+                        }
+                    }
+                }
+                Payload::CodeSectionStart { range, .. } => {
+                    code_start = range.start;
+                }
+                Payload::CustomSection(reader) => {
+                    if reader.name().starts_with(".debug_") {
+                        debug_sections.insert(reader.name().to_string(), reader.data());
+                    }
+                    if let KnownCustom::Name(names) = reader.as_known() {
+                        for name in names {
+                            if let Name::Function(fn_names) = name? {
+                                for naming in fn_names {
+                                    let naming = naming?;
+                                    self.fn_names.insert(naming.index, naming.name.to_string());
+                                }
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // Only fall back to the export name if the name section didn't name the function
+        for (fid, name) in export_names {
+            self.fn_names.entry(fid).or_insert(name);
+        }
+
+        match SrcLines::new(&debug_sections) {
+            Ok(src_lines) => self.src_lines = src_lines,
+            Err(e) => warn!("Unable to read the application's `.debug_line` section: {e}"),
+        }
+        Ok(())
+    }
+
+    /// The type of the value on top of the operand stack, if the current control frame
+    /// can reach it (values below the frame's height can't be popped inside the frame).
+    fn tos(func: &FuncValidator<ValidatorResources>) -> Option<ValType> {
+        let frame_height = func.get_control_frame(0)?.height;
+        if func.operand_stack_height() as usize <= frame_height {
+            return None;
+        }
+        // an unknown type here means that the code is unreachable
+        func.get_operand_type(0)?
+    }
+
+    fn instr(&self, fid: u32, instr_idx: usize) -> Option<&InstrInfo> {
+        let local_idx = fid.checked_sub(self.num_imported_fns)?;
+        self.instrs.get(local_idx as usize)?.get(instr_idx)
+    }
+
+    pub fn fn_name(&self, fid: u32) -> Option<&String> {
+        self.fn_names.get(&fid)
+    }
+
+    /// The ID of the function imported as `name` from `module`, if the application imports it.
+    pub fn imported_fn(&self, module: &str, name: &str) -> Option<u32> {
+        self.fn_imports
+            .get(&(module.to_string(), name.to_string()))
+            .copied()
+    }
+
+    /// The ID of the function exported as `name`, if the application exports one.
+    pub fn exported_fn(&self, name: &str) -> Option<u32> {
+        self.fn_exports.get(name).copied()
+    }
+
+    /// The number of instructions in the body of the function with ID `fid` (the last one is
+    /// the `end` of the body).
+    pub fn num_instrs(&self, fid: u32) -> Option<usize> {
+        let local_idx = fid.checked_sub(self.num_imported_fns)?;
+        self.instrs
+            .get(local_idx as usize)
+            .map(|instrs| instrs.len())
+    }
+
+    /// The byte offset (relative to the start of the code section) of the
+    /// instruction at `instr_idx` in the function with ID `fid`.
+    pub fn pc(&self, fid: u32, instr_idx: usize) -> Option<u32> {
+        self.instr(fid, instr_idx).map(|instr| instr.pc)
+    }
+
+    /// Where the instruction at `instr_idx` in the function with ID `fid` comes from in the
+    /// application's source code, if it has DWARF line information for it.
+    pub fn src_loc(&self, fid: u32, instr_idx: usize) -> Option<&SrcLoc> {
+        self.src_lines.lookup(self.pc(fid, instr_idx)?)
+    }
+
+    /// The type of the value on top of the operand stack, before (or after) the instruction
+    /// at `instr_idx` executes. This is `None` if there is no value the instruction's control
+    /// frame can see or if the instruction is unreachable.
+    pub fn tos_ty(&self, fid: u32, instr_idx: usize, after: bool) -> Option<ValType> {
+        let instr = self.instr(fid, instr_idx)?;
+        if after {
+            instr.tos_after
+        } else {
+            instr.tos_before
+        }
+    }
+
+    /// The type of local `local_idx` in the function with ID `fid` (params come first).
+    pub fn local_ty(&self, fid: u32, local_idx: u32) -> Option<ValType> {
+        let fn_idx = fid.checked_sub(self.num_imported_fns)?;
+        self.locals
+            .get(fn_idx as usize)?
+            .get(local_idx as usize)
+            .copied()
+    }
+}
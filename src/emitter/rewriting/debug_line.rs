@@ -0,0 +1,103 @@
+use gimli::{AttributeValue, ColumnType, Dwarf, EndianSlice, LittleEndian, SectionId, Unit};
+use std::collections::HashMap;
+
+type Reader<'a> = EndianSlice<'a, LittleEndian>;
+
+/// Where an instruction comes from in the application's source code.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SrcLoc {
+    pub file: String,
+    pub line: u32,
+    /// 0 if the row is for the whole line
+    pub col: u32,
+}
+
+/// The rows of the line programs in the application's `.debug_line` section, used to look up
+/// the source location of an instruction by its offset in the code section (which is what
+/// addresses are in Wasm DWARF).
+#[derive(Debug, Default)]
+pub struct SrcLines {
+    /// Sorted by address, `None` marks the end of a sequence (addresses after it, up to the
+    /// next row, have no source location)
+    rows: Vec<(u64, Option<SrcLoc>)>,
+}
+impl SrcLines {
+    /// Run the line programs of the DWARF in the passed custom `sections` (section name ->
+    /// data), there are no rows if the application has no `.debug_line` section.
+    pub fn new(sections: &HashMap<String, &[u8]>) -> gimli::Result<Self> {
+        let mut lines = Self::default();
+        if !sections.contains_key(SectionId::DebugLine.name()) {
+            return Ok(lines);
+        }
+        let dwarf = Dwarf::load(|id: SectionId| {
+            let data = sections.get(id.name()).copied().unwrap_or(&[]);
+            Ok::<_, gimli::Error>(EndianSlice::new(data, LittleEndian))
+        })?;
+
+        let mut units = dwarf.units();
+        while let Some(header) = units.next()? {
+            let unit = dwarf.unit(header)?;
+            let program = match unit.line_program.clone() {
+                Some(program) => program,
+                None => continue,
+            };
+            let mut rows = program.rows();
+            while let Some((header, row)) = rows.next_row()? {
+                if row.end_sequence() {
+                    lines.rows.push((row.address(), None));
+                    continue;
+                }
+                let file = match row.file(header) {
+                    Some(file) => file,
+                    None => continue,
+                };
+                let dir = match file.directory(header) {
+                    Some(dir) => attr_string(&dwarf, &unit, dir)?,
+                    None => String::new(),
+                };
+                let path = attr_string(&dwarf, &unit, file.path_name())?;
+                lines.rows.push((
+                    row.address(),
+                    Some(SrcLoc {
+                        file: join_path(&dir, &path),
+                        line: row.line().map_or(0, |line| line.get() as u32),
+                        col: match row.column() {
+                            ColumnType::LeftEdge => 0,
+                            ColumnType::Column(col) => col.get() as u32,
+                        },
+                    }),
+                ));
+            }
+        }
+        // A sequence can start where another one ends, so the ends go first. Otherwise, rows at
+        // the same address keep their order and the last one wins in `lookup`.
+        lines.rows.sort_by_key(|(addr, loc)| (*addr, loc.is_some()));
+        Ok(lines)
+    }
+
+    /// The source location of the instruction at `pc` (relative to the start of the code
+    /// section): the last row at or before it, unless a sequence ended in between.
+    pub fn lookup(&self, pc: u32) -> Option<&SrcLoc> {
+        let idx = self.rows.partition_point(|(addr, _)| *addr <= pc as u64);
+        self.rows[..idx].last()?.1.as_ref()
+    }
+}
+
+fn attr_string(
+    dwarf: &Dwarf<Reader>,
+    unit: &Unit<Reader>,
+    attr: AttributeValue<Reader>,
+) -> gimli::Result<String> {
+    Ok(dwarf
+        .attr_string(unit, attr)?
+        .to_string_lossy()
+        .into_owned())
+}
+
+fn join_path(dir: &str, path: &str) -> String {
+    if dir.is_empty() || path.starts_with('/') {
+        path.to_string()
+    } else {
+        format!("{}/{}", dir.trim_end_matches('/'), path)
+    }
+}
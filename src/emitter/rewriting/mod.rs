@@ -1,9 +1,11 @@
+pub mod app_info;
+pub mod debug_line;
 pub mod module_emitter;
 pub mod rules;
 pub mod visiting_emitter;
 
 use crate::common::error::{ErrorGen, WhammError};
-use crate::parser::types::{BinOp, Block, DataType, Expr, Statement, UnOp, Value};
+use crate::parser::types::{Aggregation, BinOp, Block, DataType, Expr, Statement, UnOp, Value};
 use crate::verifier::types::{Record, SymbolTable, VarAddr};
 
 use crate::emitter::rewriting::module_emitter::MemoryTracker;
@@ -65,6 +67,21 @@ fn emit_stmt<'a, T: Opcode<'a> + ModuleBuilder>(
                 emit_if_else(cond, conseq, alt, injector, table, mem_tracker, err_msg)
             }
         }
+        Statement::Aggregate {
+            name, keys, args, ..
+        } => {
+            // the keys and value are passed to the aggregation's update function
+            let mut call = Expr::Call {
+                fn_target: Box::new(Expr::VarId {
+                    is_comp_provided: true,
+                    name: Aggregation::symbol_name(name),
+                    loc: None,
+                }),
+                args: Some(keys.iter().chain(args.iter()).cloned().collect()),
+                loc: None,
+            };
+            emit_expr(&mut call, injector, table, mem_tracker, err_msg)
+        }
     }
 }
 
@@ -259,12 +276,39 @@ pub fn whamm_type_to_wasm_global(ty: &DataType) -> Global {
             },
             init_expr: InitExpr::Value(OrcaValue::I32(0)),
         },
+        OrcaType::I64 => Global {
+            ty: GlobalType {
+                content_type: ValType::I64,
+                mutable: true,
+                shared: false,
+            },
+            init_expr: InitExpr::Value(OrcaValue::I64(0)),
+        },
+        OrcaType::F32 => Global {
+            ty: GlobalType {
+                content_type: ValType::F32,
+                mutable: true,
+                shared: false,
+            },
+            init_expr: InitExpr::Value(OrcaValue::F32(0.0)),
+        },
+        OrcaType::F64 => Global {
+            ty: GlobalType {
+                content_type: ValType::F64,
+                mutable: true,
+                shared: false,
+            },
+            init_expr: InitExpr::Value(OrcaValue::F64(0.0)),
+        },
         _ => unimplemented!(),
     }
 }
 pub fn whamm_type_to_wasm_type(ty: &DataType) -> OrcaType {
     match ty {
         DataType::I32 | DataType::U32 | DataType::Boolean => OrcaType::I32,
+        DataType::I64 => OrcaType::I64,
+        DataType::F32 => OrcaType::F32,
+        DataType::F64 => OrcaType::F64,
         // the ID used to track this var in the lib
         DataType::Map { .. } => OrcaType::I32,
         DataType::Null => unimplemented!(),
@@ -274,6 +318,28 @@ pub fn whamm_type_to_wasm_type(ty: &DataType) -> OrcaType {
     }
 }
 
+/// The whamm type for a value of the passed `orca` type, `None` if whamm can't represent it.
+pub fn orca_type_to_whamm_type(ty: &OrcaType) -> Option<DataType> {
+    match ty {
+        OrcaType::I32 => Some(DataType::I32),
+        OrcaType::I64 => Some(DataType::I64),
+        OrcaType::F32 => Some(DataType::F32),
+        OrcaType::F64 => Some(DataType::F64),
+        _ => None,
+    }
+}
+
+/// The whamm type for a value of the passed Wasm type, `None` if whamm can't represent it.
+pub fn wasm_type_to_whamm_type(ty: &ValType) -> Option<DataType> {
+    match ty {
+        ValType::I32 => Some(DataType::I32),
+        ValType::I64 => Some(DataType::I64),
+        ValType::F32 => Some(DataType::F32),
+        ValType::F64 => Some(DataType::F64),
+        ValType::V128 | ValType::Ref(..) => None,
+    }
+}
+
 pub fn block_type_to_wasm(block: &Block) -> BlockType {
     match &block.return_ty {
         None => BlockType::Empty,
@@ -444,9 +510,10 @@ fn emit_expr<'a, T: Opcode<'a> + ModuleBuilder>(
             is_success &= emit_unop(op, injector);
         }
         Expr::BinOp { lhs, op, rhs, .. } => {
+            let operand_ty = expr_wasm_type(lhs, table);
             is_success &= emit_expr(lhs, injector, table, mem_tracker, err_msg)?;
             is_success &= emit_expr(rhs, injector, table, mem_tracker, err_msg)?;
-            is_success &= emit_binop(op, injector);
+            is_success &= emit_binop(op, &operand_ty, injector);
         }
         Expr::Ternary {
             cond, conseq, alt, ..
@@ -595,64 +662,160 @@ fn emit_expr<'a, T: Opcode<'a> + ModuleBuilder>(
     Ok(is_success)
 }
 
-fn emit_binop<'a, T: Opcode<'a>>(op: &BinOp, injector: &mut T) -> bool {
+fn emit_binop<'a, T: Opcode<'a>>(op: &BinOp, operand_ty: &OrcaType, injector: &mut T) -> bool {
+    match operand_ty {
+        OrcaType::I64 => emit_i64_binop(op, injector),
+        OrcaType::F32 => emit_f32_binop(op, injector),
+        OrcaType::F64 => emit_f64_binop(op, injector),
+        // booleans are represented as i32's
+        _ => emit_i32_binop(op, injector),
+    }
+}
+
+fn emit_i32_binop<'a, T: Opcode<'a>>(op: &BinOp, injector: &mut T) -> bool {
     match op {
         BinOp::And => {
-            // we only support i32's at the moment
             injector.i32_and();
         }
         BinOp::Or => {
-            // we only support i32's at the moment
             injector.i32_or();
         }
         BinOp::EQ => {
-            // we only support i32's at the moment
             injector.i32_eq();
         }
         BinOp::NE => {
-            // we only support i32's at the moment
             injector.i32_ne();
         }
         BinOp::GE => {
-            // we only support i32's at the moment (assumes signed)
+            // assumes signed
             injector.i32_gte_signed();
         }
         BinOp::GT => {
-            // we only support i32's at the moment (assumes signed)
+            // assumes signed
             injector.i32_gt_signed();
         }
         BinOp::LE => {
-            // we only support i32's at the moment (assumes signed)
+            // assumes signed
             injector.i32_lte_signed();
         }
         BinOp::LT => {
-            // we only support i32's at the moment (assumes signed)
+            // assumes signed
             injector.i32_lt_signed();
         }
         BinOp::Add => {
-            // we only support i32's at the moment
             injector.i32_add();
         }
         BinOp::Subtract => {
-            // we only support i32's at the moment
             injector.i32_sub();
         }
         BinOp::Multiply => {
-            // we only support i32's at the moment (assumes signed)
+            // assumes signed
             injector.i32_mul();
         }
         BinOp::Divide => {
-            // we only support i32's at the moment (assumes signed)
+            // assumes signed
             injector.i32_div_signed();
         }
         BinOp::Modulo => {
-            // we only support i32's at the moment (assumes signed)
+            // assumes signed
             injector.i32_rem_signed();
         }
     }
     true
 }
 
+fn emit_i64_binop<'a, T: Opcode<'a>>(op: &BinOp, injector: &mut T) -> bool {
+    match op {
+        BinOp::EQ => injector.i64_eq(),
+        BinOp::NE => injector.i64_ne(),
+        BinOp::GE => injector.i64_gte_signed(),
+        BinOp::GT => injector.i64_gt_signed(),
+        BinOp::LE => injector.i64_lte_signed(),
+        BinOp::LT => injector.i64_lt_signed(),
+        BinOp::Add => injector.i64_add(),
+        BinOp::Subtract => injector.i64_sub(),
+        BinOp::Multiply => injector.i64_mul(),
+        BinOp::Divide => injector.i64_div_signed(),
+        BinOp::Modulo => injector.i64_rem_signed(),
+        // logical operators only apply to booleans (the verifier makes sure of this)
+        BinOp::And | BinOp::Or => return false,
+    };
+    true
+}
+
+fn emit_f32_binop<'a, T: Opcode<'a>>(op: &BinOp, injector: &mut T) -> bool {
+    match op {
+        BinOp::EQ => injector.f32_eq(),
+        BinOp::NE => injector.f32_ne(),
+        BinOp::GE => injector.f32_ge(),
+        BinOp::GT => injector.f32_gt(),
+        BinOp::LE => injector.f32_le(),
+        BinOp::LT => injector.f32_lt(),
+        BinOp::Add => injector.f32_add(),
+        BinOp::Subtract => injector.f32_sub(),
+        BinOp::Multiply => injector.f32_mul(),
+        BinOp::Divide => injector.f32_div(),
+        // there is no remainder instruction for floats (the verifier makes sure of this)
+        BinOp::Modulo | BinOp::And | BinOp::Or => return false,
+    };
+    true
+}
+
+fn emit_f64_binop<'a, T: Opcode<'a>>(op: &BinOp, injector: &mut T) -> bool {
+    match op {
+        BinOp::EQ => injector.f64_eq(),
+        BinOp::NE => injector.f64_ne(),
+        BinOp::GE => injector.f64_ge(),
+        BinOp::GT => injector.f64_gt(),
+        BinOp::LE => injector.f64_le(),
+        BinOp::LT => injector.f64_lt(),
+        BinOp::Add => injector.f64_add(),
+        BinOp::Subtract => injector.f64_sub(),
+        BinOp::Multiply => injector.f64_mul(),
+        BinOp::Divide => injector.f64_div(),
+        // there is no remainder instruction for floats (the verifier makes sure of this)
+        BinOp::Modulo | BinOp::And | BinOp::Or => return false,
+    };
+    true
+}
+
+/// The Wasm type of the value that the passed (type-checked) expression leaves on the stack.
+/// Used to pick the instruction to emit for an operator.
+fn expr_wasm_type(expr: &Expr, table: &SymbolTable) -> OrcaType {
+    let ty = match expr {
+        Expr::Primitive { val, .. } => match val {
+            Value::Integer { ty, .. } | Value::Boolean { ty, .. } => ty.clone(),
+            _ => DataType::I32,
+        },
+        Expr::VarId { name, .. } => match table.lookup(name).and_then(|id| table.get_record(id)) {
+            Some(Record::Var { ty, .. }) => ty.clone(),
+            _ => DataType::I32,
+        },
+        Expr::Call { fn_target, .. } => {
+            let fn_rec = match fn_target.as_ref() {
+                Expr::VarId { name, .. } => table.lookup(name).and_then(|id| table.get_record(id)),
+                _ => None,
+            };
+            match fn_rec {
+                Some(Record::Fn { ret_ty, .. }) => ret_ty.clone(),
+                _ => DataType::I32,
+            }
+        }
+        Expr::BinOp { lhs, op, .. } => match op {
+            BinOp::Add | BinOp::Subtract | BinOp::Multiply | BinOp::Divide | BinOp::Modulo => {
+                return expr_wasm_type(lhs, table)
+            }
+            _ => DataType::Boolean,
+        },
+        Expr::Ternary { conseq, .. } => return expr_wasm_type(conseq, table),
+        Expr::UnOp { .. } => DataType::Boolean,
+    };
+    match ty {
+        DataType::I64 | DataType::F32 | DataType::F64 => whamm_type_to_wasm_type(&ty),
+        _ => OrcaType::I32,
+    }
+}
+
 fn emit_unop<'a, T: Opcode<'a>>(op: &UnOp, injector: &mut T) -> bool {
     match op {
         UnOp::Not => {
@@ -672,8 +835,14 @@ fn emit_value<'a, T: Opcode<'a> + ModuleBuilder>(
 ) -> Result<bool, Box<WhammError>> {
     let mut is_success = true;
     match val {
-        Value::Integer { val, .. } => {
-            injector.i32_const(*val);
+        Value::Integer { ty, val } => {
+            // integer literals can be coerced to the type they're used with by the verifier
+            match ty {
+                DataType::I64 => injector.i64_const(*val as i64),
+                DataType::F32 => injector.f32_const(*val as f32),
+                DataType::F64 => injector.f64_const(*val as f64),
+                _ => injector.i32_const(*val),
+            };
             is_success &= true;
         }
         Value::Str { val, .. } => {
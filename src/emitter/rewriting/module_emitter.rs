@@ -1,11 +1,14 @@
 use crate::common::error::{ErrorGen, WhammError};
-use crate::parser::types::{Block, DataType, Definition, Expr, Fn, Statement, Value};
+use crate::emitter::custom_sections::InjectedNames;
+use crate::parser::types::{
+    AggFn, Aggregation, Block, DataType, Definition, Expr, Fn, Statement, Value,
+};
 use crate::verifier::types::{Record, SymbolTable, VarAddr};
 use orca::{DataSegment, DataSegmentKind, InitExpr};
 use std::collections::HashMap;
 
 use orca::ir::types::{BlockType, DataType as OrcaType, Value as OrcaValue};
-use wasmparser::GlobalType;
+use wasmparser::{GlobalType, MemArg};
 
 use crate::emitter::rewriting::{
     emit_body, emit_expr, emit_stmt, whamm_type_to_wasm_global, Emitter,
@@ -17,11 +20,26 @@ use orca::opcode::Opcode;
 const UNEXPECTED_ERR_MSG: &str =
     "ModuleEmitter: Looks like you've found a bug...please report this behavior!";
 
+const WASM_PAGE_SIZE: u64 = 65_536;
+/// The max number of entries (distinct keys) in an aggregation's table,
+/// updates for new keys are dropped (and counted) once it is full.
+const AGG_TABLE_CAPACITY: u32 = 1024;
+/// Each aggregation table starts with a header: [len: i32, dropped: i32]
+const AGG_HEADER_SIZE: u32 = 8;
+/// The module of the WASI imports that the report of the aggregations relies on
+pub const WASI_MODULE: &str = "wasi_snapshot_preview1";
+/// The aggregation report is written to stderr, to keep it apart from the application's output
+const STDERR_FD: i32 = 2;
+/// The scratch memory of the aggregation report: [iovec: (ptr, len), nwritten, digits: 12 bytes]
+const AGG_REPORT_SCRATCH_SIZE: u32 = 24;
+
 pub struct MemoryTracker {
     pub mem_id: u32,
     pub curr_mem_offset: usize,
     pub required_initial_mem_size: u64,
     pub emitted_strings: HashMap<String, StringAddr>,
+    /// The base memory offset of each aggregation's table
+    pub emitted_aggregations: HashMap<String, usize>,
 }
 
 pub struct StringAddr {
@@ -30,6 +48,66 @@ pub struct StringAddr {
     pub len: usize,
 }
 
+/// The linear memory layout of an aggregation's table.
+/// Entries are stored densely after the header, an entry is its
+/// keys followed by its values (all i32).
+struct AggLayout {
+    base: u32,
+    num_keys: u32,
+    num_vals: u32,
+}
+/// An emitted aggregation table, to be written out by the report (see `emit_agg_report`).
+struct AggTable {
+    /// How the aggregation is named in the report, e.g. `@calls` or `script1:@calls`
+    label: String,
+    layout: AggLayout,
+    func: AggFn,
+}
+
+impl AggLayout {
+    fn new(agg: &Aggregation, base: u32) -> Self {
+        let num_vals = match agg.func {
+            AggFn::Count | AggFn::Sum | AggFn::Min | AggFn::Max => 1,
+            // [sum, count]
+            AggFn::Avg => 2,
+            // [total count, buckets...]
+            AggFn::Quantize | AggFn::LQuantize { .. } => 1 + agg.func.num_buckets() as u32,
+        };
+        Self {
+            base,
+            num_keys: agg.num_keys as u32,
+            num_vals,
+        }
+    }
+    fn entries(&self) -> u32 {
+        self.base + AGG_HEADER_SIZE
+    }
+    fn entry_size(&self) -> u32 {
+        4 * (self.num_keys + self.num_vals)
+    }
+    fn key_offset(&self, key: u32) -> u32 {
+        4 * key
+    }
+    fn val_offset(&self, val: u32) -> u32 {
+        4 * (self.num_keys + val)
+    }
+    /// The size of the table in bytes, `None` if it doesn't fit in the 32-bit address space.
+    fn size(&self) -> Option<u32> {
+        self.num_keys
+            .checked_add(self.num_vals)
+            .and_then(|num_fields| num_fields.checked_mul(4 * AGG_TABLE_CAPACITY))
+            .and_then(|entries_size| entries_size.checked_add(AGG_HEADER_SIZE))
+    }
+    /// Push the address of the entry at the index stored in `idx`
+    fn emit_entry_addr(&self, idx: u32, func: &mut FunctionBuilder) {
+        func.local_get(idx)
+            .i32_const(self.entry_size() as i32)
+            .i32_mul()
+            .i32_const(self.entries() as i32)
+            .i32_add();
+    }
+}
+
 pub struct ModuleEmitter<'a, 'b, 'c, 'd> {
     pub app_wasm: &'a mut Module<'b>,
     pub emitting_func: Option<FunctionBuilder<'b>>,
@@ -37,6 +115,13 @@ pub struct ModuleEmitter<'a, 'b, 'c, 'd> {
 
     mem_tracker: &'d mut MemoryTracker,
     fn_providing_contexts: Vec<String>,
+    /// Prefixes the names exported for the script being emitted, so that scripts that are
+    /// instrumented together don't collide (see `export_name`)
+    pub namespace: Option<String>,
+    /// The names of the functions and globals emitted so far, for the `name` section
+    pub names: InjectedNames,
+    /// The aggregation tables emitted so far, of all scripts
+    agg_tables: Vec<AggTable>,
 }
 
 impl<'a, 'b, 'c, 'd> ModuleEmitter<'a, 'b, 'c, 'd> {
@@ -52,6 +137,17 @@ impl<'a, 'b, 'c, 'd> ModuleEmitter<'a, 'b, 'c, 'd> {
             mem_tracker,
             table,
             fn_providing_contexts: vec!["whamm".to_string()],
+            namespace: None,
+            names: InjectedNames::default(),
+            agg_tables: vec![],
+        }
+    }
+
+    /// The name to export `name` as, prefixed with the namespace of the script if there is one.
+    fn export_name(&self, name: String) -> String {
+        match &self.namespace {
+            Some(namespace) => format!("{namespace}_{name}"),
+            None => name,
         }
     }
 
@@ -172,8 +268,9 @@ impl<'a, 'b, 'c, 'd> ModuleEmitter<'a, 'b, 'c, 'd> {
             .return_stmt();
 
         let strcmp_id = strcmp.finish(self.app_wasm);
-        self.app_wasm
-            .set_fn_name(strcmp_id - self.app_wasm.num_import_func(), "strcmp");
+        self.names
+            .funcs
+            .insert(strcmp_id, "whamm_strcmp".to_string());
 
         let rec_id = match self.table.lookup(&f.name.name) {
             Some(rec_id) => *rec_id,
@@ -300,8 +397,14 @@ impl<'a, 'b, 'c, 'd> ModuleEmitter<'a, 'b, 'c, 'd> {
     }
 
     pub(crate) fn memory_grow(&mut self) {
-        // If we've emitted any strings, bump the app's memory up to account for that
-        if !self.mem_tracker.emitted_strings.is_empty() {
+        // If we've emitted any strings or aggregation tables, bump the app's memory up to account for that
+        if !self.mem_tracker.emitted_strings.is_empty()
+            || !self.mem_tracker.emitted_aggregations.is_empty()
+        {
+            let required_pages = (self.mem_tracker.curr_mem_offset as u64).div_ceil(WASM_PAGE_SIZE);
+            if self.mem_tracker.required_initial_mem_size < required_pages {
+                self.mem_tracker.required_initial_mem_size = required_pages;
+            }
             if let Some(mem) = self.app_wasm.memories.get_mut(0) {
                 if mem.initial < self.mem_tracker.required_initial_mem_size {
                     mem.initial = self.mem_tracker.required_initial_mem_size;
@@ -310,6 +413,598 @@ impl<'a, 'b, 'c, 'd> ModuleEmitter<'a, 'b, 'c, 'd> {
         }
     }
 
+    fn agg_mem_arg(&self, offset: u32) -> MemArg {
+        MemArg {
+            align: 2,
+            max_align: 2,
+            offset: offset as u64,
+            memory: self.mem_tracker.mem_id,
+        }
+    }
+
+    /// Emit the table backing the aggregation (in linear memory), the function that updates it
+    /// and the exported functions to read it out:
+    /// - `get_agg_<name>_len() -> i32`: the number of entries
+    /// - `get_agg_<name>_dropped() -> i32`: the number of updates dropped since the table was full
+    /// - `get_agg_<name>_key<K>(entry) -> i32`: the K'th key of an entry
+    /// - `get_agg_<name>(entry) -> i32`: the aggregated value of an entry
+    ///   (the total count for histograms)
+    /// - `get_agg_<name>_bucket(entry, bucket) -> i32`: the count in a histogram bucket
+    pub(crate) fn emit_aggregation(&mut self, agg: &Aggregation) -> Result<bool, Box<WhammError>> {
+        // reserve the table, it relies on the memory being zeroed at instantiation
+        let layout = AggLayout::new(agg, self.mem_tracker.curr_mem_offset as u32);
+        let end = match layout.size().and_then(|size| layout.base.checked_add(size)) {
+            Some(end) => end,
+            None => {
+                return Err(Box::new(ErrorGen::get_instrumentation_error_from_loc(
+                    false,
+                    format!(
+                        "The table of aggregation `{}` doesn't fit in linear memory, \
+                        it needs fewer keys or histogram buckets",
+                        agg.name
+                    ),
+                    &agg.loc,
+                )));
+            }
+        };
+        self.mem_tracker.curr_mem_offset = end as usize;
+        self.mem_tracker
+            .emitted_aggregations
+            .insert(agg.name.clone(), layout.base as usize);
+
+        let update_id = self.emit_agg_update_fn(agg, &layout);
+        let update_name = self.export_name(format!("whamm_agg_{}", agg.name));
+        self.names.funcs.insert(update_id, update_name);
+
+        let symbol = Aggregation::symbol_name(&agg.name);
+        match self
+            .table
+            .lookup(&symbol)
+            .copied()
+            .and_then(|rec_id| self.table.get_record_mut(&rec_id))
+        {
+            Some(Record::Fn { addr, .. }) => *addr = Some(update_id),
+            rec => {
+                return Err(Box::new(ErrorGen::get_unexpected_error(
+                    true,
+                    Some(format!(
+                        "{UNEXPECTED_ERR_MSG} \
+                Expected a Record::Fn for aggregation `{symbol}`, found: {:?}",
+                        rec
+                    )),
+                    None,
+                )));
+            }
+        }
+
+        self.emit_agg_readers(agg, &layout);
+        let label = match &self.namespace {
+            Some(namespace) => format!("{namespace}:@{}", agg.name),
+            None => format!("@{}", agg.name),
+        };
+        self.agg_tables.push(AggTable {
+            label,
+            layout,
+            func: agg.func.clone(),
+        });
+        Ok(true)
+    }
+
+    pub(crate) fn has_aggregations(&self) -> bool {
+        !self.agg_tables.is_empty()
+    }
+
+    /// Emit `whamm_agg_report()`, which writes out every aggregation table to stderr through
+    /// the application's `fd_write` import (`fd_write` is its function ID). Each entry is a line:
+    /// `@<name>[<keys>]: <value>`, followed by ` | <bucket counts>` for histograms, and a full
+    /// table adds a line with the number of updates it dropped.
+    ///
+    /// Returns the ID of the function, which is exported for the host to call as well.
+    pub(crate) fn emit_agg_report(&mut self, fd_write: u32) -> u32 {
+        let scratch = self.mem_tracker.curr_mem_offset as u32;
+        self.mem_tracker.curr_mem_offset += AGG_REPORT_SCRATCH_SIZE as usize;
+        let write_str = self.emit_report_write_str(fd_write, scratch);
+        let write_i32 = self.emit_report_write_i32(write_str, scratch);
+
+        let mut report = FunctionBuilder::new(&[], &[]);
+        let entry = report.add_local(OrcaType::I32);
+        let bucket = report.add_local(OrcaType::I32);
+        for table in std::mem::take(&mut self.agg_tables) {
+            let layout = &table.layout;
+            let base = layout.base as i32;
+            let num_buckets = table.func.num_buckets() as i32;
+
+            // one line per entry
+            report
+                .i32_const(0)
+                .local_set(entry)
+                .block(BlockType::Empty)
+                .loop_stmt(BlockType::Empty)
+                .local_get(entry)
+                .i32_const(base)
+                .i32_load(self.agg_mem_arg(0))
+                .i32_gte_signed()
+                .br_if(1);
+            if layout.num_keys == 0 {
+                self.emit_report_str(&mut report, write_str, &format!("{}: ", table.label));
+            } else {
+                self.emit_report_str(&mut report, write_str, &format!("{}[", table.label));
+                for key in 0..layout.num_keys {
+                    if key > 0 {
+                        self.emit_report_str(&mut report, write_str, ", ");
+                    }
+                    layout.emit_entry_addr(entry, &mut report);
+                    report
+                        .i32_load(self.agg_mem_arg(layout.key_offset(key)))
+                        .call(write_i32);
+                }
+                self.emit_report_str(&mut report, write_str, "]: ");
+            }
+            layout.emit_entry_addr(entry, &mut report);
+            report.i32_load(self.agg_mem_arg(layout.val_offset(0)));
+            if table.func == AggFn::Avg {
+                // sum / count
+                layout.emit_entry_addr(entry, &mut report);
+                report
+                    .i32_load(self.agg_mem_arg(layout.val_offset(1)))
+                    .i32_div_signed();
+            }
+            report.call(write_i32);
+            if num_buckets > 0 {
+                self.emit_report_str(&mut report, write_str, " |");
+                report
+                    .i32_const(0)
+                    .local_set(bucket)
+                    .block(BlockType::Empty)
+                    .loop_stmt(BlockType::Empty)
+                    .local_get(bucket)
+                    .i32_const(num_buckets)
+                    .i32_gte_signed()
+                    .br_if(1);
+                self.emit_report_str(&mut report, write_str, " ");
+                layout.emit_entry_addr(entry, &mut report);
+                report
+                    .local_get(bucket)
+                    .i32_const(4)
+                    .i32_mul()
+                    .i32_add()
+                    .i32_load(self.agg_mem_arg(layout.val_offset(1)))
+                    .call(write_i32)
+                    .local_get(bucket)
+                    .i32_const(1)
+                    .i32_add()
+                    .local_set(bucket)
+                    .br(0)
+                    .end()
+                    .end();
+            }
+            self.emit_report_str(&mut report, write_str, "\n");
+            report
+                .local_get(entry)
+                .i32_const(1)
+                .i32_add()
+                .local_set(entry)
+                .br(0)
+                .end()
+                .end();
+
+            // the updates that didn't fit
+            report
+                .i32_const(base)
+                .i32_load(self.agg_mem_arg(4))
+                .if_stmt(BlockType::Empty);
+            self.emit_report_str(&mut report, write_str, &format!("{}: ", table.label));
+            report
+                .i32_const(base)
+                .i32_load(self.agg_mem_arg(4))
+                .call(write_i32);
+            self.emit_report_str(
+                &mut report,
+                write_str,
+                " update(s) dropped, the table was full\n",
+            );
+            report.end();
+        }
+
+        let report_id = report.finish(self.app_wasm);
+        let name = "whamm_agg_report".to_string();
+        self.names.funcs.insert(report_id, name.clone());
+        self.app_wasm.add_export_func(name.leak(), report_id);
+
+        // account for the report's strings and scratch memory
+        self.memory_grow();
+        report_id
+    }
+
+    /// Emits `whamm_report_str(ptr, len)`, which writes the string at `ptr` to stderr.
+    fn emit_report_write_str(&mut self, fd_write: u32, scratch: u32) -> u32 {
+        let iovec = scratch as i32;
+        let nwritten = iovec + 8;
+
+        let mut write_str = FunctionBuilder::new(&[OrcaType::I32, OrcaType::I32], &[]);
+        // the report is best effort, the errno is dropped
+        write_str
+            .i32_const(iovec)
+            .local_get(0)
+            .i32_store(self.agg_mem_arg(0))
+            .i32_const(iovec)
+            .local_get(1)
+            .i32_store(self.agg_mem_arg(4))
+            .i32_const(STDERR_FD)
+            .i32_const(iovec)
+            .i32_const(1)
+            .i32_const(nwritten)
+            .call(fd_write)
+            .drop();
+
+        let write_str_id = write_str.finish(self.app_wasm);
+        self.names
+            .funcs
+            .insert(write_str_id, "whamm_report_str".to_string());
+        write_str_id
+    }
+
+    /// Emits `whamm_report_i32(value)`, which writes the value in decimal to stderr. The digits
+    /// are put together at the end of the scratch memory, from the last one.
+    fn emit_report_write_i32(&mut self, write_str: u32, scratch: u32) -> u32 {
+        let digits_end = (scratch + AGG_REPORT_SCRATCH_SIZE) as i32;
+        let byte = MemArg {
+            align: 0,
+            max_align: 0,
+            offset: 0,
+            memory: self.mem_tracker.mem_id,
+        };
+
+        let mut write_i32 = FunctionBuilder::new(&[OrcaType::I32], &[]);
+        // params
+        let val = 0u32;
+        // locals
+        let pos = write_i32.add_local(OrcaType::I32);
+        let is_neg = write_i32.add_local(OrcaType::I32);
+
+        write_i32
+            .i32_const(digits_end)
+            .local_set(pos)
+            .local_get(val)
+            .i32_const(0)
+            .i32_lt_signed()
+            .local_set(is_neg)
+            // count down from a value <= 0, so that i32::MIN can't overflow
+            .local_get(is_neg)
+            .i32_eqz()
+            .if_stmt(BlockType::Empty)
+            .i32_const(0)
+            .local_get(val)
+            .i32_sub()
+            .local_set(val)
+            .end()
+            .loop_stmt(BlockType::Empty)
+            .local_get(pos)
+            .i32_const(1)
+            .i32_sub()
+            .local_set(pos)
+            // digit = '0' - (val % 10)
+            .local_get(pos)
+            .i32_const('0' as i32)
+            .i32_const(0)
+            .local_get(val)
+            .i32_const(10)
+            .i32_rem_signed()
+            .i32_sub()
+            .i32_add()
+            .i32_store8(byte)
+            .local_get(val)
+            .i32_const(10)
+            .i32_div_signed()
+            .local_set(val)
+            .local_get(val)
+            .br_if(0)
+            .end()
+            .local_get(is_neg)
+            .if_stmt(BlockType::Empty)
+            .local_get(pos)
+            .i32_const(1)
+            .i32_sub()
+            .local_set(pos)
+            .local_get(pos)
+            .i32_const('-' as i32)
+            .i32_store8(byte)
+            .end()
+            .local_get(pos)
+            .i32_const(digits_end)
+            .local_get(pos)
+            .i32_sub()
+            .call(write_str);
+
+        let write_i32_id = write_i32.finish(self.app_wasm);
+        self.names
+            .funcs
+            .insert(write_i32_id, "whamm_report_i32".to_string());
+        write_i32_id
+    }
+
+    /// Emit the string into memory (once) and write it out with `write_str`.
+    fn emit_report_str(&mut self, func: &mut FunctionBuilder, write_str: u32, s: &str) {
+        let mut val = Value::Str {
+            ty: DataType::Str,
+            val: s.to_string(),
+        };
+        self.emit_string(&mut val).expect(UNEXPECTED_ERR_MSG);
+        let addr = &self.mem_tracker.emitted_strings[s];
+        func.i32_const(addr.mem_offset as i32)
+            .i32_const(addr.len as i32)
+            .call(write_str);
+    }
+
+    /// Emits `whamm_agg_<name>(keys..., value?)`, which finds (or inserts) the entry
+    /// for the keys and then folds the value into it.
+    fn emit_agg_update_fn(&mut self, agg: &Aggregation, layout: &AggLayout) -> u32 {
+        let num_params = layout.num_keys as usize + agg.func.num_args();
+        let params = vec![OrcaType::I32; num_params];
+        let mut update = FunctionBuilder::new(&params, &[]);
+
+        // params
+        let val = layout.num_keys;
+        // locals
+        let i = update.add_local(OrcaType::I32);
+        let len = update.add_local(OrcaType::I32);
+        let entry = update.add_local(OrcaType::I32);
+        let bucket = update.add_local(OrcaType::I32);
+
+        let base = layout.base as i32;
+
+        #[rustfmt::skip]
+        update
+            .i32_const(base)
+            .i32_load(self.agg_mem_arg(0))
+            .local_set(len)
+            .block(BlockType::Empty) // label = @1
+            .block(BlockType::Empty) // label = @2
+            // 1. scan the used entries for one with matching keys
+            .loop_stmt(BlockType::Empty) // label = @3
+            .local_get(i)
+            .local_get(len)
+            .i32_gte_unsigned()
+            .br_if(1); // (;@2;) no match, insert a new entry
+        layout.emit_entry_addr(i, &mut update);
+        update.local_set(entry);
+        if layout.num_keys == 0 {
+            update.i32_const(1);
+        }
+        for key in 0..layout.num_keys {
+            update
+                .local_get(entry)
+                .i32_load(self.agg_mem_arg(layout.key_offset(key)))
+                .local_get(key)
+                .i32_eq();
+            if key > 0 {
+                update.i32_and();
+            }
+        }
+        #[rustfmt::skip]
+        update
+            .br_if(2) // (;@1;) found it
+            .local_get(i)
+            .i32_const(1)
+            .i32_add()
+            .local_set(i)
+            .br(0) // (;@3;)
+            .end()
+            .end()
+
+            // 2. insert a new entry, if the table is full count the dropped update and return
+            .block(BlockType::Empty)
+            .local_get(len)
+            .i32_const(AGG_TABLE_CAPACITY as i32)
+            .i32_lt_unsigned()
+            .br_if(0)
+            .i32_const(base)
+            .i32_const(base)
+            .i32_load(self.agg_mem_arg(4))
+            .i32_const(1)
+            .i32_add()
+            .i32_store(self.agg_mem_arg(4))
+            .return_stmt()
+            .end();
+        layout.emit_entry_addr(len, &mut update);
+        update.local_set(entry);
+        for key in 0..layout.num_keys {
+            update
+                .local_get(entry)
+                .local_get(key)
+                .i32_store(self.agg_mem_arg(layout.key_offset(key)));
+        }
+        if matches!(agg.func, AggFn::Min | AggFn::Max) {
+            // start from the first value
+            update
+                .local_get(entry)
+                .local_get(val)
+                .i32_store(self.agg_mem_arg(layout.val_offset(0)));
+        }
+        #[rustfmt::skip]
+        update
+            .i32_const(base)
+            .local_get(len)
+            .i32_const(1)
+            .i32_add()
+            .i32_store(self.agg_mem_arg(0))
+            .end();
+
+        // 3. fold the value into the entry
+        let v0 = self.agg_mem_arg(layout.val_offset(0));
+        match &agg.func {
+            AggFn::Count => {
+                self.emit_agg_incr(&mut update, entry, None, v0);
+            }
+            AggFn::Sum => {
+                self.emit_agg_incr(&mut update, entry, Some(val), v0);
+            }
+            AggFn::Avg => {
+                self.emit_agg_incr(&mut update, entry, Some(val), v0);
+                let v1 = self.agg_mem_arg(layout.val_offset(1));
+                self.emit_agg_incr(&mut update, entry, None, v1);
+            }
+            AggFn::Min | AggFn::Max => {
+                // skip the store if the current value should be kept
+                update
+                    .block(BlockType::Empty)
+                    .local_get(val)
+                    .local_get(entry)
+                    .i32_load(v0);
+                if agg.func == AggFn::Min {
+                    update.i32_gte_signed();
+                } else {
+                    update.i32_lte_signed();
+                }
+                update
+                    .br_if(0)
+                    .local_get(entry)
+                    .local_get(val)
+                    .i32_store(v0)
+                    .end();
+            }
+            AggFn::Quantize => {
+                self.emit_agg_incr(&mut update, entry, None, v0);
+                // bucket 0: < 0, bucket 1: == 0, bucket k+2: [2^k, 2^(k+1))
+                #[rustfmt::skip]
+                update
+                    .block(BlockType::Empty)
+                    .local_get(val)
+                    .i32_const(0)
+                    .i32_lt_signed()
+                    .br_if(0)
+                    .i32_const(1)
+                    .local_set(bucket)
+                    .local_get(val)
+                    .i32_eqz()
+                    .br_if(0)
+                    .i32_const(33)
+                    .local_get(val)
+                    .i32_clz()
+                    .i32_sub()
+                    .local_set(bucket)
+                    .end();
+                self.emit_agg_bucket_incr(&mut update, layout, entry, bucket);
+            }
+            AggFn::LQuantize { lo, hi, step } => {
+                self.emit_agg_incr(&mut update, entry, None, v0);
+                // bucket 0: < lo, last bucket: >= hi, otherwise 1 + (val - lo) / step
+                #[rustfmt::skip]
+                update
+                    .block(BlockType::Empty)
+                    .local_get(val)
+                    .i32_const(*lo)
+                    .i32_lt_signed()
+                    .br_if(0)
+                    .i32_const(agg.func.num_buckets() as i32 - 1)
+                    .local_set(bucket)
+                    .local_get(val)
+                    .i32_const(*hi)
+                    .i32_gte_signed()
+                    .br_if(0)
+                    .local_get(val)
+                    .i32_const(*lo)
+                    .i32_sub()
+                    .i32_const(*step)
+                    .i32_div_signed()
+                    .i32_const(1)
+                    .i32_add()
+                    .local_set(bucket)
+                    .end();
+                self.emit_agg_bucket_incr(&mut update, layout, entry, bucket);
+            }
+        }
+
+        update.finish(self.app_wasm)
+    }
+
+    /// Adds the value in the `by` local (or 1) to the i32 at `entry` + `mem_arg.offset`
+    fn emit_agg_incr(
+        &self,
+        func: &mut FunctionBuilder,
+        entry: u32,
+        by: Option<u32>,
+        mem_arg: MemArg,
+    ) {
+        func.local_get(entry).local_get(entry).i32_load(mem_arg);
+        match by {
+            Some(by) => func.local_get(by),
+            None => func.i32_const(1),
+        };
+        func.i32_add().i32_store(mem_arg);
+    }
+
+    fn emit_agg_bucket_incr(
+        &self,
+        func: &mut FunctionBuilder,
+        layout: &AggLayout,
+        entry: u32,
+        bucket: u32,
+    ) {
+        let buckets = self.agg_mem_arg(layout.val_offset(1));
+        func.local_get(bucket)
+            .i32_const(4)
+            .i32_mul()
+            .local_get(entry)
+            .i32_add()
+            .local_set(bucket);
+        self.emit_agg_incr(func, bucket, None, buckets);
+    }
+
+    fn emit_agg_readers(&mut self, agg: &Aggregation, layout: &AggLayout) {
+        let name = &agg.name;
+        let base = layout.base as i32;
+
+        let mut len = FunctionBuilder::new(&[], &[OrcaType::I32]);
+        len.i32_const(base).i32_load(self.agg_mem_arg(0));
+        self.export_agg_reader(format!("get_agg_{name}_len"), len);
+
+        let mut dropped = FunctionBuilder::new(&[], &[OrcaType::I32]);
+        dropped.i32_const(base).i32_load(self.agg_mem_arg(4));
+        self.export_agg_reader(format!("get_agg_{name}_dropped"), dropped);
+
+        // param 0 is the entry index
+        for key in 0..layout.num_keys {
+            let mut get_key = FunctionBuilder::new(&[OrcaType::I32], &[OrcaType::I32]);
+            layout.emit_entry_addr(0, &mut get_key);
+            get_key.i32_load(self.agg_mem_arg(layout.key_offset(key)));
+            self.export_agg_reader(format!("get_agg_{name}_key{key}"), get_key);
+        }
+
+        let mut get_val = FunctionBuilder::new(&[OrcaType::I32], &[OrcaType::I32]);
+        layout.emit_entry_addr(0, &mut get_val);
+        get_val.i32_load(self.agg_mem_arg(layout.val_offset(0)));
+        if agg.func == AggFn::Avg {
+            // sum / count
+            layout.emit_entry_addr(0, &mut get_val);
+            get_val
+                .i32_load(self.agg_mem_arg(layout.val_offset(1)))
+                .i32_div_signed();
+        }
+        self.export_agg_reader(format!("get_agg_{name}"), get_val);
+
+        if agg.func.num_buckets() > 0 {
+            // param 1 is the bucket index
+            let mut get_bucket =
+                FunctionBuilder::new(&[OrcaType::I32, OrcaType::I32], &[OrcaType::I32]);
+            layout.emit_entry_addr(0, &mut get_bucket);
+            get_bucket
+                .local_get(1)
+                .i32_const(4)
+                .i32_mul()
+                .i32_add()
+                .i32_load(self.agg_mem_arg(layout.val_offset(1)));
+            self.export_agg_reader(format!("get_agg_{name}_bucket"), get_bucket);
+        }
+    }
+
+    fn export_agg_reader(&mut self, name: String, reader: FunctionBuilder<'b>) {
+        let reader_id = reader.finish(self.app_wasm);
+        let name = self.export_name(name);
+        self.names.funcs.insert(reader_id, name.clone());
+        self.app_wasm.add_export_func(name.leak(), reader_id);
+    }
+
     pub(crate) fn emit_global_getter(
         &mut self,
         global_id: &u32,
@@ -324,7 +1019,8 @@ impl<'a, 'b, 'c, 'd> ModuleEmitter<'a, 'b, 'c, 'd> {
 
         let getter_id = getter.finish(self.app_wasm);
 
-        let fn_name = format!("get_{name}");
+        let fn_name = self.export_name(format!("get_{name}"));
+        self.names.funcs.insert(getter_id, fn_name.clone());
         self.app_wasm.add_export_func(fn_name.leak(), getter_id);
 
         Ok(true)
@@ -350,6 +1046,7 @@ impl<'a, 'b, 'c, 'd> ModuleEmitter<'a, 'b, 'c, 'd> {
             } // Ignore, continue to emit
         };
 
+        let global_name = format!("whamm_{}", self.export_name(name.clone()));
         let rec = self.table.get_record_mut(&rec_id);
         let (global_id, ty) = match rec {
             Some(Record::Var { ref mut addr, .. }) => {
@@ -357,6 +1054,7 @@ impl<'a, 'b, 'c, 'd> ModuleEmitter<'a, 'b, 'c, 'd> {
                 // this is used for user-defined global vars in the script...
                 let default_global = whamm_type_to_wasm_global(&ty);
                 let global_id = self.app_wasm.add_global(default_global.clone());
+                self.names.globals.insert(global_id, global_name);
                 *addr = Some(VarAddr::Global { addr: global_id });
                 (global_id, default_global.ty)
             }
@@ -0,0 +1,181 @@
+use crate::emitter::rewriting::rules::{
+    event_factory, probe_factory, Event, FromStr, InstrLoc, LocInfo, Package,
+};
+use crate::parser::rules::component::{CanonEventKind, ComponentPackageKind};
+use crate::parser::types::{DataType, ProbeSpec, SpecPart, Value};
+use orca::ir::module::Module;
+use orca::ir::types::FuncKind;
+use std::collections::HashMap;
+
+use crate::generator::simple_ast::SimpleProbe;
+use wasmparser::Operator;
+
+pub struct ComponentPackage {
+    kind: ComponentPackageKind,
+    pub events: Vec<Box<dyn Event>>,
+}
+impl FromStr for ComponentPackage {
+    fn from_str(name: &str) -> Self {
+        match name {
+            "canon" => Self::canon(),
+            _ => panic!("unsupported ComponentPackage: {name}"),
+        }
+    }
+}
+impl ComponentPackage {
+    fn canon() -> Self {
+        Self {
+            kind: ComponentPackageKind::Canon,
+            events: vec![],
+        }
+    }
+}
+impl Package for ComponentPackage {
+    fn get_loc_info(&self, app_wasm: &Module, loc: &InstrLoc, instr: &Operator) -> Option<LocInfo> {
+        // There is no canonical ABI outside of a component
+        let core_module = loc.core_module?;
+
+        let mut loc_info = LocInfo::new();
+        match self.kind {
+            ComponentPackageKind::Canon => {
+                loc_info.add_instr_loc(loc);
+                loc_info.static_data.insert(
+                    "core_module".to_string(),
+                    Some(Value::Integer {
+                        ty: DataType::I32,
+                        val: core_module as i32,
+                    }),
+                );
+            }
+        }
+
+        // Get location info from the rest of the configured rules
+        self.events.iter().for_each(|event| {
+            if let Some(mut other_loc_info) = event.get_loc_info(app_wasm, loc, instr) {
+                loc_info.append(&mut other_loc_info);
+            }
+        });
+
+        if loc_info.has_match() {
+            Some(loc_info)
+        } else {
+            None
+        }
+    }
+    fn add_events(&mut self, ast_events: &HashMap<String, HashMap<String, Vec<SimpleProbe>>>) {
+        let events = match self.kind {
+            ComponentPackageKind::Canon => event_factory::<CanonEvent>(ast_events),
+        };
+        self.events = events;
+    }
+}
+
+pub struct CanonEvent {
+    kind: CanonEventKind,
+    // Map from probe_mode_name -> Vec[probes_of_this_mode]
+    // Retains ordering of instrumentation units (in order of scripts passed by user)
+    probes: HashMap<String, Vec<SimpleProbe>>,
+}
+impl FromStr for CanonEvent {
+    fn from_str(name: &str) -> Self {
+        match name {
+            "lower" => Self::lower(),
+            "lift" => Self::lift(),
+            _ => panic!("unsupported CanonEvent: {name}"),
+        }
+    }
+}
+impl CanonEvent {
+    fn lower() -> Self {
+        Self {
+            kind: CanonEventKind::Lower,
+            probes: HashMap::new(),
+        }
+    }
+    fn lift() -> Self {
+        Self {
+            kind: CanonEventKind::Lift,
+            probes: HashMap::new(),
+        }
+    }
+
+    fn probe_spec(&self) -> ProbeSpec {
+        ProbeSpec {
+            provider: Some(SpecPart {
+                name: "component".to_string(),
+                loc: None,
+            }),
+            package: Some(SpecPart {
+                name: "canon".to_string(),
+                loc: None,
+            }),
+            event: Some(SpecPart {
+                name: self.kind.name(),
+                loc: None,
+            }),
+            mode: None,
+        }
+    }
+}
+impl Event for CanonEvent {
+    fn get_loc_info(&self, app_wasm: &Module, loc: &InstrLoc, instr: &Operator) -> Option<LocInfo> {
+        let mut loc_info = LocInfo::new();
+        match self.kind {
+            CanonEventKind::Lower => {
+                if let Operator::Call {
+                    function_index: fid,
+                } = instr
+                {
+                    // only the imports of a core module are lowered
+                    if let Some(FuncKind::Import(_)) = app_wasm.get_fn_kind(*fid) {
+                        let (module, name) = match app_wasm.imports.get(*fid as usize) {
+                            Some(import) => (import.module.to_string(), import.name.to_string()),
+                            None => ("".to_string(), "".to_string()),
+                        };
+                        loc_info.static_data.insert(
+                            "imp_module".to_string(),
+                            Some(Value::Str {
+                                ty: DataType::Str,
+                                val: module,
+                            }),
+                        );
+                        loc_info.static_data.insert(
+                            "imp_name".to_string(),
+                            Some(Value::Str {
+                                ty: DataType::Str,
+                                val: name,
+                            }),
+                        );
+
+                        // add the probes for this event
+                        loc_info.add_probes(self.probe_spec(), &self.probes);
+                    }
+                }
+            }
+            CanonEventKind::Lift => {
+                // a lifted function is probed at its entry (before its first instruction)
+                if let (0, Some(exp_name)) = (loc.instr_idx, &loc.fn_export) {
+                    loc_info.static_data.insert(
+                        "exp_name".to_string(),
+                        Some(Value::Str {
+                            ty: DataType::Str,
+                            val: exp_name.clone(),
+                        }),
+                    );
+
+                    // add the probes for this event
+                    loc_info.add_probes(self.probe_spec(), &self.probes);
+                }
+            }
+        }
+
+        if loc_info.has_match() {
+            Some(loc_info)
+        } else {
+            None
+        }
+    }
+    fn add_probes(&mut self, ast_probes: &HashMap<String, Vec<SimpleProbe>>) {
+        self.probes = probe_factory(ast_probes);
+    }
+}
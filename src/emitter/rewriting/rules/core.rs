@@ -1,5 +1,5 @@
 use crate::emitter::rewriting::rules::{
-    event_factory, probe_factory, Event, FromStr, LocInfo, Package,
+    event_factory, probe_factory, Event, FromStr, InstrLoc, LocInfo, Package,
 };
 use crate::parser::rules::core::{CoreEventKind, CorePackageKind};
 use std::collections::HashMap;
@@ -29,7 +29,7 @@ impl CorePackage {
     }
 }
 impl Package for CorePackage {
-    fn get_loc_info(&self, app_wasm: &Module, instr: &Operator) -> Option<LocInfo> {
+    fn get_loc_info(&self, app_wasm: &Module, loc: &InstrLoc, instr: &Operator) -> Option<LocInfo> {
         let mut loc_info = LocInfo::new();
         match self.kind {
             CorePackageKind::Default => {
@@ -39,7 +39,7 @@ impl Package for CorePackage {
 
         // Get location info from the rest of the configured rules
         self.events.iter().for_each(|event| {
-            if let Some(mut other_loc_info) = event.get_loc_info(app_wasm, instr) {
+            if let Some(mut other_loc_info) = event.get_loc_info(app_wasm, loc, instr) {
                 loc_info.append(&mut other_loc_info);
             }
         });
@@ -82,7 +82,12 @@ impl CoreEvent {
     }
 }
 impl Event for CoreEvent {
-    fn get_loc_info(&self, _app_wasm: &Module, _instr: &Operator) -> Option<LocInfo> {
+    fn get_loc_info(
+        &self,
+        _app_wasm: &Module,
+        _loc: &InstrLoc,
+        _instr: &Operator,
+    ) -> Option<LocInfo> {
         let loc_info = LocInfo::new();
         match self.kind {
             CoreEventKind::Default => {
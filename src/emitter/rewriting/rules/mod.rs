@@ -1,14 +1,18 @@
+use crate::emitter::rewriting::debug_line::SrcLoc;
+use crate::emitter::rewriting::rules::component::ComponentPackage;
 use crate::emitter::rewriting::rules::core::CorePackage;
 use crate::emitter::rewriting::rules::wasm::{OpcodeEvent, WasmPackage};
 use crate::parser::rules::WhammProviderKind;
-use crate::parser::types::{ProbeSpec, SpecPart, Value};
+use crate::parser::types::{DataType, ProbeSpec, SpecPart, Value};
 use std::collections::HashMap;
+use std::fmt;
 
 use crate::generator::simple_ast::{SimpleAstProbes, SimpleProbe};
 use orca::ir::module::Module;
 use orca::ir::types::DataType as OrcaType;
 use wasmparser::Operator;
 
+mod component;
 mod core;
 pub mod wasm;
 
@@ -113,6 +117,43 @@ impl Arg {
     }
 }
 
+/// Where the instruction being visited lives in the application.
+#[derive(Clone, Debug, Default)]
+pub struct InstrLoc {
+    /// The index of the core module the instruction is in, if the application is a component
+    pub core_module: Option<u32>,
+    pub fn_id: u32,
+    pub fn_name: String,
+    /// The name the function is exported under, this is only known in a core module of a
+    /// component (see the `component` provider)
+    pub fn_export: Option<String>,
+    pub instr_idx: u32,
+    /// The byte offset of the instruction in the code section (if it could be found)
+    pub pc: Option<u32>,
+    /// Where the instruction comes from in the source code (if the application has DWARF)
+    pub src: Option<SrcLoc>,
+}
+impl fmt::Display for InstrLoc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(core_module) = self.core_module {
+            write!(f, "core module {}, ", core_module)?;
+        }
+        if self.fn_name.is_empty() {
+            write!(f, "fn_id {}", self.fn_id)?;
+        } else {
+            write!(f, "fn_id {} (`{}`)", self.fn_id, self.fn_name)?;
+        }
+        write!(f, ", instr_idx {}", self.instr_idx)?;
+        if let Some(pc) = self.pc {
+            write!(f, ", pc {:#x}", pc)?;
+        }
+        if let Some(src) = &self.src {
+            write!(f, " ({}:{})", src.file, src.line)?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Default, Debug)]
 pub struct LocInfo<'a> {
     /// static information to be saved in symbol table
@@ -131,6 +172,61 @@ impl<'a> LocInfo<'a> {
     fn has_match(&self) -> bool {
         !self.probes.is_empty()
     }
+    fn add_instr_loc(&mut self, loc: &InstrLoc) {
+        self.static_data.insert(
+            "fn_id".to_string(),
+            Some(Value::Integer {
+                ty: DataType::I32,
+                val: loc.fn_id as i32,
+            }),
+        );
+        self.static_data.insert(
+            "fn_name".to_string(),
+            Some(Value::Str {
+                ty: DataType::Str,
+                val: loc.fn_name.clone(),
+            }),
+        );
+        self.static_data.insert(
+            "instr_idx".to_string(),
+            Some(Value::Integer {
+                ty: DataType::I32,
+                val: loc.instr_idx as i32,
+            }),
+        );
+        self.static_data.insert(
+            "pc".to_string(),
+            Some(Value::Integer {
+                ty: DataType::I32,
+                // -1 if we were unable to find the offset of this instruction
+                val: loc.pc.map_or(-1, |pc| pc as i32),
+            }),
+        );
+        self.static_data.insert(
+            "src_file".to_string(),
+            Some(Value::Str {
+                ty: DataType::Str,
+                val: loc
+                    .src
+                    .as_ref()
+                    .map_or(String::new(), |src| src.file.clone()),
+            }),
+        );
+        self.static_data.insert(
+            "src_line".to_string(),
+            Some(Value::Integer {
+                ty: DataType::I32,
+                val: loc.src.as_ref().map_or(0, |src| src.line as i32),
+            }),
+        );
+        self.static_data.insert(
+            "src_col".to_string(),
+            Some(Value::Integer {
+                ty: DataType::I32,
+                val: loc.src.as_ref().map_or(0, |src| src.col as i32),
+            }),
+        );
+    }
     fn add_probes(&mut self, base_spec: ProbeSpec, probes: &'a HashMap<String, Vec<SimpleProbe>>) {
         probes.iter().for_each(|(probe_mode, probes)| {
             let mut spec = base_spec.clone();
@@ -176,7 +272,7 @@ impl<'a> LocInfo<'a> {
 
 pub trait Provider {
     /// Pass some location to the provider and get back two types of data:
-    fn get_loc_info(&self, app_wasm: &Module, instr: &Operator) -> Option<LocInfo>;
+    fn get_loc_info(&self, app_wasm: &Module, loc: &InstrLoc, instr: &Operator) -> Option<LocInfo>;
     fn add_packages(
         &mut self,
         ast_packages: &HashMap<String, HashMap<String, HashMap<String, Vec<SimpleProbe>>>>,
@@ -184,12 +280,12 @@ pub trait Provider {
 }
 pub trait Package {
     /// Pass some location to the provider and get back two types of data:
-    fn get_loc_info(&self, app_wasm: &Module, instr: &Operator) -> Option<LocInfo>;
+    fn get_loc_info(&self, app_wasm: &Module, loc: &InstrLoc, instr: &Operator) -> Option<LocInfo>;
     fn add_events(&mut self, ast_events: &HashMap<String, HashMap<String, Vec<SimpleProbe>>>);
 }
 pub trait Event {
     /// Pass some location to the provider and get back two types of data:
-    fn get_loc_info(&self, app_wasm: &Module, instr: &Operator) -> Option<LocInfo>;
+    fn get_loc_info(&self, app_wasm: &Module, loc: &InstrLoc, instr: &Operator) -> Option<LocInfo>;
     fn add_probes(&mut self, ast_probes: &HashMap<String, Vec<SimpleProbe>>);
 }
 
@@ -203,6 +299,7 @@ impl FromStr for WhammProvider {
         match name {
             "core" => Self::core(),
             "wasm" => Self::wasm(),
+            "component" => Self::component(),
             _ => panic!("unsupported WhammProvider: {name}"),
         }
     }
@@ -220,12 +317,18 @@ impl WhammProvider {
             packages: vec![],
         }
     }
+    fn component() -> Self {
+        Self {
+            kind: WhammProviderKind::Component,
+            packages: vec![],
+        }
+    }
 }
 impl Provider for WhammProvider {
-    fn get_loc_info(&self, app_wasm: &Module, instr: &Operator) -> Option<LocInfo> {
+    fn get_loc_info(&self, app_wasm: &Module, loc: &InstrLoc, instr: &Operator) -> Option<LocInfo> {
         let mut loc_info = LocInfo::new();
         match self.kind {
-            WhammProviderKind::Core | WhammProviderKind::Wasm => {
+            WhammProviderKind::Core | WhammProviderKind::Wasm | WhammProviderKind::Component => {
                 // nothing to add
             }
         }
@@ -235,7 +338,7 @@ impl Provider for WhammProvider {
 
         // Get location info from the rest of the configured rules
         self.packages.iter().for_each(|package| {
-            if let Some(mut other_loc_info) = package.get_loc_info(app_wasm, instr) {
+            if let Some(mut other_loc_info) = package.get_loc_info(app_wasm, loc, instr) {
                 loc_info.append(&mut other_loc_info);
             }
         });
@@ -253,6 +356,7 @@ impl Provider for WhammProvider {
         let packages = match self.kind {
             WhammProviderKind::Core => package_factory::<CorePackage>(ast_packages),
             WhammProviderKind::Wasm => package_factory::<WasmPackage>(ast_packages),
+            WhammProviderKind::Component => package_factory::<ComponentPackage>(ast_packages),
         };
         self.packages = packages;
     }
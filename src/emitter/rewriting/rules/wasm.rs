@@ -1,5 +1,5 @@
 use crate::emitter::rewriting::rules::{
-    event_factory, probe_factory, Arg, Event, FromStr, LocInfo, Package,
+    event_factory, probe_factory, Arg, Event, FromStr, InstrLoc, LocInfo, Package,
 };
 use crate::parser::rules::wasm::{OpcodeEventKind, WasmPackageKind};
 use crate::parser::types::{DataType, ProbeSpec, SpecPart, Value};
@@ -32,17 +32,18 @@ impl WasmPackage {
     }
 }
 impl Package for WasmPackage {
-    fn get_loc_info(&self, app_wasm: &Module, instr: &Operator) -> Option<LocInfo> {
+    fn get_loc_info(&self, app_wasm: &Module, loc: &InstrLoc, instr: &Operator) -> Option<LocInfo> {
         let mut loc_info = LocInfo::new();
         match self.kind {
             WasmPackageKind::Opcode => {
-                // nothing to add
+                // Every opcode event knows where it is in the application
+                loc_info.add_instr_loc(loc);
             }
         }
 
         // Get location info from the rest of the configured rules
         self.events.iter().for_each(|event| {
-            if let Some(mut other_loc_info) = event.get_loc_info(app_wasm, instr) {
+            if let Some(mut other_loc_info) = event.get_loc_info(app_wasm, loc, instr) {
                 loc_info.append(&mut other_loc_info);
             }
         });
@@ -507,7 +508,12 @@ impl OpcodeEvent {
     }
 }
 impl Event for OpcodeEvent {
-    fn get_loc_info(&self, app_wasm: &Module, instr: &Operator) -> Option<LocInfo> {
+    fn get_loc_info(
+        &self,
+        app_wasm: &Module,
+        _loc: &InstrLoc,
+        instr: &Operator,
+    ) -> Option<LocInfo> {
         let mut loc_info = LocInfo::new();
 
         match self.kind {
@@ -1,34 +1,67 @@
 use crate::common::error::{ErrorGen, WhammError};
-use crate::emitter::rewriting::module_emitter::MemoryTracker;
+use crate::emitter::custom_sections::InjectedNames;
+use crate::emitter::rewriting::app_info::AppInfo;
+use crate::emitter::rewriting::module_emitter::{MemoryTracker, WASI_MODULE};
 use crate::emitter::rewriting::rules::wasm::OpcodeEvent;
-use crate::emitter::rewriting::rules::{Arg, LocInfo, Provider, WhammProvider};
-use crate::emitter::rewriting::{block_type_to_wasm, emit_expr};
+use crate::emitter::rewriting::rules::{Arg, InstrLoc, LocInfo, Provider, WhammProvider};
+use crate::emitter::rewriting::{
+    block_type_to_wasm, emit_expr, orca_type_to_whamm_type, wasm_type_to_whamm_type,
+    whamm_type_to_wasm_type,
+};
 use crate::emitter::rewriting::{emit_stmt, Emitter};
 use crate::generator::types::ExprFolder;
 use crate::parser::types::{Block, DataType, Definition, Expr, ProbeSpec, Statement, Value};
 use crate::verifier::types::{Record, SymbolTable, VarAddr};
 use orca::ir::module::Module;
-use orca::ir::types::BlockType;
+use orca::ir::types::{BlockType, FuncKind, Location};
 use orca::iterator::iterator_trait::Iterator as OrcaIterator;
 use orca::iterator::module_iterator::ModuleIterator;
 use orca::opcode::Opcode;
 use orca::ModuleBuilder;
+use std::collections::HashMap;
 use std::iter::Iterator;
+use wasmparser::Operator;
 
 const UNEXPECTED_ERR_MSG: &str =
     "VisitingEmitter: Looks like you've found a bug...please report this behavior!";
 
+/// A core module of a component, see the `component` provider.
+pub(crate) struct InComponent {
+    /// The index of the core module, in the order they appear in the component
+    pub core_module: u32,
+    /// The functions that the core module exports (before it was instrumented), these are
+    /// the ones that the component can lift
+    pub fn_exports: HashMap<u32, String>,
+}
+
 pub struct VisitingEmitter<'a, 'b, 'c, 'd> {
     pub app_iter: ModuleIterator<'a, 'b>,
     pub table: &'c mut SymbolTable,
     mem_tracker: &'d MemoryTracker,
+    app_info: AppInfo,
+    /// Where the module being instrumented is in a component, if it is one of its core modules
+    pub(crate) in_component: Option<InComponent>,
     instr_created_args: Vec<(String, usize)>,
+    instr_site_vars: Vec<String>,
+    /// The locals that hold the scoped variables (`this.x`/`frame.x`) in the function
+    /// that is currently being instrumented, keyed by the ID of the variable's record so that
+    /// the variables of different scripts don't share a local
+    scoped_locals: HashMap<usize, u32>,
+    scoped_locals_fn: Option<u32>,
+    /// The records of the `this.x` variables that have been reset at the current instruction
+    this_vars_reset: Vec<usize>,
+    this_vars_site: Option<(u32, u32)>,
+    /// The names of the locals injected so far, for the `name` section
+    pub(crate) names: InjectedNames,
 }
 
 impl<'a, 'b, 'c, 'd> VisitingEmitter<'a, 'b, 'c, 'd> {
     // note: only used in integration test
+    /// The `app_info` must be pulled from the module as the application passed it in, before
+    /// anything was injected into `app_wasm`.
     pub fn new(
         app_wasm: &'a mut Module<'b>,
+        app_info: AppInfo,
         table: &'c mut SymbolTable,
         mem_tracker: &'d MemoryTracker,
     ) -> Self {
@@ -36,7 +69,15 @@ impl<'a, 'b, 'c, 'd> VisitingEmitter<'a, 'b, 'c, 'd> {
             app_iter: ModuleIterator::new(app_wasm, vec![]),
             table,
             mem_tracker,
+            app_info,
+            in_component: None,
             instr_created_args: vec![],
+            instr_site_vars: vec![],
+            scoped_locals: HashMap::new(),
+            scoped_locals_fn: None,
+            this_vars_reset: vec![],
+            this_vars_site: None,
+            names: InjectedNames::default(),
         };
 
         a
@@ -75,9 +116,103 @@ impl<'a, 'b, 'c, 'd> VisitingEmitter<'a, 'b, 'c, 'd> {
         }
     }
 
+    /// The ID of the function that the current instruction is in, and the index of the
+    /// instruction in the function's body.
+    fn curr_fn_instr(&self) -> (u32, usize) {
+        // A `ModuleIterator` only yields `Location::Module`, the component locations come from
+        // orca's component iterator. whamm instruments a component's core modules on their own
+        // instead (see `emitter::component`), but either way the indices are within the module.
+        let (func_idx, instr_idx) = match self.app_iter.curr_loc() {
+            Location::Module {
+                func_idx,
+                instr_idx,
+            }
+            | Location::Component {
+                func_idx,
+                instr_idx,
+                ..
+            } => (func_idx, instr_idx),
+        };
+        // The iterator's func_idx does not account for imported functions
+        (
+            func_idx as u32 + self.app_iter.module.num_import_func(),
+            instr_idx,
+        )
+    }
+
+    /// The ID of the function that the current instruction is in, without building the whole
+    /// `InstrLoc`.
+    pub(crate) fn curr_fn_id(&self) -> u32 {
+        self.curr_fn_instr().0
+    }
+
+    /// The name of the function with the passed ID, empty if it has none.
+    pub(crate) fn fn_name(&self, fn_id: u32) -> String {
+        self.app_info.fn_name(fn_id).cloned().unwrap_or_default()
+    }
+
+    pub(crate) fn curr_instr_loc(&self) -> InstrLoc {
+        let (fn_id, instr_idx) = self.curr_fn_instr();
+        InstrLoc {
+            core_module: self.in_component.as_ref().map(|c| c.core_module),
+            fn_id,
+            fn_name: self.fn_name(fn_id),
+            fn_export: self
+                .in_component
+                .as_ref()
+                .and_then(|c| c.fn_exports.get(&fn_id).cloned()),
+            instr_idx: instr_idx as u32,
+            pc: self.app_info.pc(fn_id, instr_idx),
+            src: self.app_info.src_loc(fn_id, instr_idx).cloned(),
+        }
+    }
+
+    /// The module that the function called by the current instruction is imported from, if it
+    /// is a call to an imported function.
+    pub(crate) fn curr_call_import_module(&self) -> Option<String> {
+        if let Some(Operator::Call { function_index }) = self.app_iter.curr_op() {
+            let module = &self.app_iter.module;
+            if let Some(FuncKind::Import(_)) = module.get_fn_kind(*function_index) {
+                return module
+                    .imports
+                    .get(*function_index as usize)
+                    .map(|import| import.module.to_string());
+            }
+        }
+        None
+    }
+
+    /// Whether the application exits at the current instruction: a `return` (or the final `end`)
+    /// of the `_start` function of a WASI command, or a call to WASI's `proc_exit`.
+    pub(crate) fn at_app_exit(&self) -> bool {
+        let (fn_id, instr_idx) = self.curr_fn_instr();
+        let in_start = self.app_info.exported_fn("_start") == Some(fn_id);
+        match self.app_iter.curr_op() {
+            Some(Operator::Return) => in_start,
+            Some(Operator::End) => {
+                in_start && self.app_info.num_instrs(fn_id) == Some(instr_idx + 1)
+            }
+            Some(Operator::Call { function_index }) => {
+                self.app_info.imported_fn(WASI_MODULE, "proc_exit") == Some(*function_index)
+            }
+            _ => false,
+        }
+    }
+
+    /// Call the function with ID `fid` before the current instruction.
+    pub(crate) fn call_before(&mut self, fid: u32) {
+        self.app_iter.before();
+        self.app_iter.call(fid);
+    }
+
+    /// Describes the current instruction for error messages.
+    pub(crate) fn curr_site(&self) -> String {
+        self.curr_instr_loc().to_string()
+    }
+
     pub(crate) fn get_loc_info<'e>(&self, rule: &'e WhammProvider) -> Option<LocInfo<'e>> {
         if let Some(curr_instr) = self.app_iter.curr_op() {
-            rule.get_loc_info(self.app_iter.module, curr_instr)
+            rule.get_loc_info(self.app_iter.module, &self.curr_instr_loc(), curr_instr)
         } else {
             None
         }
@@ -89,7 +224,8 @@ impl<'a, 'b, 'c, 'd> VisitingEmitter<'a, 'b, 'c, 'd> {
         // to the call.
         let mut arg_recs: Vec<(String, usize)> = vec![]; // vec to retain order!
 
-        let mut arg_locals: Vec<(String, u32)> = vec![];
+        let mut arg_locals: Vec<(String, DataType, u32)> = vec![];
+        let (fid, _) = self.curr_fn_instr();
         args.iter().for_each(
             |Arg {
                  name: arg_name,
@@ -97,7 +233,11 @@ impl<'a, 'b, 'c, 'd> VisitingEmitter<'a, 'b, 'c, 'd> {
              }| {
                 // create local for the param in the module
                 let arg_local_id = self.app_iter.add_local(*arg_ty);
-                arg_locals.push((arg_name.to_string(), arg_local_id));
+                self.names
+                    .name_local(fid, arg_local_id, format!("whamm_{arg_name}"));
+                // a type whamm can't represent is never used by the probe (see `define_site_vars`)
+                let whamm_ty = orca_type_to_whamm_type(arg_ty).unwrap_or(DataType::AssumeGood);
+                arg_locals.push((arg_name.to_string(), whamm_ty, arg_local_id));
             },
         );
 
@@ -105,7 +245,7 @@ impl<'a, 'b, 'c, 'd> VisitingEmitter<'a, 'b, 'c, 'd> {
         arg_locals
             .iter()
             .rev()
-            .for_each(|(arg_name, arg_local_id)| {
+            .for_each(|(arg_name, arg_ty, arg_local_id)| {
                 // emit an opcode in the event to assign the ToS to this new local
                 self.app_iter.local_set(*arg_local_id);
 
@@ -113,7 +253,7 @@ impl<'a, 'b, 'c, 'd> VisitingEmitter<'a, 'b, 'c, 'd> {
                 let id = self.table.put(
                     arg_name.to_string(),
                     Record::Var {
-                        ty: DataType::I32, // we only support integers right now.
+                        ty: arg_ty.clone(),
                         name: arg_name.to_string(),
                         value: None,
                         is_comp_provided: false,
@@ -129,6 +269,128 @@ impl<'a, 'b, 'c, 'd> VisitingEmitter<'a, 'b, 'c, 'd> {
         true
     }
 
+    /// Define the passed site variables (see `is_site_var`) with their types at the current
+    /// instruction. Variables that aren't available at this instruction are left undefined,
+    /// the type checker reports their use.
+    pub(crate) fn define_site_vars(&mut self, site_vars: &[String], args: &[Arg], is_after: bool) {
+        let loc = self.curr_instr_loc();
+        for name in site_vars.iter() {
+            let (ty, addr) = if name == "tos" {
+                // the address is assigned once the value has been saved, see `save_tos`
+                let ty = self
+                    .app_info
+                    .tos_ty(loc.fn_id, loc.instr_idx as usize, is_after)
+                    .as_ref()
+                    .and_then(wasm_type_to_whamm_type);
+                (ty, None)
+            } else if let Some(idx) = name.strip_prefix("arg") {
+                // the address is assigned once the args have been saved, see `save_args`
+                let ty = args
+                    .get(idx.parse::<usize>().unwrap())
+                    .and_then(|arg| orca_type_to_whamm_type(&arg.ty));
+                (ty, None)
+            } else {
+                let local_idx = name[5..].parse::<u32>().unwrap();
+                let ty = self
+                    .app_info
+                    .local_ty(loc.fn_id, local_idx)
+                    .as_ref()
+                    .and_then(wasm_type_to_whamm_type);
+                (ty, Some(VarAddr::Local { addr: local_idx }))
+            };
+
+            if let Some(ty) = ty {
+                self.table.put(
+                    name.clone(),
+                    Record::Var {
+                        ty,
+                        name: name.clone(),
+                        value: None,
+                        is_comp_provided: false,
+                        addr,
+                        loc: None,
+                    },
+                );
+                self.instr_site_vars.push(name.clone());
+            }
+        }
+    }
+
+    /// Bind the scoped variables (see `is_scoped_var`) used by a probe to locals of the
+    /// function being instrumented. Since locals start out as zero on each call, `frame.x`
+    /// lives as long as the function activation. `this.x` is reset before the instruction
+    /// each time it runs, so it only carries state from the `before` to the `after` probe.
+    pub(crate) fn bind_scoped_vars(&mut self, scoped_vars: &[String]) {
+        let loc = self.curr_instr_loc();
+        if self.scoped_locals_fn != Some(loc.fn_id) {
+            self.scoped_locals_fn = Some(loc.fn_id);
+            self.scoped_locals.clear();
+        }
+        if self.this_vars_site != Some((loc.fn_id, loc.instr_idx)) {
+            self.this_vars_site = Some((loc.fn_id, loc.instr_idx));
+            self.this_vars_reset.clear();
+        }
+
+        for name in scoped_vars.iter() {
+            let rec_id = match self.table.lookup(name) {
+                Some(rec_id) => *rec_id,
+                None => continue,
+            };
+            let ty = match self.table.get_record(&rec_id) {
+                Some(Record::Var { ty, .. }) => ty.clone(),
+                _ => continue,
+            };
+            let local_id = match self.scoped_locals.get(&rec_id) {
+                Some(local_id) => *local_id,
+                None => {
+                    let local_id = self.app_iter.add_local(whamm_type_to_wasm_type(&ty));
+                    self.names
+                        .name_local(loc.fn_id, local_id, format!("whamm_{name}"));
+                    self.scoped_locals.insert(rec_id, local_id);
+                    local_id
+                }
+            };
+            if name.starts_with("this.") && !self.this_vars_reset.contains(&rec_id) {
+                self.app_iter.before();
+                match ty {
+                    DataType::I64 => self.app_iter.i64_const(0),
+                    DataType::F32 => self.app_iter.f32_const(0.0),
+                    DataType::F64 => self.app_iter.f64_const(0.0),
+                    _ => self.app_iter.i32_const(0),
+                };
+                self.app_iter.local_set(local_id);
+                self.this_vars_reset.push(rec_id);
+            }
+            if let Some(Record::Var { addr, .. }) = self.table.get_record_mut(&rec_id) {
+                *addr = Some(VarAddr::Local { addr: local_id });
+            }
+        }
+    }
+
+    /// Save the value on top of the stack to a new local so that the probe can read it as `tos`.
+    /// Does nothing if the probe does not use `tos`.
+    pub(crate) fn save_tos(&mut self) -> bool {
+        if !self.instr_site_vars.iter().any(|name| name == "tos") {
+            return true;
+        }
+        let rec_id = match self.table.lookup("tos") {
+            Some(rec_id) => *rec_id,
+            None => return false,
+        };
+        let (fid, _) = self.curr_fn_instr();
+        if let Some(Record::Var { ty, addr, .. }) = self.table.get_record_mut(&rec_id) {
+            let tos_local_id = self.app_iter.add_local(whamm_type_to_wasm_type(ty));
+            self.names
+                .name_local(fid, tos_local_id, "whamm_tos".to_string());
+            // tee to leave the value on the stack for the instruction
+            self.app_iter.local_tee(tos_local_id);
+            *addr = Some(VarAddr::Local { addr: tos_local_id });
+            true
+        } else {
+            false
+        }
+    }
+
     pub(crate) fn emit_args(&mut self) -> Result<bool, Box<WhammError>> {
         for (_param_name, param_rec_id) in self.instr_created_args.iter() {
             let param_rec = self.table.get_record_mut(param_rec_id);
@@ -196,6 +458,10 @@ impl<'a, 'b, 'c, 'd> VisitingEmitter<'a, 'b, 'c, 'd> {
             self.table.remove_record(&arg_name);
         }
         self.instr_created_args.clear();
+        for name in self.instr_site_vars.iter() {
+            self.table.remove_record(name);
+        }
+        self.instr_site_vars.clear();
     }
 
     pub(crate) fn fold_expr(&mut self, expr: &mut Expr) -> bool {
@@ -230,26 +496,52 @@ impl<'a, 'b, 'c, 'd> VisitingEmitter<'a, 'b, 'c, 'd> {
         Ok(is_success)
     }
 
-    pub(crate) fn emit_if_with_orig_as_else(
+    /// Open a link in the chain of `alt` probes at this site: the body runs if the condition
+    /// holds, otherwise the `else` is left open for the next link (see `close_alt_chain`).
+    pub(crate) fn emit_alt_if(
         &mut self,
         condition: &mut Expr,
         conseq: &mut Block,
     ) -> Result<bool, Box<WhammError>> {
         let mut is_success = true;
 
-        // The consequent and alternate blocks must have the same type...
-        // this means that the result of the `if` should be the same as
-        // the result of the original instruction!
+        // emit the condition of the `if` expression
+        is_success &= self.emit_expr(condition)?;
+        // emit the beginning of the if block
+        let block_ty = self.orig_block_type();
+        self.app_iter.if_stmt(block_ty);
+
+        is_success &= self.emit_body(conseq)?;
+
+        // emit the beginning of the else
+        self.app_iter.else_stmt();
+        Ok(is_success)
+    }
+
+    /// Close the `num_open` links of the chain of `alt` probes at this site. Unless one of the
+    /// probes in the chain always runs, the innermost `else` falls back to the original
+    /// instruction, called with the args saved in `fallback_args`.
+    pub(crate) fn close_alt_chain(&mut self, num_open: usize, fallback_args: Option<&[u32]>) {
+        if let Some(args) = fallback_args {
+            self.emit_locals(args);
+            self.emit_orig();
+        }
+        for _ in 0..num_open {
+            // emit the end of the if block
+            self.app_iter.end();
+        }
+    }
+
+    /// The consequent and alternate blocks must have the same type...
+    /// this means that the result of the `if` should be the same as
+    /// the result of the original instruction!
+    fn orig_block_type(&mut self) -> BlockType {
         let orig_ty_id = OpcodeEvent::get_ty_info_for_instr(
             self.app_iter.module,
             self.app_iter.curr_op().unwrap(),
         )
         .1;
-
-        // emit the condition of the `if` expression
-        is_success &= self.emit_expr(condition)?;
-        // emit the beginning of the if block
-        let block_ty = match orig_ty_id {
+        match orig_ty_id {
             Some(ty_id) => {
                 let ty = match self.app_iter.module.types.get(ty_id as usize) {
                     Some(ty) => ty.results.clone(),
@@ -260,20 +552,29 @@ impl<'a, 'b, 'c, 'd> VisitingEmitter<'a, 'b, 'c, 'd> {
                 BlockType::FuncType(self.app_iter.module.add_type(&[], &ty))
             }
             None => BlockType::Empty,
-        };
-        self.app_iter.if_stmt(block_ty);
-
-        is_success &= self.emit_body(conseq)?;
-
-        // emit the beginning of the else
-        self.app_iter.else_stmt();
+        }
+    }
 
-        is_success &= self.emit_args()?;
-        is_success &= self.emit_orig();
+    /// The locals that the args of the current instruction were saved to (see `save_args`)
+    pub(crate) fn arg_locals(&self) -> Vec<u32> {
+        let mut locals = vec![];
+        for (_param_name, param_rec_id) in self.instr_created_args.iter() {
+            if let Some(Record::Var {
+                addr: Some(VarAddr::Local { addr }),
+                ..
+            }) = self.table.get_record(param_rec_id)
+            {
+                locals.push(*addr);
+            }
+        }
+        locals
+    }
 
-        // emit the end of the if block
-        self.app_iter.end();
-        Ok(is_success)
+    /// Place the values of the passed locals on the stack
+    pub(crate) fn emit_locals(&mut self, locals: &[u32]) {
+        for local in locals.iter() {
+            self.app_iter.local_get(*local);
+        }
     }
 
     fn handle_alt_call_by_name(
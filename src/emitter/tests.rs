@@ -1 +1,100 @@
+use crate::emitter::component::write_u32;
+use crate::emitter::rewriting::app_info::AppInfo;
+use crate::emitter::validation::validate;
+use gimli::write::{
+    Address, DwarfUnit, EndianVec, LineEncoding, LineProgram, LineString, Sections,
+};
 
+/// The validator finds the function that is invalid, so that it can be mapped back to the
+/// probes that were injected into it.
+#[test]
+fn validate_finds_invalid_fn() {
+    let module = wat::parse_str(
+        r#"
+(module
+  (import "env" "log" (func (param i32)))
+  (func (result i32)
+    i32.const 0)
+  (func (result i32)
+    i64.const 0))
+"#,
+    )
+    .unwrap();
+    let invalid = validate(&module).unwrap_err();
+    assert_eq!(invalid.fn_id, Some(2));
+    assert!(invalid.message.contains("type mismatch"));
+
+    let module = wat::parse_str("(module (func (result i32) i32.const 0))").unwrap();
+    assert!(validate(&module).is_ok());
+}
+
+/// Append a custom section to the encoded `module`.
+fn add_custom_section(module: &mut Vec<u8>, name: &str, data: &[u8]) {
+    let mut contents = vec![];
+    write_u32(name.len() as u32, &mut contents);
+    contents.extend_from_slice(name.as_bytes());
+    contents.extend_from_slice(data);
+    module.push(0);
+    write_u32(contents.len() as u32, module);
+    module.extend(contents);
+}
+
+/// The source location of an instruction is looked up in `.debug_line` by its offset in the
+/// code section.
+#[test]
+fn src_loc_from_debug_line() {
+    let mut module = wat::parse_str(
+        r#"
+(module
+  (func
+    i32.const 0
+    drop
+    nop))
+"#,
+    )
+    .unwrap();
+    let info = AppInfo::new(&module);
+    let (drop_pc, nop_pc) = (info.pc(0, 1).unwrap(), info.pc(0, 2).unwrap());
+
+    // `drop` comes from line 3 of main.rs, `nop` has no line information
+    let encoding = gimli::Encoding {
+        format: gimli::Format::Dwarf32,
+        version: 4,
+        address_size: 4,
+    };
+    let mut program = LineProgram::new(
+        encoding,
+        LineEncoding::default(),
+        LineString::String(b"/src".to_vec()),
+        LineString::String(b"main.rs".to_vec()),
+        None,
+    );
+    let dir = program.default_directory();
+    let file = program.add_file(LineString::String(b"main.rs".to_vec()), dir, None);
+    program.begin_sequence(Some(Address::Constant(0)));
+    program.row().file = file;
+    program.row().line = 3;
+    program.row().column = 5;
+    program.row().address_offset = drop_pc as u64;
+    program.generate_row();
+    program.end_sequence(nop_pc as u64);
+    let mut dwarf = DwarfUnit::new(encoding);
+    dwarf.unit.line_program = program;
+    let mut sections = Sections::new(EndianVec::new(gimli::LittleEndian));
+    dwarf.write(&mut sections).unwrap();
+    sections
+        .for_each(|id, data| {
+            if !data.slice().is_empty() {
+                add_custom_section(&mut module, id.name(), data.slice());
+            }
+            Ok::<_, ()>(())
+        })
+        .unwrap();
+
+    let info = AppInfo::new(&module);
+    assert!(info.src_loc(0, 0).is_none());
+    let src = info.src_loc(0, 1).unwrap();
+    assert!(src.file.ends_with("main.rs"));
+    assert_eq!((src.line, src.col), (3, 5));
+    assert!(info.src_loc(0, 2).is_none());
+}
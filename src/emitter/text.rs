@@ -0,0 +1,181 @@
+use crate::emitter::component::is_component;
+use std::borrow::Cow;
+
+/// The format to write the instrumented module in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, clap::ValueEnum)]
+pub enum EmitFormat {
+    /// The binary format
+    #[default]
+    Wasm,
+    /// The text format, with the code that was injected marked by comments
+    Wat,
+}
+impl EmitFormat {
+    /// Where the instrumented module is written to if no output path is passed.
+    pub fn default_output_path(&self) -> &'static str {
+        match self {
+            EmitFormat::Wasm => "./output/output.wasm",
+            EmitFormat::Wat => "./output/output.wat",
+        }
+    }
+}
+
+pub const REGION_START: &str = ";; >>> whamm";
+pub const REGION_END: &str = ";; <<< whamm";
+pub const GENERATED: &str = ";; whamm: generated by the instrumentation";
+
+/// Past this many (original x instrumented) lines in a function, the lines are matched up
+/// greedily instead of computing their longest common subsequence.
+const MAX_DIFF_CELLS: usize = 1 << 22;
+
+/// The application as binary Wasm, it's converted if it's in the text format.
+pub fn app_to_binary(app: &[u8]) -> Result<Cow<[u8]>, String> {
+    wat::parse_bytes(app).map_err(|e| e.to_string())
+}
+
+/// Print the `instrumented` version of the `app` module as WAT. Each run of lines that isn't in
+/// the original function is wrapped in `REGION_START`/`REGION_END` comments, and the functions
+/// that the instrumentation added are headed by a `GENERATED` comment.
+pub fn to_annotated_wat(app: &[u8], instrumented: &[u8]) -> Result<String, String> {
+    if is_component(instrumented) {
+        return Err("only core modules can be printed as WAT, not components".to_string());
+    }
+    let orig = wasm_to_wat(&app_to_binary(app)?)?;
+    let instrumented = wasm_to_wat(instrumented)?;
+
+    let orig_fns: Vec<Vec<&str>> = segments(&orig)
+        .into_iter()
+        .filter_map(|segment| match segment {
+            Segment::Fn(lines) => Some(lines),
+            Segment::Line(_) => None,
+        })
+        .collect();
+
+    let mut wat = format!(
+        ";; instrumented by whamm, the code between `{}` and `{}` was injected\n",
+        REGION_START, REGION_END
+    );
+    let mut fn_idx = 0;
+    for segment in segments(&instrumented) {
+        match segment {
+            Segment::Line(line) => push_line(line, &mut wat),
+            Segment::Fn(lines) => {
+                // The functions the instrumentation adds come after the application's
+                match orig_fns.get(fn_idx) {
+                    Some(orig_lines) => mark_injected(orig_lines, &lines, &mut wat),
+                    None => {
+                        push_line(&format!("  {}", GENERATED), &mut wat);
+                        lines.iter().for_each(|line| push_line(line, &mut wat));
+                    }
+                }
+                fn_idx += 1;
+            }
+        }
+    }
+    Ok(wat)
+}
+
+fn wasm_to_wat(wasm: &[u8]) -> Result<String, String> {
+    wabt::wasm2wat(wasm).map_err(|e| format!("{:?}", e))
+}
+
+enum Segment<'a> {
+    Line(&'a str),
+    /// The lines of a function
+    Fn(Vec<&'a str>),
+}
+
+/// Split the WAT of a module into its functions and the lines in between.
+fn segments(wat: &str) -> Vec<Segment> {
+    let mut segments = vec![];
+    for line in wat.lines() {
+        // The module's fields are indented by two spaces, what's in them by more
+        if line.starts_with("  (func") {
+            segments.push(Segment::Fn(vec![line]));
+            continue;
+        }
+        if line.starts_with("   ") {
+            if let Some(Segment::Fn(lines)) = segments.last_mut() {
+                lines.push(line);
+                continue;
+            }
+        }
+        segments.push(Segment::Line(line));
+    }
+    segments
+}
+
+fn push_line(line: &str, wat: &mut String) {
+    wat.push_str(line);
+    wat.push('\n');
+}
+
+/// Write the `instrumented` lines of a function, marking the ones that aren't in `orig`.
+fn mark_injected(orig: &[&str], instrumented: &[&str], wat: &mut String) {
+    let is_orig = match_orig_lines(orig, instrumented);
+
+    let mut in_region = false;
+    for (line, is_orig) in instrumented.iter().zip(is_orig) {
+        if !is_orig && !in_region {
+            push_line(&format!("{}{}", indent_of(line), REGION_START), wat);
+        } else if is_orig && in_region {
+            push_line(&format!("{}{}", indent_of(line), REGION_END), wat);
+        }
+        in_region = !is_orig;
+        push_line(line, wat);
+    }
+    if in_region {
+        push_line(&format!("    {}", REGION_END), wat);
+    }
+}
+
+fn indent_of(line: &str) -> &str {
+    &line[..line.len() - line.trim_start().len()]
+}
+
+/// Which of the `instrumented` lines are matched with a line in `orig`, using their longest
+/// common subsequence (the injected code is added around the original instructions, and `alt`
+/// probes replace some of them).
+fn match_orig_lines(orig: &[&str], instrumented: &[&str]) -> Vec<bool> {
+    let (n, m) = (orig.len(), instrumented.len());
+    if n.saturating_mul(m) > MAX_DIFF_CELLS {
+        let mut next = 0;
+        return instrumented
+            .iter()
+            .map(|line| {
+                let matched = next < n && orig[next] == *line;
+                if matched {
+                    next += 1;
+                }
+                matched
+            })
+            .collect();
+    }
+
+    // lcs[i][j] is the length of the LCS of orig[i..] and instrumented[j..]
+    let mut lcs = vec![vec![0u32; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i][j] = if orig[i] == instrumented[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut is_orig = vec![false; m];
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if orig[i] == instrumented[j] {
+            is_orig[j] = true;
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    is_orig
+}
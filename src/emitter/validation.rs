@@ -0,0 +1,49 @@
+use wasmparser::{Parser, Payload, TypeRef, Validator, WasmFeatures};
+
+/// Why an instrumented module failed to validate.
+#[derive(Debug)]
+pub struct InvalidModule {
+    pub message: String,
+    /// The byte offset in the module that the validator stopped at
+    pub offset: usize,
+    /// The ID of the function whose body holds `offset`, if it's in the code section
+    pub fn_id: Option<u32>,
+}
+
+/// Validate the (instrumented) `module`, on failure this finds the function that is invalid.
+pub fn validate(module: &[u8]) -> Result<(), InvalidModule> {
+    let e = match Validator::new_with_features(WasmFeatures::all()).validate_all(module) {
+        Ok(_) => return Ok(()),
+        Err(e) => e,
+    };
+    Err(InvalidModule {
+        message: e.message().to_string(),
+        offset: e.offset(),
+        fn_id: fn_at_offset(module, e.offset()),
+    })
+}
+
+/// The ID of the function whose body holds the byte `offset` of the `module`.
+fn fn_at_offset(module: &[u8], offset: usize) -> Option<u32> {
+    let mut num_imported_fns = 0;
+    let mut fn_idx = 0;
+    for payload in Parser::new(0).parse_all(module) {
+        match payload.ok()? {
+            Payload::ImportSection(reader) => {
+                for import in reader {
+                    if let TypeRef::Func(..) = import.ok()?.ty {
+                        num_imported_fns += 1;
+                    }
+                }
+            }
+            Payload::CodeSectionEntry(body) => {
+                if body.range().contains(&offset) {
+                    return Some(num_imported_fns + fn_idx);
+                }
+                fn_idx += 1;
+            }
+            _ => {}
+        }
+    }
+    None
+}
@@ -1,5 +1,7 @@
+pub mod fn_filter;
 pub mod init_generator;
 pub mod instr_generator;
+pub mod report;
 pub mod simple_ast;
 pub mod types;
 
@@ -0,0 +1,102 @@
+use crate::generator::types::ExprFolder;
+use crate::parser::types::{DataType, Expr, Value};
+use crate::verifier::types::SymbolTable;
+use glob::Pattern;
+
+/// Picks the functions of the application to instrument, this is applied before any probe is
+/// matched against the instructions of a function.
+#[derive(Clone, Debug, Default)]
+pub struct FnFilter {
+    /// Only instrument the functions whose name or ID matches one of these (all if empty)
+    include: Vec<Pattern>,
+    /// Never instrument the functions whose name or ID matches one of these
+    exclude: Vec<Pattern>,
+    /// Don't instrument the calls to functions imported from these modules
+    skip_imports_of: Vec<String>,
+}
+impl FnFilter {
+    pub fn new(
+        include: &[String],
+        exclude: &[String],
+        skip_imports_of: &[String],
+    ) -> Result<Self, String> {
+        Ok(Self {
+            include: Self::patterns(include)?,
+            exclude: Self::patterns(exclude)?,
+            skip_imports_of: skip_imports_of.to_vec(),
+        })
+    }
+
+    fn patterns(globs: &[String]) -> Result<Vec<Pattern>, String> {
+        globs
+            .iter()
+            .map(|glob| {
+                Pattern::new(glob)
+                    .map_err(|e| format!("Invalid function filter `{}`: {}", glob, e.msg))
+            })
+            .collect()
+    }
+
+    /// Whether the function with ID `fn_id` named `fn_name` should be instrumented, a pattern
+    /// can match either one (`fn_name` is empty if the function has no name).
+    pub fn includes_fn(&self, fn_id: u32, fn_name: &str) -> bool {
+        let fn_id = fn_id.to_string();
+        let matches = |pattern: &Pattern| {
+            pattern.matches(&fn_id) || (!fn_name.is_empty() && pattern.matches(fn_name))
+        };
+        (self.include.is_empty() || self.include.iter().any(matches))
+            && !self.exclude.iter().any(matches)
+    }
+
+    /// Whether the calls to the functions imported from `module` are skipped.
+    pub fn skips_imports_of(&self, module: &str) -> bool {
+        self.skip_imports_of.iter().any(|skipped| skipped == module)
+    }
+}
+
+/// Whether the `predicate` of a probe can be true anywhere in the function with ID `fn_id`
+/// named `fn_name`. Only `fn_id` and `fn_name` are bound before folding the predicate, so this
+/// is `false` only if it is false at every site in the function.
+pub fn may_hold_in_fn(predicate: &Expr, fn_id: u32, fn_name: &str) -> bool {
+    let mut predicate = predicate.clone();
+    bind_fn_vars(&mut predicate, fn_id, fn_name);
+    let folded = ExprFolder::fold_expr(&predicate, &SymbolTable::new());
+    ExprFolder::get_single_bool(&folded) != Some(false)
+}
+
+fn bind_fn_vars(expr: &mut Expr, fn_id: u32, fn_name: &str) {
+    match expr {
+        Expr::VarId { name, .. } => {
+            let val = match name.as_str() {
+                "fn_id" => Value::Integer {
+                    ty: DataType::I32,
+                    val: fn_id as i32,
+                },
+                "fn_name" => Value::Str {
+                    ty: DataType::Str,
+                    val: fn_name.to_string(),
+                },
+                _ => return,
+            };
+            *expr = Expr::Primitive { val, loc: None };
+        }
+        Expr::UnOp { expr, .. } => bind_fn_vars(expr, fn_id, fn_name),
+        Expr::BinOp { lhs, rhs, .. } => {
+            bind_fn_vars(lhs, fn_id, fn_name);
+            bind_fn_vars(rhs, fn_id, fn_name);
+        }
+        Expr::Ternary {
+            cond, conseq, alt, ..
+        } => {
+            bind_fn_vars(cond, fn_id, fn_name);
+            bind_fn_vars(conseq, fn_id, fn_name);
+            bind_fn_vars(alt, fn_id, fn_name);
+        }
+        Expr::Call { args, .. } => {
+            for arg in args.iter_mut().flatten() {
+                bind_fn_vars(arg, fn_id, fn_name);
+            }
+        }
+        Expr::Primitive { .. } => {}
+    }
+}
@@ -2,12 +2,12 @@
 // ==== CodeGenerator ====
 // =======================
 
-use crate::common::error::ErrorGen;
+use crate::common::error::{ErrorGen, WhammError};
 use crate::emitter::rewriting::module_emitter::ModuleEmitter;
 use crate::parser::rules::{Event, Package, Probe, Provider};
 use crate::parser::types::{
-    BinOp, Block, DataType, Definition, Expr, Fn, Global, ProvidedFunction, Script, Statement,
-    UnOp, Value, Whamm, WhammVisitorMut,
+    Aggregation, BinOp, Block, DataType, Definition, Expr, Fn, Global, ProvidedFunction, Script,
+    Statement, UnOp, Value, Whamm, WhammVisitorMut,
 };
 use log::{trace, warn};
 use std::collections::HashMap;
@@ -23,6 +23,9 @@ pub struct InitGenerator<'a, 'b, 'c, 'd, 'e> {
     pub emitter: ModuleEmitter<'a, 'b, 'c, 'd>,
     pub context_name: String,
     pub err: &'e mut ErrorGen,
+    /// The errors about each script (keyed by script ID), these are taken out of `err` so
+    /// that they can be reported along with the script they point into
+    pub errors: Vec<(String, WhammError)>,
 }
 impl InitGenerator<'_, '_, '_, '_, '_> {
     pub fn run(&mut self, whamm: &mut Whamm) -> bool {
@@ -54,6 +57,18 @@ impl InitGenerator<'_, '_, '_, '_, '_> {
         is_success
     }
 
+    fn visit_aggregations(&mut self, aggregations: &HashMap<String, Aggregation>) -> bool {
+        let mut is_success = true;
+        for agg in aggregations.values() {
+            match self.emitter.emit_aggregation(agg) {
+                Err(e) => self.err.add_error(*e),
+                Ok(res) => is_success &= res,
+            }
+        }
+
+        is_success
+    }
+
     fn visit_stmts(&mut self, stmts: &mut [Statement]) -> bool {
         let mut is_success = true;
         stmts.iter_mut().for_each(|stmt| {
@@ -78,10 +93,18 @@ impl WhammVisitorMut<bool> for InitGenerator<'_, '_, '_, '_, '_> {
         // do not inject globals into Wasm that are used/defined by the compiler
         // because they are statically-defined and folded away
 
-        // visit scripts
+        // visit scripts, each gets its own namespace for what it exports if there are several
+        let namespaced = whamm.scripts.len() > 1;
+        let whamm_errors = self.err.take_errors();
         whamm.scripts.iter_mut().for_each(|script| {
+            self.emitter.namespace = namespaced.then(|| script.name.clone());
             is_success &= self.visit_script(script);
+            for e in self.err.take_errors() {
+                self.errors.push((script.name.clone(), e));
+            }
         });
+        self.emitter.namespace = None;
+        self.err.add_errors(whamm_errors);
 
         trace!("Exiting: CodeGenerator::visit_whamm");
         // Remove from `context_name`
@@ -103,6 +126,8 @@ impl WhammVisitorMut<bool> for InitGenerator<'_, '_, '_, '_, '_> {
         });
         // inject globals
         is_success &= self.visit_globals(&script.globals);
+        // inject the tables backing the aggregations
+        is_success &= self.visit_aggregations(&script.aggregations);
         // visit providers
         script.providers.iter_mut().for_each(|(_name, provider)| {
             is_success &= self.visit_provider(provider);
@@ -333,6 +358,14 @@ impl WhammVisitorMut<bool> for InitGenerator<'_, '_, '_, '_, '_> {
                 is_success &= self.visit_block(conseq);
                 is_success &= self.visit_block(alt);
 
+                is_success
+            }
+            Statement::Aggregate { keys, args, .. } => {
+                let mut is_success = true;
+                keys.iter_mut().chain(args.iter_mut()).for_each(|expr| {
+                    is_success &= self.visit_expr(expr);
+                });
+
                 is_success
             }
         }
@@ -1,10 +1,17 @@
-use crate::common::error::ErrorGen;
+use crate::common::error::{ErrorGen, WhammError, WhammWarning};
 use crate::emitter::rewriting::rules::{provider_factory, Arg, LocInfo, WhammProvider};
 use crate::emitter::rewriting::visiting_emitter::VisitingEmitter;
 use crate::emitter::rewriting::Emitter;
-use crate::generator::simple_ast::SimpleAST;
+use crate::generator::fn_filter::{may_hold_in_fn, FnFilter};
+use crate::generator::report::{Injection, InjectionReport, PredicateKind};
+use crate::generator::simple_ast::{SimpleAST, SimpleProbe};
 use crate::generator::types::ExprFolder;
-use crate::parser::types::{Block, Expr};
+use crate::parser::types::{Block, Expr, Location, ProbeSpec};
+use crate::verifier::verifier::type_check_site;
+use log::debug;
+use pest::error::LineColLocation;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
 
 const UNEXPECTED_ERR_MSG: &str =
     "InstrGenerator: Looks like you've found a bug...please report this behavior!";
@@ -15,6 +22,120 @@ fn get_loc_info<'a>(rule: &'a WhammProvider, emitter: &VisitingEmitter) -> Optio
     emitter.get_loc_info(rule)
 }
 
+/// Identifies a probe definition in a script, the probes in the SimpleAST are copies of these
+/// (one per event/mode that the definition's spec matched).
+type ProbeKey = (String, Option<(usize, usize)>);
+
+fn probe_key(probe: &SimpleProbe) -> ProbeKey {
+    let start = probe.loc.as_ref().map(|loc| match loc.line_col {
+        LineColLocation::Pos(pos) | LineColLocation::Span(pos, _) => pos,
+    });
+    (probe.script_id.clone(), start)
+}
+
+/// How often a probe definition matched a site in the application and was injected there.
+#[derive(Default)]
+struct ProbeStats {
+    /// The (concrete) specs of the probes copied from this definition
+    specs: Vec<String>,
+    loc: Option<Location>,
+    /// The number of sites that matched the probe
+    matched: u32,
+    /// The number of matched sites where the predicate folded to `false`
+    folded_false: u32,
+    /// The number of functions that were skipped since the predicate folded to `false` on
+    /// `fn_id`/`fn_name` alone (see `may_hold_in_fn`)
+    fns_folded_false: u32,
+    /// The number of sites that matched the probe, but were filtered out by the `FnFilter`
+    filtered_out: u32,
+    /// The number of sites the probe was injected at
+    injected: u32,
+}
+
+/// How often each probe definition matched a site in the application and was injected there.
+/// This is carried across the runs of `InstrGenerator` over the core modules of a component, so
+/// that a probe is only reported if it wasn't injected into any of them.
+#[derive(Default)]
+pub struct ProbeTally {
+    stats: BTreeMap<ProbeKey, ProbeStats>,
+}
+impl ProbeTally {
+    /// Warn about the probes that were never injected, and why (keyed by script ID).
+    pub fn warnings(&self) -> Vec<(String, WhammWarning)> {
+        let mut warnings = vec![];
+        for ((script_id, _), stats) in self.stats.iter() {
+            debug!(
+                "{}: matched {} site(s), predicate false at {}, injected at {}",
+                stats.specs.join(", "),
+                stats.matched,
+                stats.folded_false,
+                stats.injected
+            );
+            if stats.injected > 0 {
+                continue;
+            }
+            let message = if stats.matched == 0 && stats.filtered_out > 0 {
+                format!(
+                    "This probe was never injected, all {} matching site(s) are in functions \
+                    (or calls) that were filtered out",
+                    stats.filtered_out
+                )
+            } else if stats.matched == 0 && stats.fns_folded_false > 0 {
+                format!(
+                    "This probe was never injected, its predicate is false in all {} function(s) \
+                    that weren't filtered out",
+                    stats.fns_folded_false
+                )
+            } else if stats.matched == 0 {
+                format!(
+                    "This probe was never injected, no instruction in the application matches `{}`",
+                    stats.specs.join("`, `")
+                )
+            } else if stats.folded_false == stats.matched {
+                format!(
+                    "This probe was never injected, its predicate is false at all {} matching site(s)",
+                    stats.matched
+                )
+            } else {
+                // the probe failed to type check at some site, that's already an error
+                continue;
+            };
+            let loc = stats.loc.as_ref().map(|loc| loc.line_col.clone());
+            warnings.push((script_id.clone(), ErrorGen::get_instr_warn(message, loc)));
+        }
+        warnings
+    }
+}
+
+/// The chain of `alt` probes that is being emitted at the current site. Only one probe can
+/// replace an instruction, so each `alt` probe whose predicate is checked at runtime opens an
+/// `if` whose `else` tries the next probe in the chain. The innermost `else` falls back to the
+/// original instruction.
+#[derive(Default)]
+struct AltChain {
+    /// The number of `if`s that are open
+    num_open: usize,
+    /// The locals that the last probe in the chain saved the instruction's args to
+    arg_locals: Vec<u32>,
+    /// The (script ID, spec) of the probe in the chain that always runs, if there is one.
+    /// The probes after it are never reached.
+    always_runs: Option<(String, String)>,
+}
+
+/// Whether the probes are injected at an instruction.
+#[derive(Clone, Copy, PartialEq)]
+enum Visit {
+    Inject,
+    /// The `FnFilter` filters the instruction out, the probes that match it are only counted
+    FilteredOut,
+    /// No probe can be injected (see `visit_fn`), the instruction isn't matched at all
+    Skip,
+}
+
+fn is_alt(probe_spec: &ProbeSpec) -> bool {
+    matches!(&probe_spec.mode, Some(mode) if mode.name == "alt")
+}
+
 /// The second phase of instrumenting a Wasm module by actually emitting the
 /// instrumentation code.
 ///
@@ -31,6 +152,24 @@ pub struct InstrGenerator<'a, 'b, 'c, 'd, 'e> {
     curr_probe_mode: String,
     /// The current probe's body and predicate
     curr_probe: Option<(Option<Block>, Option<Expr>)>,
+    /// How often each probe was matched and injected, see `ProbeTally::warnings`
+    pub tally: ProbeTally,
+    alt_chain: AltChain,
+    /// Where each probe was injected
+    pub report: InjectionReport,
+    /// The warnings about the probes of each script (keyed by script ID), these are kept
+    /// apart from `err` since they point into the script they're about
+    pub warnings: Vec<(String, WhammWarning)>,
+    /// The errors about the probes of each script (keyed by script ID), these are taken out of
+    /// `err` for the same reason
+    pub errors: Vec<(String, WhammError)>,
+    /// Which functions (and calls) to instrument
+    pub fn_filter: FnFilter,
+    /// The function being visited, and whether its instructions are visited
+    curr_fn: Option<(u32, Visit)>,
+    /// The ID of the function that reports the aggregations, it is called when the application
+    /// exits (see `VisitingEmitter::at_app_exit`)
+    pub agg_report: Option<u32>,
 }
 impl<'a, 'b, 'c, 'd, 'e> InstrGenerator<'a, 'b, 'c, 'd, 'e> {
     pub fn new(
@@ -45,9 +184,75 @@ impl<'a, 'b, 'c, 'd, 'e> InstrGenerator<'a, 'b, 'c, 'd, 'e> {
             curr_instr_args: vec![],
             curr_probe_mode: "".to_string(),
             curr_probe: None,
+            tally: ProbeTally::default(),
+            alt_chain: AltChain::default(),
+            report: InjectionReport::default(),
+            warnings: vec![],
+            errors: vec![],
+            fn_filter: FnFilter::default(),
+            curr_fn: None,
+            agg_report: None,
         }
     }
 
+    /// Whether the probes are injected at the current instruction, they aren't if the function
+    /// it is in isn't visited (see `visit_fn`) or if it calls an import that the `fn_filter`
+    /// skips.
+    fn visit_curr_instr(&mut self) -> Visit {
+        let fn_id = self.emitter.curr_fn_id();
+        let visit = match self.curr_fn {
+            Some((curr_fn_id, visit)) if curr_fn_id == fn_id => visit,
+            _ => {
+                let fn_name = self.emitter.fn_name(fn_id);
+                let visit = self.visit_fn(fn_id, &fn_name);
+                self.curr_fn = Some((fn_id, visit));
+                visit
+            }
+        };
+        if visit == Visit::Inject
+            && self
+                .emitter
+                .curr_call_import_module()
+                .is_some_and(|module| self.fn_filter.skips_imports_of(&module))
+        {
+            return Visit::FilteredOut;
+        }
+        visit
+    }
+
+    /// Whether the function's instructions are visited: the `fn_filter` may exclude it, and
+    /// none of them need to be if the predicate of every probe is false on `fn_id`/`fn_name`
+    /// alone.
+    fn visit_fn(&mut self, fn_id: u32, fn_name: &str) -> Visit {
+        if !self.fn_filter.includes_fn(fn_id, fn_name) {
+            return Visit::FilteredOut;
+        }
+        let mut folded_false = vec![];
+        for probe in self.ast.probes.values().flat_map(|packages| {
+            packages
+                .values()
+                .flat_map(|events| events.values())
+                .flat_map(|modes| modes.values())
+                .flatten()
+        }) {
+            match &probe.predicate {
+                Some(predicate) if !may_hold_in_fn(predicate, fn_id, fn_name) => {
+                    folded_false.push(probe_key(probe));
+                }
+                _ => return Visit::Inject,
+            }
+        }
+        // a probe is copied for each event its spec matches
+        folded_false.sort();
+        folded_false.dedup();
+        for key in folded_false {
+            if let Some(stats) = self.tally.stats.get_mut(&key) {
+                stats.fns_folded_false += 1;
+            }
+        }
+        Visit::Skip
+    }
+
     pub fn configure_probe_mode(&mut self) -> bool {
         // TODO -- make the probe mode an enum!
         match self.curr_probe_mode.as_str() {
@@ -73,6 +278,8 @@ impl<'a, 'b, 'c, 'd, 'e> InstrGenerator<'a, 'b, 'c, 'd, 'e> {
         //       3. create a new clone of the probe, fold the predicate
         //       4. traverse the behavior tree to emit code! (if predicate is not false)
 
+        self.init_probe_stats();
+
         // Initialize the emitter rules
         let rules = provider_factory::<WhammProvider>(&self.ast.probes);
 
@@ -81,69 +288,239 @@ impl<'a, 'b, 'c, 'd, 'e> InstrGenerator<'a, 'b, 'c, 'd, 'e> {
         let mut first_instr = true;
         while first_instr || self.emitter.next_instr() {
             first_instr = false;
-            rules.iter().for_each(|rule| {
-                // Check if any of the configured rules match this instruction in the application.
-                if let Some(loc_info) = get_loc_info(rule, &self.emitter) {
-                    if loc_info.num_alt_probes > 1 {
-                        self.err
-                            .multiple_alt_matches(self.emitter.curr_instr_name().as_str());
-                    }
-                    // This location has matched some rules, inject each matched probe!
-                    loc_info.probes.iter().for_each(|(probe_spec, probe)| {
-                        // Enter the scope for this matched probe
-                        is_success = self
-                            .emitter
-                            .enter_scope_via_spec(&probe.script_id, probe_spec);
-
-                        // Initialize the symbol table with the metadata at this program point
-                        loc_info.static_data.iter().for_each(
-                            |(static_var_name, static_var_val)| {
-                                if let Err(e) = self.emitter.define(static_var_name, static_var_val)
-                                {
-                                    self.err.add_error(*e);
-                                }
-                            },
-                        );
-
-                        // Create a new clone of the probe, fold the predicate.
-                        // NOTE: We make a clone so that the probe is reset for each instruction!
-                        let (body_clone, mut pred_clone) =
-                            (probe.body.clone(), probe.predicate.clone());
-                        if let Some(pred) = &mut pred_clone {
-                            // Fold predicate
-                            is_success = self.emitter.fold_expr(pred);
-
-                            // If the predicate evaluates to false, short-circuit!
-                            if let Some(pred_as_bool) = ExprFolder::get_single_bool(pred) {
-                                if !pred_as_bool {
-                                    // predicate is reduced to false, short-circuit!
-                                    return;
-                                }
+            let visit = self.visit_curr_instr();
+            if visit != Visit::Skip {
+                rules.iter().for_each(|rule| {
+                    // Check if any of the configured rules match this instruction in the
+                    // application.
+                    if let Some(loc_info) = get_loc_info(rule, &self.emitter) {
+                        if visit == Visit::FilteredOut {
+                            // Only count the matches, to tell why a probe was never injected
+                            for (_, probe) in loc_info.probes.iter() {
+                                let key = probe_key(probe);
+                                self.tally.stats.entry(key).or_default().filtered_out += 1;
                             }
+                            return;
                         }
+                        // The `alt` probes go last, chained in order of priority (see
+                        // `AltChain`). The sort is stable, so probes with the same priority keep
+                        // the order of the scripts (and of the probes in each script).
+                        let mut probes: Vec<&(ProbeSpec, &SimpleProbe)> =
+                            loc_info.probes.iter().collect();
+                        probes.sort_by_key(|(probe_spec, probe)| {
+                            if is_alt(probe_spec) {
+                                (true, Reverse(probe.priority))
+                            } else {
+                                (false, Reverse(0))
+                            }
+                        });
+
+                        // This location has matched some rules, inject each matched probe!
+                        probes.into_iter().for_each(|(probe_spec, probe)| {
+                            is_success &= self.inject_probe(probe_spec, probe, &loc_info);
+                            for e in self.err.take_errors() {
+                                self.errors.push((probe.script_id.clone(), e));
+                            }
+                        });
+                        self.close_alt_chain();
+                    }
+                });
+            }
+            // The results of the aggregations are reported when the application exits, after
+            // the probes at the exit (which may update them) have run.
+            if let Some(agg_report) = self.agg_report {
+                if self.emitter.at_app_exit() {
+                    self.emitter.call_before(agg_report);
+                }
+            }
+        }
 
-                        self.curr_instr_args = loc_info.args.clone(); // must clone so that this lives long enough
-                        self.curr_probe_mode = probe_spec.mode.as_ref().unwrap().name.clone();
-                        self.curr_probe = Some((body_clone, pred_clone));
+        is_success
+    }
+}
+impl InstrGenerator<'_, '_, '_, '_, '_> {
+    /// Inject the `probe` (copied from a definition with the concrete `probe_spec`) at the
+    /// current instruction, which it matched with the passed `loc_info`.
+    fn inject_probe(
+        &mut self,
+        probe_spec: &ProbeSpec,
+        probe: &SimpleProbe,
+        loc_info: &LocInfo,
+    ) -> bool {
+        let stats = self.tally.stats.entry(probe_key(probe)).or_default();
+        stats.matched += 1;
 
-                        // emit the probe (since the predicate is not false)
-                        is_success &= self.emit_probe();
+        // Enter the scope for this matched probe
+        let mut is_success = self
+            .emitter
+            .enter_scope_via_spec(&probe.script_id, probe_spec);
 
-                        // Now that we've emitted this probe, reset the symbol table's static/dynamic
-                        // data defined for this instr
-                        self.emitter.reset_table_data(&loc_info);
-                    });
+        // Initialize the symbol table with the metadata at this program point
+        loc_info
+            .static_data
+            .iter()
+            .for_each(|(static_var_name, static_var_val)| {
+                if let Err(e) = self.emitter.define(static_var_name, static_var_val) {
+                    self.err.add_error(*e);
                 }
             });
+
+        let probe_mode = probe_spec.mode.as_ref().unwrap().name.clone();
+
+        // Create a new clone of the probe, fold the predicate.
+        // NOTE: We make a clone so that the probe is reset for each instruction!
+        let (mut body_clone, mut pred_clone) = (probe.body.clone(), probe.predicate.clone());
+
+        // The types of the site variables depend on this program point,
+        // re-check the probe now that we know them.
+        if !probe.site_vars.is_empty() {
+            self.emitter
+                .define_site_vars(&probe.site_vars, &loc_info.args, probe_mode == "after");
+            let site = self.emitter.curr_site();
+            if !type_check_site(
+                &mut pred_clone,
+                &mut body_clone,
+                &site,
+                self.emitter.table,
+                self.err,
+            ) {
+                self.emitter.reset_table_data(loc_info);
+                return false;
+            }
+        }
+
+        if let Some(pred) = &mut pred_clone {
+            // Fold predicate
+            is_success &= self.emitter.fold_expr(pred);
+
+            // If the predicate evaluates to false, short-circuit!
+            if let Some(pred_as_bool) = ExprFolder::get_single_bool(pred) {
+                if !pred_as_bool {
+                    // predicate is reduced to false, short-circuit!
+                    if let Some(stats) = self.tally.stats.get_mut(&probe_key(probe)) {
+                        stats.folded_false += 1;
+                    }
+                    self.emitter.reset_table_data(loc_info);
+                    return is_success;
+                }
+            }
+        }
+
+        // An `alt` probe after one that always runs is never reached
+        if probe_mode == "alt" {
+            if let Some((script_id, spec)) = &self.alt_chain.always_runs {
+                let message = format!(
+                    "This `alt` probe is never reached at {}, the `alt` probe `{}` \
+                    of {} comes before it (see `@priority`) and always runs",
+                    self.emitter.curr_site(),
+                    spec,
+                    script_id
+                );
+                let loc = probe.loc.as_ref().map(|loc| loc.line_col.clone());
+                self.warnings.push((
+                    probe.script_id.clone(),
+                    ErrorGen::get_instr_warn(message, loc),
+                ));
+                self.emitter.reset_table_data(loc_info);
+                return is_success;
+            }
+        }
+
+        // `this.x`/`frame.x` live in locals of the function being instrumented
+        if !probe.scoped_vars.is_empty() {
+            self.emitter.bind_scoped_vars(&probe.scoped_vars);
+        }
+
+        // Record where the probe is injected
+        if let Some(stats) = self.tally.stats.get_mut(&probe_key(probe)) {
+            stats.injected += 1;
         }
+        let predicate = match &pred_clone {
+            None => PredicateKind::None,
+            Some(pred) => match ExprFolder::get_single_bool(pred) {
+                Some(true) => PredicateKind::FoldedTrue,
+                _ => PredicateKind::RuntimeCheck,
+            },
+        };
+        self.report.add(Injection {
+            script_id: probe.script_id.clone(),
+            probe_spec: probe_spec.full_name(),
+            site: self.emitter.curr_instr_loc(),
+            mode: probe_mode.clone(),
+            predicate,
+            loc: probe.loc.clone(),
+        });
+
+        self.curr_instr_args = loc_info.args.clone(); // must clone so that this lives long enough
+        self.curr_probe_mode = probe_mode;
+        self.curr_probe = Some((body_clone, pred_clone));
+
+        // emit the probe (since the predicate is not false)
+        is_success &= self.emit_probe();
+        if self.curr_probe_mode == "alt" {
+            self.alt_chain.arg_locals = self.emitter.arg_locals();
+            if predicate != PredicateKind::RuntimeCheck {
+                self.alt_chain.always_runs =
+                    Some((probe.script_id.clone(), probe_spec.full_name()));
+            }
+        }
+
+        // Now that we've emitted this probe, reset the symbol table's static/dynamic
+        // data defined for this instr
+        self.emitter.reset_table_data(loc_info);
 
         is_success
     }
-}
-impl InstrGenerator<'_, '_, '_, '_, '_> {
+
+    fn init_probe_stats(&mut self) {
+        if !self.tally.stats.is_empty() {
+            // this is a later core module of a component, the probes are already tallied
+            return;
+        }
+        for (provider, packages) in self.ast.probes.iter() {
+            for (package, events) in packages.iter() {
+                for (event, modes) in events.iter() {
+                    for (mode, probes) in modes.iter() {
+                        for probe in probes.iter() {
+                            let stats = self.tally.stats.entry(probe_key(probe)).or_default();
+                            stats
+                                .specs
+                                .push(format!("{provider}:{package}:{event}:{mode}"));
+                            stats.loc = probe.loc.clone();
+                        }
+                    }
+                }
+            }
+        }
+        self.tally
+            .stats
+            .values_mut()
+            .for_each(|stats| stats.specs.sort());
+    }
+
+    /// Close the `if`s of the chain of `alt` probes at the current site, if there are any.
+    fn close_alt_chain(&mut self) {
+        let chain = std::mem::take(&mut self.alt_chain);
+        if chain.num_open > 0 {
+            self.emitter.alternate();
+            let fallback_args = match chain.always_runs {
+                Some(_) => None,
+                None => Some(chain.arg_locals.as_slice()),
+            };
+            self.emitter.close_alt_chain(chain.num_open, fallback_args);
+        }
+    }
+
     fn emit_probe(&mut self) -> bool {
         let mut is_success = true;
 
+        if self.curr_probe_mode == "alt" && self.alt_chain.num_open > 0 {
+            // The previous probe in the chain took the args off the stack, put them back
+            self.emitter.before();
+            self.emitter.emit_locals(&self.alt_chain.arg_locals);
+        }
+
+        is_success &= self.save_tos();
         is_success &= self.save_args();
 
         self.configure_probe_mode();
@@ -162,7 +539,8 @@ impl InstrGenerator<'_, '_, '_, '_, '_> {
                     self.replace_args();
                 }
                 "alt" => {
-                    is_success &= self.emit_probe_as_if_else();
+                    is_success &= self.emit_alt_if();
+                    self.alt_chain.num_open += 1;
                 }
                 _ => {
                     self.err.unexpected_error(
@@ -181,6 +559,16 @@ impl InstrGenerator<'_, '_, '_, '_, '_> {
         is_success
     }
 
+    fn save_tos(&mut self) -> bool {
+        // `tos` is the operand of the instruction, or its result for `after` probes
+        if self.curr_probe_mode == "after" {
+            self.emitter.after();
+        } else {
+            self.emitter.before();
+        }
+        self.emitter.save_tos()
+    }
+
     fn save_args(&mut self) -> bool {
         if !self.curr_instr_args.is_empty() {
             // The current instruction has args, save them (before)
@@ -245,9 +633,9 @@ impl InstrGenerator<'_, '_, '_, '_, '_> {
         }
     }
 
-    fn emit_probe_as_if_else(&mut self) -> bool {
+    fn emit_alt_if(&mut self) -> bool {
         if let Some((Some(ref mut body), Some(ref mut pred))) = self.curr_probe {
-            match self.emitter.emit_if_with_orig_as_else(pred, body) {
+            match self.emitter.emit_alt_if(pred, body) {
                 Err(e) => {
                     self.err.add_error(*e);
                     false
@@ -0,0 +1,277 @@
+use crate::common::terminal::{green, grey_italics, magenta, white};
+use crate::emitter::rewriting::rules::InstrLoc;
+use crate::parser::types::{Expr, Location, Value as WhammValue};
+use serde_json::{json, Value};
+use termcolor::{Buffer, BufferWriter, ColorChoice};
+
+/// How the report of where probes were injected is written out.
+#[derive(Clone, Copy, Debug, Default, PartialEq, clap::ValueEnum)]
+pub enum ReportFormat {
+    /// A human-readable table
+    #[default]
+    Table,
+    /// A JSON object listing each injection
+    Json,
+}
+
+/// How the predicate of an injected probe ended up being emitted.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PredicateKind {
+    /// The probe has no predicate
+    None,
+    /// The predicate folded to `true` at this site, the body is emitted unconditionally
+    FoldedTrue,
+    /// The predicate is checked at runtime
+    RuntimeCheck,
+}
+impl PredicateKind {
+    pub fn name(&self) -> &str {
+        match self {
+            PredicateKind::None => "none",
+            PredicateKind::FoldedTrue => "folded_true",
+            PredicateKind::RuntimeCheck => "runtime_check",
+        }
+    }
+}
+
+/// A probe that was injected at some site in the application.
+#[derive(Clone, Debug)]
+pub struct Injection {
+    pub script_id: String,
+    /// The (concrete) spec of the injected probe
+    pub probe_spec: String,
+    pub site: InstrLoc,
+    pub mode: String,
+    pub predicate: PredicateKind,
+    /// Where the probe is in its script
+    pub loc: Option<Location>,
+}
+
+/// Every probe injection that `InstrGenerator` made, in the order they were made.
+#[derive(Debug, Default)]
+pub struct InjectionReport {
+    pub injections: Vec<Injection>,
+}
+impl InjectionReport {
+    pub fn add(&mut self, injection: Injection) {
+        self.injections.push(injection);
+    }
+
+    pub fn render(&self, format: ReportFormat) -> String {
+        match format {
+            ReportFormat::Table => self.to_table(),
+            ReportFormat::Json => format!("{:#}", self.to_json()),
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        let injections: Vec<Value> = self
+            .injections
+            .iter()
+            .map(|injection| {
+                json!({
+                    "script_id": injection.script_id,
+                    "probe_spec": injection.probe_spec,
+                    "core_module": injection.site.core_module,
+                    "fn_id": injection.site.fn_id,
+                    "fn_name": injection.site.fn_name,
+                    "instr_idx": injection.site.instr_idx,
+                    "pc": injection.site.pc,
+                    "mode": injection.mode,
+                    "predicate": injection.predicate.name(),
+                })
+            })
+            .collect();
+        json!({ "injections": injections })
+    }
+
+    /// One row per injection, with the columns padded to line up. The core module of each site
+    /// is only listed if the application is a component.
+    pub fn to_table(&self) -> String {
+        let in_component = self
+            .injections
+            .iter()
+            .any(|injection| injection.site.core_module.is_some());
+        let mut header = [
+            "script",
+            "probe",
+            "fn_id",
+            "fn_name",
+            "instr_idx",
+            "pc",
+            "mode",
+            "predicate",
+        ]
+        .map(String::from)
+        .to_vec();
+        if in_component {
+            header.insert(2, "core_module".to_string());
+        }
+        let mut rows = vec![header];
+        for injection in self.injections.iter() {
+            let mut row = vec![
+                injection.script_id.clone(),
+                injection.probe_spec.clone(),
+                injection.site.fn_id.to_string(),
+                if injection.site.fn_name.is_empty() {
+                    "-".to_string()
+                } else {
+                    injection.site.fn_name.clone()
+                },
+                injection.site.instr_idx.to_string(),
+                match injection.site.pc {
+                    Some(pc) => format!("{:#x}", pc),
+                    None => "-".to_string(),
+                },
+                injection.mode.clone(),
+                injection.predicate.name().to_string(),
+            ];
+            if in_component {
+                row.insert(
+                    2,
+                    match injection.site.core_module {
+                        Some(core_module) => core_module.to_string(),
+                        None => "-".to_string(),
+                    },
+                );
+            }
+            rows.push(row);
+        }
+
+        let mut widths = vec![0; rows[0].len()];
+        for row in rows.iter() {
+            for (width, cell) in widths.iter_mut().zip(row.iter()) {
+                *width = (*width).max(cell.len());
+            }
+        }
+        let mut table = String::new();
+        for row in rows.iter() {
+            let cells: Vec<String> = row
+                .iter()
+                .zip(widths.iter())
+                .map(|(cell, width)| format!("{:width$}", cell, width = width))
+                .collect();
+            table.push_str(cells.join("  ").trim_end());
+            table.push('\n');
+        }
+        table.push_str(&format!("{} injection(s)\n", self.injections.len()));
+        table
+    }
+}
+
+/// A site in the application that a probe specification matches, see `whamm info --app`.
+#[derive(Clone, Debug)]
+pub struct MatchedSite {
+    pub site: InstrLoc,
+    /// The instruction at the site
+    pub instr: String,
+    /// The (concrete) specs that match the site
+    pub specs: Vec<String>,
+    /// The compiler-provided globals that would be bound at the site, with their values
+    pub static_data: Vec<(String, Option<WhammValue>)>,
+}
+
+pub fn print_matches(sites: &[MatchedSite]) {
+    let writer = BufferWriter::stderr(ColorChoice::Always);
+    let mut buffer = writer.buffer();
+    write_matches(sites, &mut buffer);
+
+    writer
+        .print(&buffer)
+        .expect("Uh oh, something went wrong while printing to terminal");
+}
+
+/// Write the sites that matched a probe specification to `buffer`, this is what
+/// `whamm info --app` prints after the information for the specification.
+pub fn write_matches(sites: &[MatchedSite], buffer: &mut Buffer) {
+    white(
+        true,
+        format!("\nMATCHED {} SITE(S) IN THE APPLICATION\n\n", sites.len()),
+        buffer,
+    );
+    for site in sites.iter() {
+        magenta(true, site.instr.clone(), buffer);
+        white(false, format!(" at {}\n", site.site), buffer);
+        for spec in site.specs.iter() {
+            grey_italics(false, format!("    {}\n", spec), buffer);
+        }
+        for (name, val) in site.static_data.iter() {
+            green(true, format!("    {}", name), buffer);
+            white(false, format!(" = {}\n", value_str(val)), buffer);
+        }
+    }
+}
+
+/// The sites that matched a probe specification as JSON, this is what `whamm info --app`
+/// adds to the export as `matched_sites` with `--format json`.
+pub fn matches_to_json(sites: &[MatchedSite]) -> Value {
+    let sites: Vec<Value> = sites
+        .iter()
+        .map(|site| {
+            let static_data: serde_json::Map<String, Value> = site
+                .static_data
+                .iter()
+                .map(|(name, val)| (name.clone(), value_json(val)))
+                .collect();
+            json!({
+                "core_module": site.site.core_module,
+                "fn_id": site.site.fn_id,
+                "fn_name": site.site.fn_name,
+                "instr_idx": site.site.instr_idx,
+                "pc": site.site.pc,
+                "instr": site.instr,
+                "specs": site.specs,
+                "static_data": static_data,
+            })
+        })
+        .collect();
+    Value::Array(sites)
+}
+
+/// The sites that matched a probe specification as a Markdown section, this is what
+/// `whamm info --app` adds to the export with `--format markdown`.
+pub fn matches_markdown(sites: &[MatchedSite]) -> String {
+    let mut md = format!("# Matched {} site(s) in the application #\n\n", sites.len());
+    for site in sites.iter() {
+        md.push_str(&format!("## `{}` at {} ##\n\n", site.instr, site.site));
+        for spec in site.specs.iter() {
+            md.push_str(&format!("- `{}`\n", spec));
+        }
+        if !site.static_data.is_empty() {
+            md.push_str("\n| global | value |\n|---|---|\n");
+            for (name, val) in site.static_data.iter() {
+                md.push_str(&format!("| `{}` | `{}` |\n", name, value_str(val)));
+            }
+        }
+        md.push('\n');
+    }
+    md
+}
+
+/// `null` if the value isn't known statically.
+fn value_json(val: &Option<WhammValue>) -> Value {
+    match val {
+        Some(WhammValue::Integer { val, .. }) => json!(val),
+        Some(WhammValue::Str { val, .. }) => json!(val),
+        Some(WhammValue::Boolean { val, .. }) => json!(val),
+        Some(WhammValue::Tuple { vals, .. }) => Value::Array(
+            vals.iter()
+                .map(|val| match val {
+                    Expr::Primitive { val, .. } => value_json(&Some(val.clone())),
+                    _ => Value::Null,
+                })
+                .collect(),
+        ),
+        None => Value::Null,
+    }
+}
+
+fn value_str(val: &Option<WhammValue>) -> String {
+    match val {
+        Some(WhammValue::Integer { val, .. }) => val.to_string(),
+        Some(WhammValue::Str { val, .. }) => format!("\"{}\"", val),
+        Some(WhammValue::Boolean { val, .. }) => val.to_string(),
+        Some(WhammValue::Tuple { vals, .. }) => format!("({} values)", vals.len()),
+        None => "(not known statically)".to_string(),
+    }
+}
@@ -4,7 +4,8 @@ use parser_types::{Expr, Fn, Script, Statement, Whamm, WhammVisitor};
 use std::collections::HashMap;
 
 use crate::common::error::ErrorGen;
-use crate::parser::types::{BinOp, Block, DataType, UnOp, Value};
+use crate::parser::types::{BinOp, Block, DataType, Location, UnOp, Value};
+use crate::verifier::verifier::{is_scoped_var, is_site_var};
 use log::trace;
 
 /// This is a structure that saves a simplified variation of the activated
@@ -62,19 +63,108 @@ pub type SimpleAstProbes =
 #[derive(Clone, Debug)]
 pub struct SimpleProbe {
     pub script_id: String,
+    /// Where the probe's mode is in the script, this identifies the probe definition that
+    /// this was copied from (a spec with wildcards is copied into each match)
+    pub loc: Option<Location>,
     pub predicate: Option<Expr>,
     pub body: Option<Block>,
+    /// The order of the probe in a chain of `alt` probes, higher goes first
+    pub priority: i32,
+    /// The variables used by this probe that are bound per-site (see `is_site_var`)
+    pub site_vars: Vec<String>,
+    /// The `this.x`/`frame.x` variables used by this probe (see `is_scoped_var`)
+    pub scoped_vars: Vec<String>,
 }
 impl SimpleProbe {
     fn new(script_id: String, probe: &dyn Probe) -> Self {
         Self {
             script_id,
+            loc: probe.loc().to_owned(),
             predicate: probe.predicate().to_owned(),
             body: probe.body().to_owned(),
+            priority: probe.priority(),
+            site_vars: collect_vars(probe, is_site_var),
+            scoped_vars: collect_vars(probe, is_scoped_var),
         }
     }
 }
 
+/// Collect the names of the variables used by the probe that `keep` matches
+fn collect_vars(probe: &dyn Probe, keep: fn(&str) -> bool) -> Vec<String> {
+    let mut vars = vec![];
+    if let Some(pred) = probe.predicate() {
+        collect_vars_in_expr(pred, keep, &mut vars);
+    }
+    if let Some(body) = probe.body() {
+        collect_vars_in_block(body, keep, &mut vars);
+    }
+    vars
+}
+
+fn collect_vars_in_block(block: &Block, keep: fn(&str) -> bool, vars: &mut Vec<String>) {
+    for stmt in block.stmts.iter() {
+        match stmt {
+            Statement::Decl { .. } => {}
+            Statement::Assign { var_id, expr, .. } => {
+                collect_vars_in_expr(var_id, keep, vars);
+                collect_vars_in_expr(expr, keep, vars);
+            }
+            Statement::Expr { expr, .. } | Statement::Return { expr, .. } => {
+                collect_vars_in_expr(expr, keep, vars);
+            }
+            Statement::If {
+                cond, conseq, alt, ..
+            } => {
+                collect_vars_in_expr(cond, keep, vars);
+                collect_vars_in_block(conseq, keep, vars);
+                collect_vars_in_block(alt, keep, vars);
+            }
+            Statement::Aggregate { keys, args, .. } => {
+                keys.iter()
+                    .chain(args.iter())
+                    .for_each(|expr| collect_vars_in_expr(expr, keep, vars));
+            }
+        }
+    }
+}
+
+fn collect_vars_in_expr(expr: &Expr, keep: fn(&str) -> bool, vars: &mut Vec<String>) {
+    match expr {
+        Expr::VarId { name, .. } => {
+            if keep(name) && !vars.contains(name) {
+                vars.push(name.clone());
+            }
+        }
+        Expr::UnOp { expr, .. } => collect_vars_in_expr(expr, keep, vars),
+        Expr::BinOp { lhs, rhs, .. } => {
+            collect_vars_in_expr(lhs, keep, vars);
+            collect_vars_in_expr(rhs, keep, vars);
+        }
+        Expr::Ternary {
+            cond, conseq, alt, ..
+        } => {
+            collect_vars_in_expr(cond, keep, vars);
+            collect_vars_in_expr(conseq, keep, vars);
+            collect_vars_in_expr(alt, keep, vars);
+        }
+        Expr::Call { args, .. } => {
+            for arg in args.iter().flatten() {
+                collect_vars_in_expr(arg, keep, vars);
+            }
+        }
+        Expr::Primitive {
+            val: Value::Tuple { vals, .. },
+            ..
+        } => {
+            for val in vals.iter() {
+                collect_vars_in_expr(val, keep, vars);
+            }
+        }
+        Expr::Primitive { .. } => {}
+    }
+}
+
+#[derive(Clone)]
 pub struct SimpleAST {
     pub global_stmts: Vec<Statement>,
     /// This points to probes defined in the `Whamm` AST node!
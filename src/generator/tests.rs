@@ -3,6 +3,7 @@
 // =================
 
 use crate::common::error::ErrorGen;
+use crate::generator::fn_filter::{may_hold_in_fn, FnFilter};
 use crate::generator::types::ExprFolder;
 use crate::parser::tests;
 use crate::parser::types::Expr::{BinOp as ExprBinOp, VarId};
@@ -277,3 +278,40 @@ wasm::call:alt /
         panic!();
     }
 }
+
+#[test]
+pub fn fn_filter_by_name_or_id() {
+    setup_logger();
+    let filter = FnFilter::new(
+        &["app_*".to_string(), "7".to_string()],
+        &["app_alloc".to_string()],
+        &["wasi_snapshot_preview1".to_string()],
+    )
+    .unwrap();
+    assert!(filter.includes_fn(3, "app_main"));
+    assert!(filter.includes_fn(7, ""));
+    assert!(!filter.includes_fn(4, "app_alloc"));
+    assert!(!filter.includes_fn(5, "malloc"));
+    assert!(filter.skips_imports_of("wasi_snapshot_preview1"));
+    assert!(!filter.skips_imports_of("env"));
+
+    // With no patterns, every function is instrumented
+    assert!(FnFilter::default().includes_fn(5, "malloc"));
+    assert!(FnFilter::new(&["[".to_string()], &[], &[]).is_err());
+}
+
+#[test]
+pub fn fold_predicate_per_fn() {
+    setup_logger();
+    let script = r#"
+wasm::call:alt / fn_name == "app_main" && target_imp_name == "log" / {}
+    "#;
+    let mut err = ErrorGen::new("".to_string(), "".to_string(), 0);
+    let whamm = tests::get_ast(script, &mut err);
+    let pred = get_pred(&whamm);
+
+    // Whether the rest of the predicate holds is only known at each site
+    assert!(may_hold_in_fn(pred, 3, "app_main"));
+    assert!(!may_hold_in_fn(pred, 4, "malloc"));
+    assert!(!may_hold_in_fn(pred, 5, ""));
+}
@@ -1,5 +1,712 @@
 pub mod common;
 pub mod emitter;
 pub mod generator;
+pub mod lsp;
 pub mod parser;
 pub mod verifier;
+
+use crate::common::error::{Diagnostics, ErrorGen, WhammError, WhammWarning};
+use crate::emitter::component::{self, ComponentError};
+use crate::emitter::custom_sections::{self, InjectedNames};
+use crate::emitter::rewriting::app_info::{fn_exports, AppInfo};
+use crate::emitter::rewriting::module_emitter::{MemoryTracker, ModuleEmitter, WASI_MODULE};
+use crate::emitter::rewriting::rules::{provider_factory, InstrLoc, WhammProvider};
+use crate::emitter::rewriting::visiting_emitter::{InComponent, VisitingEmitter};
+use crate::emitter::text;
+use crate::emitter::validation::{self, InvalidModule};
+use crate::generator::fn_filter::FnFilter;
+use crate::generator::init_generator::InitGenerator;
+use crate::generator::instr_generator::{InstrGenerator, ProbeTally};
+use crate::generator::report::{Injection, InjectionReport, MatchedSite};
+use crate::generator::simple_ast::{build_simple_ast, SimpleAST};
+use crate::parser::types::Whamm;
+use crate::parser::whamm_parser::parse_script;
+use crate::verifier::lints::lint;
+use crate::verifier::types::SymbolTable;
+use crate::verifier::verifier::{build_symbol_table, type_check};
+use log::warn;
+use orca::ir::module::Module as WasmModule;
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+/// A whamm script to instrument an application with.
+pub struct ScriptSource {
+    /// Where the script came from, this is only used when reporting diagnostics
+    pub path: String,
+    pub text: String,
+}
+impl ScriptSource {
+    pub fn new(path: String, text: String) -> Self {
+        Self { path, text }
+    }
+
+    pub fn from_file(path: &str) -> std::io::Result<Self> {
+        Ok(Self {
+            path: path.to_string(),
+            text: std::fs::read_to_string(path)?,
+        })
+    }
+}
+
+/// Configures a run of `instrument`.
+pub struct Options {
+    /// Stop processing the script once this many errors have been collected
+    pub max_errors: i32,
+    /// If the application is a component, only instrument the core module at this index
+    /// (in the order they appear in the binary). Otherwise, every core module is instrumented.
+    pub core_module: Option<u32>,
+    /// Which functions of the application to instrument, by default every one is
+    pub fn_filter: FnFilter,
+}
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            max_errors: 15,
+            core_module: None,
+            fn_filter: FnFilter::default(),
+        }
+    }
+}
+
+/// The result of a successful run of `instrument`.
+pub struct Instrumented {
+    /// The encoded, instrumented Wasm module (or component)
+    pub wasm: Vec<u8>,
+    /// The warnings collected along the way (e.g. probes that were never injected), one
+    /// `Diagnostics` per script in the order they were passed. Any warnings that aren't about a
+    /// single script are in an extra one at the end.
+    pub diagnostics: Vec<Diagnostics>,
+    /// Where each probe was injected
+    pub report: InjectionReport,
+}
+
+/// Instrument the `app` Wasm module (in the binary or text format) with the passed `scripts`
+/// and return the encoded result.
+///
+/// If the `app` is a component, each of its core modules is instrumented (or only the one
+/// selected with `Options::core_module`) and the component is re-encoded around them.
+///
+/// This runs the full pipeline (parse, verify, then emit the instrumentation) without ever
+/// exiting the process. If anything goes wrong, the collected errors and warnings are returned
+/// for the caller to report (for the first script that has errors).
+///
+/// Multiple scripts are composed in the order they're passed: the probes of an earlier script
+/// are injected before those of a later one at the same site. Each script has its own scope,
+/// so their globals and functions don't collide, and what they export is prefixed with the
+/// script's ID (`script0_`, `script1_`, ...).
+pub fn instrument(
+    app: &[u8],
+    scripts: &[ScriptSource],
+    opts: &Options,
+) -> Result<Instrumented, Diagnostics> {
+    if scripts.is_empty() {
+        let mut err = ErrorGen::new("".to_string(), "".to_string(), opts.max_errors);
+        err.add_error(ErrorGen::get_unexpected_error(
+            false,
+            Some("Expected at least one script to instrument with".to_string()),
+            None,
+        ));
+        return Err(err.take_diagnostics());
+    }
+
+    // Verify each script on its own first, so that its diagnostics point into it
+    let mut errs = vec![];
+    let mut verified = vec![];
+    for script in scripts.iter() {
+        let mut err = ErrorGen::new(script.path.clone(), script.text.clone(), opts.max_errors);
+        let mut whamm = parse(script, &mut err)?;
+        let (symbol_table, simple_ast) = verify(&mut whamm, &mut err, true)?;
+        verified.push(Verified {
+            whamm,
+            symbol_table,
+            simple_ast,
+        });
+        errs.push(err);
+    }
+
+    let paths: Vec<&str> = scripts.iter().map(|script| script.path.as_str()).collect();
+    let mut verified = if verified.len() == 1 {
+        verified.remove(0)
+    } else {
+        // Then put the scripts together, each script is added in order and gets its own scope.
+        // They have all been verified already, this only builds the symbol table of the whole.
+        let mut whamm = Whamm::new();
+        for mut script in verified {
+            whamm.add_script(script.whamm.scripts.remove(0));
+        }
+        let mut scratch_err = ErrorGen::new(paths.join(", "), "".to_string(), opts.max_errors);
+        let (symbol_table, simple_ast) = verify(&mut whamm, &mut scratch_err, false)?;
+        Verified {
+            whamm,
+            symbol_table,
+            simple_ast,
+        }
+    };
+    let mut errs = ScriptErrs {
+        script_ids: verified
+            .whamm
+            .scripts
+            .iter()
+            .map(|script| script.name.clone())
+            .collect(),
+        errs,
+        combined: ErrorGen::new(paths.join(", "), "".to_string(), opts.max_errors),
+    };
+
+    let app = app_to_binary(app, errs.general())?;
+    let is_component = component::is_component(&app);
+    if is_component {
+        check_core_modules(&app, opts.core_module, errs.general())?;
+    }
+
+    let mut tally = ProbeTally::default();
+    let mut report = InjectionReport::default();
+    let mut warnings = vec![];
+    let mut instrument_module =
+        |module: &[u8], core_module: Option<u32>| -> Result<Vec<u8>, Diagnostics> {
+            // The probes are only to blame for an invalid module if the app was valid
+            if let Err(invalid) = validation::validate(module) {
+                let message = format!(
+                    "The application is invalid before instrumenting: {} (at offset {:#x})",
+                    invalid.message, invalid.offset
+                );
+                errs.general()
+                    .add_error(ErrorGen::get_instrumentation_error(true, message));
+                errs.check_has_errors()?;
+            }
+
+            // The sites are described by the module as the application passed it in, before
+            // `InitGenerator` changes it (and `orca` re-encodes it)
+            let app_info = AppInfo::new(module);
+            let in_component = core_module.map(|core_module| InComponent {
+                core_module,
+                fn_exports: fn_exports(module),
+            });
+            let mut app_wasm = parse_app(module, errs.general())?;
+            let emitted = emit(
+                &mut app_wasm,
+                app_info,
+                in_component,
+                &mut verified,
+                &opts.fn_filter,
+                std::mem::take(&mut tally),
+                &mut errs,
+            )?;
+            report.injections.extend(emitted.report.injections);
+            tally = emitted.tally;
+            warnings.extend(emitted.warnings);
+
+            let mut instrumented = app_wasm.encode();
+            match custom_sections::update(module, &instrumented, &emitted.names) {
+                Ok(updated) => {
+                    instrumented = updated.wasm;
+                    if !updated.dropped_dwarf.is_empty() {
+                        errs.general().add_warn(ErrorGen::get_instr_warn(
+                            format!(
+                                "Dropped the DWARF sections of the application ({}), \
+                                their code offsets don't match the instrumented code",
+                                updated.dropped_dwarf.join(", ")
+                            ),
+                            None,
+                        ));
+                    }
+                }
+                Err(e) => {
+                    warn!("Unable to update the custom sections of the instrumented module: {e}")
+                }
+            }
+
+            // Catch bad injected code here, rather than when the module is loaded by an engine
+            if let Err(invalid) = validation::validate(&instrumented) {
+                for (script_id, error) in invalid_module_errors(&invalid, &emitted.report) {
+                    errs.get(script_id.as_deref()).add_error(error);
+                }
+                errs.check_has_errors()?;
+            }
+            Ok(instrumented)
+        };
+
+    let wasm = if is_component {
+        let instrumented = component::map_core_modules(&app, &mut |idx, module| {
+            if opts.core_module.is_some_and(|selected| selected != idx) {
+                return Ok(None);
+            }
+            instrument_module(module, Some(idx)).map(Some)
+        });
+        match instrumented {
+            Ok(component) => component,
+            Err(ComponentError::Instrument(diagnostics)) => return Err(diagnostics),
+            Err(ComponentError::Malformed(e)) => {
+                return Err(malformed_component(e, errs.general()))
+            }
+        }
+    } else {
+        instrument_module(&app, None)?
+    };
+    warnings.extend(tally.warnings());
+
+    // Hand the warnings about each script's probes to the script
+    for (script_id, warning) in warnings {
+        errs.get(Some(&script_id)).add_warn(warning);
+    }
+    let mut diagnostics: Vec<Diagnostics> = errs
+        .errs
+        .iter_mut()
+        .map(|err| err.take_diagnostics())
+        .collect();
+    if errs.combined.has_warnings {
+        diagnostics.push(errs.combined.take_diagnostics());
+    }
+
+    Ok(Instrumented {
+        wasm,
+        diagnostics,
+        report,
+    })
+}
+
+/// The errors for an instrumented module that failed to validate. The code that was injected
+/// into the invalid function is what broke it, so there is an error for each probe that was
+/// injected there (pointing at the probe). Each error comes with the ID of the script that the
+/// probe is in, if it is about a probe.
+fn invalid_module_errors(
+    invalid: &InvalidModule,
+    report: &InjectionReport,
+) -> Vec<(Option<String>, WhammError)> {
+    let message = format!(
+        "The instrumented module is invalid: {} (at offset {:#x})",
+        invalid.message, invalid.offset
+    );
+    let fn_id = match invalid.fn_id {
+        Some(fn_id) => fn_id,
+        None => return vec![(None, ErrorGen::get_instrumentation_error(true, message))],
+    };
+
+    // (script ID, spec, location) -> the sites the probe was injected at in the function
+    let mut probes: Vec<(&Injection, Vec<&InstrLoc>)> = vec![];
+    for injection in report.injections.iter() {
+        if injection.site.fn_id != fn_id {
+            continue;
+        }
+        match probes.iter_mut().find(|(probe, _)| {
+            probe.script_id == injection.script_id
+                && probe.probe_spec == injection.probe_spec
+                && probe.loc == injection.loc
+        }) {
+            Some((_, sites)) => sites.push(&injection.site),
+            None => probes.push((injection, vec![&injection.site])),
+        }
+    }
+    if probes.is_empty() {
+        return vec![(
+            None,
+            ErrorGen::get_instrumentation_error(
+                true,
+                format!(
+                    "{}, in function {} which was generated by whamm",
+                    message, fn_id
+                ),
+            ),
+        )];
+    }
+
+    probes
+        .into_iter()
+        .map(|(probe, sites)| {
+            let more = match sites.len() {
+                1 => "".to_string(),
+                n => format!(" (and {} more site(s) in the function)", n - 1),
+            };
+            let message = format!(
+                "{}, the probe `{}` was injected into the invalid function at {}{}",
+                message, probe.probe_spec, sites[0], more
+            );
+            (
+                Some(probe.script_id.clone()),
+                ErrorGen::get_instrumentation_error_from_loc(true, message, &probe.loc),
+            )
+        })
+        .collect()
+}
+
+/// Make sure that the `component` has the core module to instrument (any if `core_module` is
+/// `None`).
+fn check_core_modules(
+    component: &[u8],
+    core_module: Option<u32>,
+    err: &mut ErrorGen,
+) -> Result<(), Diagnostics> {
+    let num_modules = match component::num_core_modules(component) {
+        Ok(num_modules) => num_modules,
+        Err(e) => return Err(malformed_component(e, err)),
+    };
+    let message = match core_module {
+        Some(idx) if idx >= num_modules => format!(
+            "Cannot instrument core module {} of the component, it has {} core module(s)",
+            idx, num_modules
+        ),
+        None if num_modules == 0 => "The component has no core modules to instrument".to_string(),
+        _ => return Ok(()),
+    };
+    err.add_error(ErrorGen::get_instrumentation_error(true, message));
+    Err(err.take_diagnostics())
+}
+
+fn malformed_component(e: String, err: &mut ErrorGen) -> Diagnostics {
+    err.add_error(ErrorGen::get_instrumentation_error(
+        true,
+        format!("Failed to parse the application component: {}", e),
+    ));
+    err.take_diagnostics()
+}
+
+/// List every site in the `app` Wasm module that the (possibly partial) probe `spec` matches,
+/// along with the static data each site would bind. Nothing is emitted, this uses the same
+/// rules as `instrument` to find the sites, and leaves out the same ones: those in the functions
+/// (and calls) that the `opts.fn_filter` filters out, and those in the core modules of a
+/// component other than `opts.core_module`.
+pub fn preview_matches(
+    app: &[u8],
+    spec: &str,
+    opts: &Options,
+) -> Result<Vec<MatchedSite>, Diagnostics> {
+    // `BEGIN` and `END` don't match any sites in the application
+    if spec.eq_ignore_ascii_case("begin") || spec.eq_ignore_ascii_case("end") {
+        return Ok(vec![]);
+    }
+    // Probe the spec with an empty body, any missing parts of the spec match everything
+    let mut parts: Vec<&str> = spec.split(':').collect();
+    while parts.len() < 4 {
+        parts.push("*");
+    }
+    let script = ScriptSource::new(spec.to_string(), format!("{} {{ }}", parts.join(":")));
+    let mut err = ErrorGen::new(script.path.clone(), script.text.clone(), opts.max_errors);
+
+    let whamm = parse(&script, &mut err)?;
+    let simple_ast = build_simple_ast(&whamm, &mut err);
+    err.check_has_errors()?;
+    let app = app_to_binary(app, &mut err)?;
+    if !component::is_component(&app) {
+        return preview_module(&app, None, &simple_ast, &opts.fn_filter, &mut err);
+    }
+    check_core_modules(&app, opts.core_module, &mut err)?;
+
+    let mut sites = vec![];
+    let previewed = component::map_core_modules(&app, &mut |idx, module| {
+        if opts.core_module.is_some_and(|selected| selected != idx) {
+            return Ok(None);
+        }
+        sites.extend(preview_module(
+            module,
+            Some(idx),
+            &simple_ast,
+            &opts.fn_filter,
+            &mut err,
+        )?);
+        Ok::<_, Diagnostics>(None)
+    });
+    match previewed {
+        Ok(_) => Ok(sites),
+        Err(ComponentError::Instrument(diagnostics)) => Err(diagnostics),
+        Err(ComponentError::Malformed(e)) => Err(malformed_component(e, &mut err)),
+    }
+}
+
+/// The sites in the `module` that the probes in the `simple_ast` match and the `fn_filter`
+/// doesn't filter out, `core_module` is its index if it is one of the core modules of a
+/// component.
+fn preview_module(
+    module: &[u8],
+    core_module: Option<u32>,
+    simple_ast: &SimpleAST,
+    fn_filter: &FnFilter,
+    err: &mut ErrorGen,
+) -> Result<Vec<MatchedSite>, Diagnostics> {
+    let mut app_wasm = parse_app(module, err)?;
+
+    let mut symbol_table = SymbolTable::new();
+    let mem_tracker = MemoryTracker {
+        mem_id: 0,
+        curr_mem_offset: 0,
+        required_initial_mem_size: 0,
+        emitted_strings: HashMap::new(),
+        emitted_aggregations: HashMap::new(),
+    };
+    let mut emitter = VisitingEmitter::new(
+        &mut app_wasm,
+        AppInfo::new(module),
+        &mut symbol_table,
+        &mem_tracker,
+    );
+    emitter.in_component = core_module.map(|core_module| InComponent {
+        core_module,
+        fn_exports: fn_exports(module),
+    });
+    let rules = provider_factory::<WhammProvider>(&simple_ast.probes);
+
+    let mut sites = vec![];
+    let mut first_instr = true;
+    while first_instr || emitter.next_instr() {
+        first_instr = false;
+        let fn_id = emitter.curr_fn_id();
+        if !fn_filter.includes_fn(fn_id, &emitter.fn_name(fn_id))
+            || emitter
+                .curr_call_import_module()
+                .is_some_and(|module| fn_filter.skips_imports_of(&module))
+        {
+            continue;
+        }
+        for rule in rules.iter() {
+            if let Some(loc_info) = emitter.get_loc_info(rule) {
+                let mut specs: Vec<String> = loc_info
+                    .probes
+                    .iter()
+                    .map(|(probe_spec, _)| probe_spec.full_name())
+                    .collect();
+                specs.sort();
+                specs.dedup();
+                let mut static_data: Vec<_> = loc_info.static_data.into_iter().collect();
+                static_data.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+                sites.push(MatchedSite {
+                    site: emitter.curr_instr_loc(),
+                    instr: emitter.curr_instr_name(),
+                    specs,
+                    static_data,
+                });
+            }
+        }
+    }
+
+    Ok(sites)
+}
+
+fn parse(script: &ScriptSource, err: &mut ErrorGen) -> Result<Whamm, Diagnostics> {
+    match parse_script(&script.text, err) {
+        Some(whamm) => {
+            err.check_fatal()?;
+            Ok(whamm)
+        }
+        None => Err(err.take_diagnostics()),
+    }
+}
+
+fn verify(
+    whamm: &mut Whamm,
+    err: &mut ErrorGen,
+    run_lints: bool,
+) -> Result<(SymbolTable, SimpleAST), Diagnostics> {
+    let mut symbol_table = build_symbol_table(whamm, err);
+    err.check_fatal()?;
+    if type_check(whamm, &mut symbol_table, err) && run_lints {
+        lint(whamm, err);
+    }
+    let simple_ast = build_simple_ast(whamm, err);
+    err.check_has_errors()?;
+
+    Ok((symbol_table, simple_ast))
+}
+
+/// The application can be passed in the text format as well, it's converted to binary Wasm.
+fn app_to_binary<'a>(app: &'a [u8], err: &mut ErrorGen) -> Result<Cow<'a, [u8]>, Diagnostics> {
+    match text::app_to_binary(app) {
+        Ok(app) => Ok(app),
+        Err(e) => {
+            err.add_error(ErrorGen::get_instrumentation_error(
+                true,
+                format!("Failed to parse the application WAT: {}", e),
+            ));
+            Err(err.take_diagnostics())
+        }
+    }
+}
+
+fn parse_app<'a>(app: &'a [u8], err: &mut ErrorGen) -> Result<WasmModule<'a>, Diagnostics> {
+    // Read app Wasm into Orca module
+    let app_wasm = match WasmModule::parse(app, false) {
+        Ok(app_wasm) => app_wasm,
+        Err(e) => {
+            err.add_error(ErrorGen::get_instrumentation_error(
+                true,
+                format!("Failed to parse the application Wasm: {:?}", e),
+            ));
+            return Err(err.take_diagnostics());
+        }
+    };
+    if app_wasm.memories.len() > 1 {
+        // TODO -- make this work with multi-memory
+        err.add_error(ErrorGen::get_instrumentation_error(
+            true,
+            "Only single memory is supported".to_string(),
+        ));
+        return Err(err.take_diagnostics());
+    };
+
+    Ok(app_wasm)
+}
+
+/// The scripts after the front end (parse and verify) has run on them, ready to be emitted.
+struct Verified {
+    whamm: Whamm,
+    symbol_table: SymbolTable,
+    simple_ast: SimpleAST,
+}
+
+/// The `ErrorGen` of each script (in the order they were passed), and one for the diagnostics
+/// that aren't about a single script. The diagnostics about a script's probes are routed to the
+/// script's own `ErrorGen` by its ID, so that they point into the script.
+struct ScriptErrs {
+    script_ids: Vec<String>,
+    errs: Vec<ErrorGen>,
+    combined: ErrorGen,
+}
+impl ScriptErrs {
+    /// Where the diagnostics that aren't about a single script go, this is the script's own
+    /// `ErrorGen` if there is only one.
+    fn general(&mut self) -> &mut ErrorGen {
+        match self.errs.as_mut_slice() {
+            [err] => err,
+            _ => &mut self.combined,
+        }
+    }
+
+    /// The `ErrorGen` of the script with the passed ID, see `general` for `None`.
+    fn get(&mut self, script_id: Option<&str>) -> &mut ErrorGen {
+        match script_id.and_then(|script_id| self.script_ids.iter().position(|id| id == script_id))
+        {
+            Some(i) => &mut self.errs[i],
+            None => self.general(),
+        }
+    }
+
+    fn add_errors(&mut self, errors: Vec<(String, WhammError)>) {
+        for (script_id, error) in errors {
+            self.get(Some(&script_id)).add_error(error);
+        }
+    }
+
+    /// Hands back the diagnostics of the first script that has errors (or the general ones).
+    fn check_has_errors(&mut self) -> Result<(), Diagnostics> {
+        for err in self.errs.iter_mut() {
+            err.check_has_errors()?;
+        }
+        self.combined.check_has_errors()
+    }
+}
+
+/// What emitting the instrumentation into a module produced, besides the module itself.
+struct Emitted {
+    report: InjectionReport,
+    tally: ProbeTally,
+    warnings: Vec<(String, WhammWarning)>,
+    /// The names of what was injected, for the `name` section
+    names: InjectedNames,
+}
+
+/// Emit the function that writes out the aggregations (see `ModuleEmitter::emit_agg_report`),
+/// returns its ID if it should be called when the application exits. The report needs the
+/// application to import WASI's `fd_write`, and is only called automatically in a WASI command
+/// (a module that exports `_start`) or where the application calls `proc_exit`.
+fn emit_agg_report(
+    emitter: &mut ModuleEmitter,
+    app_info: &AppInfo,
+    err: &mut ErrorGen,
+) -> Option<u32> {
+    let Some(fd_write) = app_info.imported_fn(WASI_MODULE, "fd_write") else {
+        err.add_warn(ErrorGen::get_instr_warn(
+            format!(
+                "The aggregations can't be reported when the application exits, it doesn't \
+                import `fd_write` from `{WASI_MODULE}`. Read them through the `get_agg_*` \
+                exports instead"
+            ),
+            None,
+        ));
+        return None;
+    };
+    let report = emitter.emit_agg_report(fd_write);
+    if app_info.exported_fn("_start").is_none()
+        && app_info.imported_fn(WASI_MODULE, "proc_exit").is_none()
+    {
+        err.add_warn(ErrorGen::get_instr_warn(
+            "The aggregations can't be reported when the application exits, it neither exports \
+            `_start` nor imports `proc_exit`. Call the exported `whamm_agg_report` at the end of \
+            the run instead"
+                .to_string(),
+            None,
+        ));
+    }
+    Some(report)
+}
+
+/// Emit the instrumentation into the `app_wasm` module, `app_info` is pulled from the module
+/// before anything was injected and `in_component` is set if it is one of the core modules of a
+/// component. The `tally` of the probes is carried over from the modules that were instrumented
+/// before it.
+///
+/// The same `verified` scripts are emitted into every module, each one gets its own copy of the
+/// symbol table since that holds the addresses of what was injected into the module.
+fn emit(
+    app_wasm: &mut WasmModule,
+    app_info: AppInfo,
+    in_component: Option<InComponent>,
+    verified: &mut Verified,
+    fn_filter: &FnFilter,
+    tally: ProbeTally,
+    errs: &mut ScriptErrs,
+) -> Result<Emitted, Diagnostics> {
+    // TODO Configure the generator based on target (wizard vs bytecode rewriting)
+
+    // Create the memory tracker
+    let mut mem_tracker = MemoryTracker {
+        mem_id: 0,                     // Assuming the ID of the first memory is 0!
+        curr_mem_offset: 1_052_576, // Set default memory base address to DEFAULT + 4KB = 1048576 bytes + 4000 bytes = 1052576 bytes
+        required_initial_mem_size: 27, // Size memory must be to account for the added data
+        emitted_strings: HashMap::new(),
+        emitted_aggregations: HashMap::new(),
+    };
+
+    let mut symbol_table = verified.symbol_table.clone();
+
+    // Phase 0 of instrumentation (emit globals and provided fns)
+    let mut init = InitGenerator {
+        emitter: ModuleEmitter::new(app_wasm, &mut symbol_table, &mut mem_tracker),
+        context_name: "".to_string(),
+        err: errs.general(),
+        errors: vec![],
+    };
+    init.run(&mut verified.whamm);
+    let agg_report = if init.emitter.has_aggregations() {
+        emit_agg_report(&mut init.emitter, &app_info, init.err)
+    } else {
+        None
+    };
+    let mut names = std::mem::take(&mut init.emitter.names);
+    let script_errors = std::mem::take(&mut init.errors);
+    errs.add_errors(script_errors);
+    errs.check_has_errors()?;
+
+    // Phase 1 of instrumentation (actually emits the instrumentation code)
+    // This structure is necessary since we need to have the fns/globals injected (a single time)
+    // and ready to use in every body/predicate.
+    let mut emitter = VisitingEmitter::new(app_wasm, app_info, &mut symbol_table, &mem_tracker);
+    emitter.in_component = in_component;
+    let mut instr = InstrGenerator::new(emitter, verified.simple_ast.clone(), errs.general());
+    instr.tally = tally;
+    instr.fn_filter = fn_filter.clone();
+    instr.agg_report = agg_report;
+    instr.run();
+    let report = std::mem::take(&mut instr.report);
+    let tally = std::mem::take(&mut instr.tally);
+    let warnings = std::mem::take(&mut instr.warnings);
+    let script_errors = std::mem::take(&mut instr.errors);
+    names.extend(std::mem::take(&mut instr.emitter.names));
+    errs.add_errors(script_errors);
+    errs.check_has_errors()?;
+
+    Ok(Emitted {
+        report,
+        tally,
+        warnings,
+        names,
+    })
+}
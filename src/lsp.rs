@@ -0,0 +1,5 @@
+pub mod analysis;
+pub mod server;
+
+#[cfg(test)]
+pub mod tests;
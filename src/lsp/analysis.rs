@@ -0,0 +1,498 @@
+use crate::common::error::{Diagnostics, ErrorGen};
+use crate::common::terminal::white;
+use crate::parser::rules::{provider_factory, Event, Package, Probe, Provider, WhammProvider};
+use crate::parser::types::{
+    print_fns, print_global_vars, BinOp, Block, DataType, Expr, Fn, Location, ProbeSpec,
+    ProvidedFunction, ProvidedGlobal, Script, SpecPart, Statement, UnOp, Value, Whamm,
+    WhammVisitor,
+};
+use crate::parser::whamm_parser::{parse_script, write_info};
+use crate::verifier::lints::lint;
+use crate::verifier::types::{Record, SymbolTable};
+use crate::verifier::verifier::{build_symbol_table, type_check};
+use pest::error::LineColLocation;
+use std::collections::HashMap;
+use termcolor::Buffer;
+
+/// Editors re-analyze on every keystroke, so report everything instead of stopping early.
+const MAX_ERRORS: i32 = 100;
+
+/// The result of running the front-end of the compiler over a script.
+pub struct Analysis {
+    pub diagnostics: Diagnostics,
+    whamm: Option<Whamm>,
+    /// Only present if the script was type checked, which is when the table contains the
+    /// locals of every probe body.
+    table: Option<SymbolTable>,
+}
+
+/// What an identifier in the script refers to.
+#[derive(Debug)]
+pub struct Symbol {
+    pub name: String,
+    /// Where the identifier appears under the cursor
+    pub usage: Location,
+    /// Where the identifier is declared, this is `None` for compiler-provided symbols
+    pub definition: Option<Location>,
+    /// The same text `whamm info` prints for this symbol
+    pub docs: Option<String>,
+}
+
+/// One option for the next part of a probe specification.
+#[derive(Debug)]
+pub struct SpecCompletion {
+    pub name: String,
+    /// Which part of the spec this is: provider, package, event or mode
+    pub part: &'static str,
+    pub docs: String,
+}
+
+/// Parse, build the symbol table for and type check the passed script text.
+pub fn analyze(text: &str) -> Analysis {
+    let mut err = ErrorGen::new("".to_string(), text.to_string(), MAX_ERRORS);
+    let mut whamm = parse_script(&text.to_string(), &mut err);
+
+    let mut table = None;
+    if let Some(whamm) = &mut whamm {
+        if !err.has_fatal {
+            let mut symbol_table = build_symbol_table(whamm, &mut err);
+            if !err.has_fatal {
+                if type_check(whamm, &mut symbol_table, &mut err) {
+                    lint(whamm, &mut err);
+                }
+                table = Some(symbol_table);
+            }
+        }
+    }
+
+    Analysis {
+        diagnostics: err.take_diagnostics(),
+        whamm,
+        table,
+    }
+}
+
+impl Analysis {
+    /// Resolve the identifier at the passed (one-based) line and column.
+    pub fn symbol_at(&mut self, line: usize, col: usize) -> Option<Symbol> {
+        let (whamm, table) = match (&self.whamm, &mut self.table) {
+            (Some(whamm), Some(table)) => (whamm, table),
+            _ => return None,
+        };
+
+        let mut finder = SymbolFinder {
+            table,
+            pos: (line, col),
+            context: vec![],
+            found: None,
+        };
+        finder.visit_whamm(whamm);
+        let (name, usage, rec_id, context) = finder.found?;
+
+        let record = rec_id.and_then(|id| finder.table.get_record(&id));
+        let definition = record.and_then(|record| record.loc().clone());
+        let docs = match record {
+            Some(record) if !record.is_comp_provided() => user_docs(whamm, &name, record),
+            _ => provided_docs(whamm, &context, &name),
+        };
+
+        Some(Symbol {
+            name,
+            usage,
+            definition,
+            docs,
+        })
+    }
+}
+
+/// Walks the AST the same way as the `TypeChecker` so that the symbol table scopes line up,
+/// looking for the identifier at `pos`.
+struct SymbolFinder<'a> {
+    table: &'a mut SymbolTable,
+    pos: (usize, usize),
+    /// The names of the script, provider, package, event and probe mode being visited
+    context: Vec<String>,
+    /// The name, location, record and context of the identifier at `pos`
+    found: Option<(String, Location, Option<usize>, Vec<String>)>,
+}
+impl SymbolFinder<'_> {
+    fn check(&mut self, name: &str, loc: &Option<Location>) {
+        if self.found.is_some() {
+            return;
+        }
+        if let Some(loc) = loc {
+            if contains(&loc.line_col, self.pos) {
+                let rec_id = self.table.lookup(name).copied();
+                self.found = Some((name.to_string(), loc.clone(), rec_id, self.context.clone()));
+            }
+        }
+    }
+}
+
+fn contains(line_col: &LineColLocation, pos: (usize, usize)) -> bool {
+    match line_col {
+        LineColLocation::Pos(at) => *at == pos,
+        LineColLocation::Span(start, end) => *start <= pos && pos <= *end,
+    }
+}
+
+impl WhammVisitor<()> for SymbolFinder<'_> {
+    fn visit_whamm(&mut self, whamm: &Whamm) {
+        self.table.reset();
+        whamm
+            .scripts
+            .iter()
+            .for_each(|script| self.visit_script(script));
+    }
+
+    fn visit_script(&mut self, script: &Script) {
+        self.table.enter_named_scope(&script.name);
+        self.context.push(script.name.clone());
+
+        script
+            .global_stmts
+            .iter()
+            .for_each(|stmt| self.visit_stmt(stmt));
+        script
+            .fns
+            .iter()
+            .for_each(|function| self.visit_fn(function));
+        script
+            .providers
+            .iter()
+            .for_each(|(_name, provider)| self.visit_provider(provider));
+
+        self.context.pop();
+        let _ = self.table.exit_scope();
+    }
+
+    fn visit_provider(&mut self, provider: &Box<dyn Provider>) {
+        let _ = self.table.enter_scope();
+        self.context.push(provider.name());
+
+        provider
+            .packages()
+            .for_each(|package| self.visit_package(package));
+
+        self.context.pop();
+        let _ = self.table.exit_scope();
+    }
+
+    fn visit_package(&mut self, package: &dyn Package) {
+        let _ = self.table.enter_scope();
+        self.context.push(package.name());
+
+        package.events().for_each(|event| self.visit_event(event));
+
+        self.context.pop();
+        let _ = self.table.exit_scope();
+    }
+
+    fn visit_event(&mut self, event: &dyn Event) {
+        let _ = self.table.enter_scope();
+        self.context.push(event.name());
+
+        event.probes().iter().for_each(|(_mode, probe_list)| {
+            probe_list.iter().for_each(|probe| self.visit_probe(probe));
+        });
+
+        self.context.pop();
+        let _ = self.table.exit_scope();
+    }
+
+    fn visit_probe(&mut self, probe: &Box<dyn Probe>) {
+        let _ = self.table.enter_scope();
+        self.context.push(probe.mode_name());
+
+        if let Some(predicate) = probe.predicate() {
+            self.visit_expr(predicate);
+        }
+        if let Some(body) = probe.body() {
+            self.visit_block(body);
+        }
+
+        self.context.pop();
+        let _ = self.table.exit_scope();
+    }
+
+    fn visit_fn(&mut self, function: &Fn) {
+        self.table.enter_named_scope(&function.name.name);
+
+        self.check(&function.name.name, &function.name.loc);
+        function
+            .params
+            .iter()
+            .for_each(|param| self.visit_formal_param(param));
+        self.visit_block(&function.body);
+
+        let _ = self.table.exit_scope();
+    }
+
+    fn visit_formal_param(&mut self, param: &(Expr, DataType)) {
+        self.visit_expr(&param.0);
+    }
+
+    fn visit_block(&mut self, block: &Block) {
+        block.stmts.iter().for_each(|stmt| self.visit_stmt(stmt));
+    }
+
+    fn visit_stmt(&mut self, stmt: &Statement) {
+        match stmt {
+            Statement::Decl { var_id, .. } => self.visit_expr(var_id),
+            Statement::Assign { var_id, expr, .. } => {
+                self.visit_expr(var_id);
+                self.visit_expr(expr);
+            }
+            Statement::Expr { expr, .. } | Statement::Return { expr, .. } => self.visit_expr(expr),
+            Statement::If {
+                cond, conseq, alt, ..
+            } => {
+                self.visit_expr(cond);
+                self.visit_block(conseq);
+                self.visit_block(alt);
+            }
+            Statement::Aggregate { keys, args, .. } => {
+                keys.iter()
+                    .chain(args.iter())
+                    .for_each(|expr| self.visit_expr(expr));
+            }
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::UnOp { expr, .. } => self.visit_expr(expr),
+            Expr::Ternary {
+                cond, conseq, alt, ..
+            } => {
+                self.visit_expr(cond);
+                self.visit_expr(conseq);
+                self.visit_expr(alt);
+            }
+            Expr::BinOp { lhs, rhs, .. } => {
+                self.visit_expr(lhs);
+                self.visit_expr(rhs);
+            }
+            Expr::Call {
+                fn_target, args, ..
+            } => {
+                self.visit_expr(fn_target);
+                if let Some(args) = args {
+                    args.iter().for_each(|arg| self.visit_expr(arg));
+                }
+            }
+            Expr::VarId { name, loc, .. } => self.check(name, loc),
+            Expr::Primitive { val, .. } => self.visit_value(val),
+        }
+    }
+
+    fn visit_unop(&mut self, _unop: &UnOp) {}
+
+    fn visit_binop(&mut self, _binop: &BinOp) {}
+
+    fn visit_datatype(&mut self, _datatype: &DataType) {}
+
+    fn visit_value(&mut self, val: &Value) {
+        if let Value::Tuple { vals, .. } = val {
+            vals.iter().for_each(|val| self.visit_expr(val));
+        }
+    }
+}
+
+/// Show user-defined functions with their signature and variables with their type.
+fn user_docs(whamm: &Whamm, name: &str, record: &Record) -> Option<String> {
+    let mut buffer = Buffer::no_color();
+    match record {
+        Record::Fn { .. } => {
+            let function = whamm
+                .scripts
+                .iter()
+                .flat_map(|script| script.fns.iter())
+                .find(|function| function.name.name == name)?;
+            function.print(&mut buffer);
+        }
+        Record::Var { ty, .. } => {
+            white(false, format!("{}: ", name), &mut buffer);
+            ty.print(&mut buffer);
+        }
+        _ => return None,
+    }
+    Some(to_string(buffer))
+}
+
+/// Look for the docs of a compiler-provided symbol from the innermost part of the probe
+/// specification outwards, ending with the core `whamm` functionality.
+fn provided_docs(whamm: &Whamm, context: &[String], name: &str) -> Option<String> {
+    let mut in_scope: Vec<(&HashMap<String, ProvidedGlobal>, &Vec<ProvidedFunction>)> =
+        vec![(&whamm.globals, &whamm.fns)];
+
+    let script = whamm
+        .scripts
+        .iter()
+        .find(|script| Some(&script.name) == context.first());
+    let provider = script.and_then(|script| script.providers.get(context.get(1)?));
+    if let Some(provider) = provider {
+        in_scope.push((provider.get_provided_globals(), provider.get_provided_fns()));
+        let package = provider
+            .packages()
+            .find(|package| Some(&package.name()) == context.get(2));
+        if let Some(package) = package {
+            in_scope.push((package.get_provided_globals(), package.get_provided_fns()));
+            let event = package
+                .events()
+                .find(|event| Some(&event.name()) == context.get(3));
+            if let Some(event) = event {
+                in_scope.push((event.get_provided_globals(), event.get_provided_fns()));
+                let probe = context
+                    .get(4)
+                    .and_then(|mode| event.probes().get(mode))
+                    .and_then(|probes| probes.first());
+                if let Some(probe) = probe {
+                    in_scope.push((
+                        probe.get_mode_provided_globals(),
+                        probe.get_mode_provided_fns(),
+                    ));
+                }
+            }
+        }
+    }
+
+    let mut tabs = 0;
+    let mut buffer = Buffer::no_color();
+    for (globals, fns) in in_scope.iter().rev() {
+        if let Some(global) = globals.get(name) {
+            let global = HashMap::from([(name.to_string(), global.clone())]);
+            print_global_vars(&mut tabs, &global, &mut buffer);
+            return Some(to_string(buffer));
+        }
+        if let Some(function) = fns.iter().find(|function| function.name == name) {
+            print_fns(&mut tabs, &[function.clone()], &mut buffer);
+            return Some(to_string(buffer));
+        }
+    }
+    None
+}
+
+/// What `whamm info --globals --functions` prints for the passed probe specification.
+pub fn spec_info(spec: &str) -> Option<String> {
+    let mut err = ErrorGen::new("".to_string(), spec.to_string(), MAX_ERRORS);
+    let mut buffer = Buffer::no_color();
+    write_info(spec, true, true, &mut buffer, &mut err);
+    if err.has_errors {
+        return None;
+    }
+    Some(to_string(buffer))
+}
+
+/// The characters that can make up a probe specification, see `PROBE_SPEC` in the grammar.
+fn is_spec_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "_*+\\?|![]:".contains(c)
+}
+
+/// The probe specification around the passed (zero-based) column of `line`, if any.
+pub fn spec_at(line: &str, col: usize) -> Option<String> {
+    let chars: Vec<char> = line.chars().collect();
+    if col > chars.len() {
+        return None;
+    }
+    let start = chars[..col]
+        .iter()
+        .rposition(|c| !is_spec_char(*c))
+        .map_or(0, |i| i + 1);
+    let end = chars[col..]
+        .iter()
+        .position(|c| !is_spec_char(*c))
+        .map_or(chars.len(), |i| col + i);
+
+    let spec: String = chars[start..end].iter().collect();
+    if spec.contains(':') {
+        Some(spec)
+    } else {
+        None
+    }
+}
+
+/// The options for the part of the probe specification that ends `line_prefix`.
+/// Providers are only suggested at the start of a line, since that's where probes are
+/// declared.
+pub fn spec_completions(line_prefix: &str) -> Vec<SpecCompletion> {
+    let word = line_prefix
+        .rsplit(|c: char| !is_spec_char(c))
+        .next()
+        .unwrap_or_default();
+    let parts: Vec<&str> = word.split(':').collect();
+    let depth = parts.len() - 1;
+    if (depth == 0 && word.len() != line_prefix.len()) || depth > 3 {
+        return vec![];
+    }
+
+    // Match everything for the part being completed, the editor filters by what's typed
+    let mut probe_spec = ProbeSpec::new();
+    for (i, slot) in [
+        &mut probe_spec.provider,
+        &mut probe_spec.package,
+        &mut probe_spec.event,
+        &mut probe_spec.mode,
+    ]
+    .into_iter()
+    .enumerate()
+    .take(depth + 1)
+    {
+        let name = if i == depth { "*" } else { parts[i] };
+        *slot = Some(SpecPart {
+            name: name.to_string(),
+            loc: None,
+        });
+    }
+
+    let mut providers: HashMap<String, Box<dyn Provider>> = HashMap::new();
+    if provider_factory::<WhammProvider>(&mut providers, &probe_spec, None, None, None, 0, true)
+        .is_err()
+    {
+        return vec![];
+    }
+
+    let mut options: Vec<SpecCompletion> = vec![];
+    for provider in providers.values() {
+        if depth == 0 {
+            options.push(option(provider.name(), "provider", provider.docs()));
+            continue;
+        }
+        for package in provider.packages() {
+            if depth == 1 {
+                options.push(option(package.name(), "package", package.docs()));
+                continue;
+            }
+            for event in package.events() {
+                if depth == 2 {
+                    options.push(option(event.name(), "event", event.docs()));
+                    continue;
+                }
+                for (mode, probes) in event.probes().iter() {
+                    if let Some(probe) = probes.first() {
+                        options.push(option(mode.clone(), "mode", probe.mode_docs()));
+                    }
+                }
+            }
+        }
+    }
+
+    // The same name can be matched through several patterns
+    options.sort_by(|a, b| a.name.cmp(&b.name));
+    options.dedup_by(|a, b| a.name == b.name);
+    options
+}
+
+fn option(name: String, part: &'static str, docs: &str) -> SpecCompletion {
+    SpecCompletion {
+        name,
+        part,
+        docs: docs.to_string(),
+    }
+}
+
+fn to_string(buffer: Buffer) -> String {
+    String::from_utf8_lossy(buffer.as_slice())
+        .trim_end()
+        .to_string()
+}
@@ -0,0 +1,307 @@
+use crate::common::diagnostics::{char_col, lsp_range};
+use crate::lsp::analysis::{analyze, spec_at, spec_completions, spec_info, Analysis};
+use log::{debug, trace};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::{self, BufRead, Read, Write};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+// JSON-RPC error codes
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_REQUEST: i32 = -32600;
+
+// LSP enum values
+const TEXT_DOCUMENT_SYNC_FULL: i32 = 1;
+const COMPLETION_KIND_MODULE: i32 = 9;
+const COMPLETION_KIND_EVENT: i32 = 23;
+const COMPLETION_KIND_ENUM_MEMBER: i32 = 20;
+
+/// The state of the language server: the open documents and where we are in the LSP lifecycle.
+#[derive(Default)]
+pub struct Server {
+    /// The text of each open document, keyed by URI
+    documents: HashMap<String, String>,
+    shutdown_requested: bool,
+    /// Set once the client sends `exit`, holds the process' exit code
+    pub exit_code: Option<i32>,
+}
+
+impl Server {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Handle a single message from the client, returning the messages to send back.
+    pub fn handle(&mut self, msg: &Value) -> Vec<Value> {
+        let method = msg["method"].as_str().unwrap_or_default();
+        let params = &msg["params"];
+        trace!("Handling LSP message: {}", method);
+
+        let id = match msg.get("id") {
+            Some(id) => id,
+            None => return self.handle_notification(method, params),
+        };
+        if self.shutdown_requested {
+            return vec![error_response(
+                id,
+                INVALID_REQUEST,
+                "The server is shutting down",
+            )];
+        }
+
+        let result = match method {
+            "initialize" => json!({
+                "capabilities": {
+                    "textDocumentSync": TEXT_DOCUMENT_SYNC_FULL,
+                    "completionProvider": { "triggerCharacters": [":"] },
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                },
+                "serverInfo": { "name": "whamm", "version": env!("CARGO_PKG_VERSION") },
+            }),
+            "shutdown" => {
+                self.shutdown_requested = true;
+                Value::Null
+            }
+            "textDocument/completion" => self.completion(params),
+            "textDocument/hover" => self.hover(params),
+            "textDocument/definition" => self.definition(params),
+            _ => {
+                return vec![error_response(
+                    id,
+                    METHOD_NOT_FOUND,
+                    &format!("Unsupported method: {}", method),
+                )]
+            }
+        };
+        vec![json!({ "jsonrpc": "2.0", "id": id, "result": result })]
+    }
+
+    fn handle_notification(&mut self, method: &str, params: &Value) -> Vec<Value> {
+        let uri = params["textDocument"]["uri"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+        match method {
+            "exit" => {
+                self.exit_code = Some(if self.shutdown_requested { 0 } else { 1 });
+                vec![]
+            }
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                self.documents.insert(uri.clone(), text.to_string());
+                vec![self.publish_diagnostics(&uri)]
+            }
+            "textDocument/didChange" => {
+                // We only ask for full syncs, so the last change holds the whole document
+                let changes = params["contentChanges"].as_array();
+                if let Some(text) = changes
+                    .and_then(|c| c.last())
+                    .and_then(|c| c["text"].as_str())
+                {
+                    self.documents.insert(uri.clone(), text.to_string());
+                }
+                vec![self.publish_diagnostics(&uri)]
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                vec![notification(
+                    "textDocument/publishDiagnostics",
+                    json!({ "uri": uri, "diagnostics": [] }),
+                )]
+            }
+            _ => {
+                debug!("Ignoring LSP notification: {}", method);
+                vec![]
+            }
+        }
+    }
+
+    fn publish_diagnostics(&self, uri: &str) -> Value {
+        let diagnostics = match self.analyze(uri) {
+            Some(analysis) => analysis.diagnostics.to_lsp(uri),
+            None => vec![],
+        };
+        notification(
+            "textDocument/publishDiagnostics",
+            json!({ "uri": uri, "diagnostics": diagnostics }),
+        )
+    }
+
+    /// Run the front-end of the compiler over an open document. A bug in one of the phases
+    /// shouldn't take the whole editor session down with it, so panics are caught here.
+    fn analyze(&self, uri: &str) -> Option<Analysis> {
+        let text = self.documents.get(uri)?;
+        catch_unwind(AssertUnwindSafe(|| analyze(text))).ok()
+    }
+
+    /// The text of the line at the `position` of the request along with the (zero-based)
+    /// line and column of the position. The column counts chars, the position's `character`
+    /// counts UTF-16 code units.
+    fn line_at(&self, params: &Value) -> Option<(&str, usize, usize)> {
+        let uri = params["textDocument"]["uri"].as_str()?;
+        let line = params["position"]["line"].as_u64()? as usize;
+        let character = params["position"]["character"].as_u64()? as usize;
+        let text = self
+            .documents
+            .get(uri)?
+            .lines()
+            .nth(line)
+            .unwrap_or_default();
+        Some((text, line, char_col(text, character)))
+    }
+
+    fn completion(&self, params: &Value) -> Value {
+        let (text, _, col) = match self.line_at(params) {
+            Some(at) => at,
+            None => return Value::Null,
+        };
+        let prefix: String = text.chars().take(col).collect();
+        let items: Vec<Value> = spec_completions(&prefix)
+            .into_iter()
+            .map(|option| {
+                let kind = match option.part {
+                    "event" => COMPLETION_KIND_EVENT,
+                    "mode" => COMPLETION_KIND_ENUM_MEMBER,
+                    _ => COMPLETION_KIND_MODULE,
+                };
+                json!({
+                    "label": option.name,
+                    "kind": kind,
+                    "detail": option.part,
+                    "documentation": option.docs,
+                })
+            })
+            .collect();
+        json!(items)
+    }
+
+    fn hover(&self, params: &Value) -> Value {
+        let (text, line, col) = match self.line_at(params) {
+            Some(at) => at,
+            None => return Value::Null,
+        };
+
+        // Probe specifications are documented like `whamm info` does, anything else is
+        // looked up in the symbol table.
+        if let Some(info) = spec_at(text, col).and_then(|spec| spec_info(&spec)) {
+            return hover_contents(info, None);
+        }
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let symbol = self
+            .analyze(uri)
+            .and_then(|mut analysis| analysis.symbol_at(line + 1, col + 1));
+        match symbol {
+            Some(symbol) => match symbol.docs {
+                Some(docs) => hover_contents(
+                    docs,
+                    Some(lsp_range(
+                        &self.documents[uri],
+                        Some(&symbol.usage.line_col),
+                    )),
+                ),
+                None => Value::Null,
+            },
+            None => Value::Null,
+        }
+    }
+
+    fn definition(&self, params: &Value) -> Value {
+        let (_, line, col) = match self.line_at(params) {
+            Some(at) => at,
+            None => return Value::Null,
+        };
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let definition = self
+            .analyze(uri)
+            .and_then(|mut analysis| analysis.symbol_at(line + 1, col + 1))
+            .and_then(|symbol| symbol.definition);
+        match definition {
+            Some(loc) => json!({
+                "uri": uri,
+                "range": lsp_range(&self.documents[uri], Some(&loc.line_col)),
+            }),
+            None => Value::Null,
+        }
+    }
+}
+
+fn hover_contents(text: String, range: Option<Value>) -> Value {
+    let mut hover = json!({
+        "contents": { "kind": "markdown", "value": format!("```\n{}\n```", text) },
+    });
+    if let Some(range) = range {
+        hover["range"] = range;
+    }
+    hover
+}
+
+fn notification(method: &str, params: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "method": method, "params": params })
+}
+
+fn error_response(id: &Value, code: i32, message: &str) -> Value {
+    json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "error": { "code": code, "message": message },
+    })
+}
+
+/// Read one `Content-Length` framed message, `None` means the client closed the stream.
+pub fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut content_length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(len) = header.strip_prefix("Content-Length:") {
+            content_length = len.trim().parse::<usize>().ok();
+        }
+    }
+
+    let len = match content_length {
+        Some(len) => len,
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "LSP message is missing its Content-Length header",
+            ))
+        }
+    };
+    let mut body = vec![0; len];
+    reader.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+pub fn write_message(writer: &mut impl Write, msg: &Value) -> io::Result<()> {
+    let body = msg.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+/// Serve LSP requests over stdin/stdout until the client exits, returning the exit code.
+pub fn run() -> io::Result<i32> {
+    let stdin = io::stdin();
+    let mut reader = stdin.lock();
+    let mut stdout = io::stdout();
+
+    let mut server = Server::new();
+    while let Some(msg) = read_message(&mut reader)? {
+        for response in server.handle(&msg) {
+            write_message(&mut stdout, &response)?;
+        }
+        if let Some(code) = server.exit_code {
+            return Ok(code);
+        }
+    }
+    // The client went away without asking us to exit
+    Ok(1)
+}
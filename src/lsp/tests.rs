@@ -0,0 +1,229 @@
+use crate::common::diagnostics::{char_col, utf16_col};
+use crate::lsp::analysis::{analyze, spec_at, spec_completions, spec_info};
+use crate::lsp::server::{read_message, write_message, Server};
+use serde_json::{json, Value};
+use std::io::Cursor;
+
+pub fn setup_logger() {
+    let _ = env_logger::builder().is_test(true).try_init();
+}
+
+const URI: &str = "file:///monitor.mm";
+
+const SCRIPT: &str = r#"
+i32 count;
+wasm:opcode:br_if:before {
+    count = count + fn_id;
+}
+"#;
+
+fn open(server: &mut Server, text: &str) -> Vec<Value> {
+    server.handle(&json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/didOpen",
+        "params": {
+            "textDocument": { "uri": URI, "languageId": "whamm", "version": 1, "text": text }
+        }
+    }))
+}
+
+fn request(server: &mut Server, id: i32, method: &str, line: usize, character: usize) -> Value {
+    let mut responses = server.handle(&json!({
+        "jsonrpc": "2.0",
+        "id": id,
+        "method": method,
+        "params": {
+            "textDocument": { "uri": URI },
+            "position": { "line": line, "character": character }
+        }
+    }));
+    assert_eq!(responses.len(), 1);
+    responses.remove(0)["result"].take()
+}
+
+// =============
+// = The Tests =
+// =============
+
+#[test]
+pub fn test_lifecycle() {
+    setup_logger();
+    let mut server = Server::new();
+
+    let init =
+        server.handle(&json!({ "jsonrpc": "2.0", "id": 0, "method": "initialize", "params": {} }));
+    let capabilities = &init[0]["result"]["capabilities"];
+    assert_eq!(capabilities["hoverProvider"], json!(true));
+    assert_eq!(capabilities["definitionProvider"], json!(true));
+    assert_eq!(
+        capabilities["completionProvider"]["triggerCharacters"],
+        json!([":"])
+    );
+
+    let unknown =
+        server.handle(&json!({ "jsonrpc": "2.0", "id": 1, "method": "workspace/symbol" }));
+    assert_eq!(unknown[0]["error"]["code"], json!(-32601));
+
+    server.handle(&json!({ "jsonrpc": "2.0", "id": 2, "method": "shutdown" }));
+    assert_eq!(server.exit_code, None);
+    server.handle(&json!({ "jsonrpc": "2.0", "method": "exit" }));
+    assert_eq!(server.exit_code, Some(0));
+}
+
+#[test]
+pub fn test_message_framing() {
+    setup_logger();
+    let msg = json!({ "jsonrpc": "2.0", "id": 7, "method": "shutdown" });
+    let mut out = vec![];
+    write_message(&mut out, &msg).unwrap();
+    assert!(out.starts_with(b"Content-Length: "));
+
+    let mut reader = Cursor::new(out);
+    assert_eq!(read_message(&mut reader).unwrap(), Some(msg));
+    assert_eq!(read_message(&mut reader).unwrap(), None);
+}
+
+#[test]
+pub fn test_publish_diagnostics() {
+    setup_logger();
+    let mut server = Server::new();
+
+    let published = open(&mut server, SCRIPT);
+    assert_eq!(
+        published[0]["method"],
+        json!("textDocument/publishDiagnostics")
+    );
+    assert_eq!(published[0]["params"]["diagnostics"], json!([]));
+
+    // A type error on the third line
+    let published = open(
+        &mut server,
+        "\ni32 count;\nwasm:opcode:br_if:before / count == \"a\" / { }",
+    );
+    let diagnostics = published[0]["params"]["diagnostics"].as_array().unwrap();
+    assert!(!diagnostics.is_empty());
+    assert_eq!(diagnostics[0]["severity"], json!(1));
+    assert_eq!(diagnostics[0]["source"], json!("whamm"));
+    assert_eq!(diagnostics[0]["range"]["start"]["line"], json!(2));
+}
+
+#[test]
+pub fn test_spec_completions() {
+    setup_logger();
+    let names = |prefix: &str| -> Vec<String> {
+        spec_completions(prefix)
+            .into_iter()
+            .map(|option| option.name)
+            .collect()
+    };
+
+    assert_eq!(names("wa"), vec!["component", "core", "wasm"]);
+    assert!(names("wasm:").contains(&"opcode".to_string()));
+    assert!(names("wasm:opcode:").contains(&"br_if".to_string()));
+    assert!(names("wasm:opcode:br_if:").contains(&"before".to_string()));
+
+    let events = spec_completions("wasm:opcode:");
+    assert!(events.iter().all(|option| option.part == "event"));
+    assert!(events.iter().all(|option| !option.docs.is_empty()));
+
+    // Providers are only suggested where probes are declared
+    assert!(names("    count = wa").is_empty());
+    assert!(names("nope:").is_empty());
+}
+
+#[test]
+pub fn test_hover_spec() {
+    setup_logger();
+    let line = "wasm:opcode:br_if:before {";
+    assert_eq!(
+        spec_at(line, 14),
+        Some("wasm:opcode:br_if:before".to_string())
+    );
+    assert_eq!(spec_at(line, 25), None);
+
+    let info = spec_info("wasm:opcode:br_if:before").unwrap();
+    assert!(info.contains("br_if"));
+    assert!(info.contains("fn_id"));
+    assert!(spec_info("nope:opcode").is_none());
+
+    let mut server = Server::new();
+    open(&mut server, SCRIPT);
+    let hover = request(&mut server, 1, "textDocument/hover", 2, 14);
+    assert!(hover["contents"]["value"]
+        .as_str()
+        .unwrap()
+        .contains("br_if"));
+}
+
+#[test]
+pub fn test_hover_symbol() {
+    setup_logger();
+    let mut analysis = analyze(SCRIPT);
+
+    // The user-defined global
+    let count = analysis.symbol_at(4, 14).unwrap();
+    assert_eq!(count.name, "count");
+    assert_eq!(count.docs.unwrap(), "count: i32");
+
+    // A compiler-provided global of the `wasm:opcode` package
+    let fn_id = analysis.symbol_at(4, 23).unwrap();
+    assert_eq!(fn_id.name, "fn_id");
+    assert!(fn_id.definition.is_none());
+    assert!(fn_id
+        .docs
+        .unwrap()
+        .contains("The ID of the function containing the probed instruction."));
+}
+
+#[test]
+pub fn test_goto_definition() {
+    setup_logger();
+    let mut server = Server::new();
+    open(&mut server, SCRIPT);
+
+    let definition = request(&mut server, 1, "textDocument/definition", 3, 14);
+    assert_eq!(definition["uri"], json!(URI));
+    assert_eq!(
+        definition["range"]["start"],
+        json!({ "line": 1, "character": 4 })
+    );
+
+    // Compiler-provided and unknown positions have nowhere to go
+    assert_eq!(
+        request(&mut server, 2, "textDocument/definition", 3, 22),
+        Value::Null
+    );
+    assert_eq!(
+        request(&mut server, 3, "textDocument/definition", 0, 0),
+        Value::Null
+    );
+}
+
+#[test]
+pub fn test_utf16_positions() {
+    setup_logger();
+    // `😀` is 2 UTF-16 code units, `é` is 1
+    let line = "/* 😀 é */ count";
+    assert_eq!(utf16_col(line, 11), 12);
+    assert_eq!(char_col(line, 12), 11);
+    for col in 0..=line.chars().count() + 2 {
+        assert_eq!(char_col(line, utf16_col(line, col)), col);
+    }
+    // in the middle of `😀`
+    assert_eq!(char_col(line, 4), 4);
+
+    let mut server = Server::new();
+    open(
+        &mut server,
+        "i32 count;\nwasm:opcode:br_if:before {\n    /* 😀 é */ count = count + fn_id;\n}",
+    );
+    // the second `count` is at chars 22 to 26, and UTF-16 code units 23 to 27
+    let hover = request(&mut server, 1, "textDocument/hover", 2, 27);
+    assert_eq!(
+        hover["range"],
+        json!({
+            "start": { "line": 2, "character": 23 },
+            "end": { "line": 2, "character": 28 },
+        })
+    );
+}
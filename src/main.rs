@@ -1,29 +1,20 @@
 extern crate core;
 
-use cli::{Cmd, WhammCli};
-use std::collections::HashMap;
+use cli::{Cmd, InstrArgs, WhammCli};
 
-use crate::common::error::ErrorGen;
-use crate::emitter::rewriting::module_emitter::{MemoryTracker, ModuleEmitter};
-use crate::generator::init_generator::InitGenerator;
-use crate::generator::instr_generator::InstrGenerator;
-use crate::parser::whamm_parser::*;
+use whamm::common::diagnostics::{emit_all, DiagnosticsFormat};
+use whamm::common::error::{Diagnostics, ErrorGen};
+use whamm::emitter::text::{to_annotated_wat, EmitFormat};
+use whamm::generator::fn_filter::FnFilter;
+use whamm::generator::report::{matches_markdown, matches_to_json, print_matches};
+use whamm::parser::catalog::{catalog, catalog_markdown, InfoFormat};
+use whamm::parser::formatter::format_script;
+use whamm::parser::whamm_parser::*;
+use whamm::{instrument, preview_matches, Options, ScriptSource};
 
 mod cli;
-pub mod common;
-pub mod emitter;
-pub mod generator;
-pub mod parser;
-pub mod verifier;
-
-use crate::emitter::rewriting::visiting_emitter::VisitingEmitter;
-use crate::generator::simple_ast::build_simple_ast;
-use crate::parser::types::Whamm;
-use crate::verifier::types::SymbolTable;
-use crate::verifier::verifier::{build_symbol_table, type_check};
+
 use clap::Parser;
-use log::{error, info};
-use orca::ir::module::Module as WasmModule;
 use std::path::PathBuf;
 use std::process::exit;
 
@@ -55,11 +46,24 @@ fn try_main() -> Result<(), failure::Error> {
             spec,
             globals,
             functions,
+            app,
+            format,
+            diagnostics_format,
         } => {
-            run_info(spec, globals, functions);
+            run_info(spec, globals, functions, app, format, diagnostics_format);
         }
         Cmd::Instr(args) => {
-            run_instr(args.app, args.script, args.output_path, args.virgil);
+            run_instr(args);
+        }
+        Cmd::Lsp => {
+            exit(whamm::lsp::server::run()?);
+        }
+        Cmd::Fmt {
+            scripts,
+            check,
+            diagnostics_format,
+        } => {
+            run_fmt(scripts, check, diagnostics_format);
         }
     }
 
@@ -73,120 +77,240 @@ fn try_path(path: &String) {
     }
 }
 
-fn run_info(spec: String, print_globals: bool, print_functions: bool) {
+fn run_info(
+    spec: String,
+    print_globals: bool,
+    print_functions: bool,
+    app_wasm_path: Option<String>,
+    format: InfoFormat,
+    diagnostics_format: DiagnosticsFormat,
+) {
     // Parse the script and generate the information
     let mut err = ErrorGen::new("".to_string(), spec.clone(), MAX_ERRORS);
-    print_info(spec, print_globals, print_functions, &mut err);
-
-    err.fatal_report("PrintInfo");
-}
-
-fn run_instr(
-    app_wasm_path: String,
-    script_path: String,
-    output_wasm_path: String,
-    _emit_virgil: bool,
-) {
-    // Set up error reporting mechanism
-    let mut err = ErrorGen::new(script_path.clone(), "".to_string(), MAX_ERRORS);
+    let catalog = match format {
+        InfoFormat::Text => {
+            print_info(spec.clone(), print_globals, print_functions, &mut err);
+            None
+        }
+        // The exports are meant to be consumed by other tools, so they always hold everything
+        InfoFormat::Json | InfoFormat::Markdown => catalog(&spec, &mut err),
+    };
 
-    // Process the script
-    let mut whamm = get_script_ast(&script_path, &mut err);
-    let mut symbol_table = get_symbol_table(&mut whamm, &mut err);
-    let simple_ast = build_simple_ast(&whamm, &mut err);
-    err.check_too_many();
+    // Only the CLI decides to exit on errors. The diagnostics are written out together at the
+    // end, so that a machine-readable format is a single document.
+    let mut diagnostics = vec![err.take_diagnostics()];
+    if err.has_errors {
+        exit_with(&mut diagnostics, diagnostics_format);
+    }
 
-    // If there were any errors encountered, report and exit!
-    err.check_has_errors();
+    // Preview the sites the spec matches in the application
+    let sites = app_wasm_path.map(|app_wasm_path| {
+        let app_wasm = match std::fs::read(&app_wasm_path) {
+            Ok(app_wasm) => app_wasm,
+            Err(error) => {
+                diagnostics.push(error_diagnostics(
+                    &spec,
+                    format!("Cannot read specified file {}: {}", app_wasm_path, error),
+                ));
+                exit_with(&mut diagnostics, diagnostics_format);
+            }
+        };
+        match preview_matches(&app_wasm, &spec, &Options::default()) {
+            Ok(sites) => sites,
+            Err(preview_diagnostics) => {
+                diagnostics.push(preview_diagnostics);
+                exit_with(&mut diagnostics, diagnostics_format);
+            }
+        }
+    });
 
-    // Read app Wasm into Orca module
-    let buff = std::fs::read(app_wasm_path).unwrap();
-    let mut app_wasm = WasmModule::parse(&buff, false).unwrap();
+    // The matched sites are part of the export, so that it stays a single document
+    match catalog {
+        Some(mut catalog) if format == InfoFormat::Json => {
+            if let Some(sites) = &sites {
+                catalog["matched_sites"] = matches_to_json(sites);
+            }
+            println!("{:#}", catalog);
+        }
+        Some(catalog) => {
+            print!("{}", catalog_markdown(&catalog));
+            if let Some(sites) = &sites {
+                print!("{}", matches_markdown(sites));
+            }
+        }
+        None => {
+            if let Some(sites) = &sites {
+                print_matches(sites);
+            }
+        }
+    }
+    emit_all(&mut diagnostics, diagnostics_format);
+}
 
-    // TODO Configure the generator based on target (wizard vs bytecode rewriting)
+fn run_instr(args: InstrArgs) {
+    let InstrArgs {
+        app: app_wasm_path,
+        core_module,
+        include_func,
+        exclude_func,
+        skip_imports_of,
+        script: mut script_paths,
+        script_list: script_list_path,
+        output_path: output_wasm_path,
+        emit: emit_format,
+        virgil: _emit_virgil,
+        report: report_format,
+        diagnostics_format,
+    } = args;
+    let output_wasm_path =
+        output_wasm_path.unwrap_or_else(|| emit_format.default_output_path().to_string());
+    if let Some(script_list_path) = script_list_path {
+        match std::fs::read_to_string(&script_list_path) {
+            Ok(script_list) => script_paths.extend(
+                script_list
+                    .lines()
+                    .map(|line| line.trim())
+                    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                    .map(|line| line.to_string()),
+            ),
+            Err(error) => fail(
+                &script_list_path,
+                format!("Cannot read specified file {}: {}", script_list_path, error),
+                diagnostics_format,
+            ),
+        }
+    }
 
-    // Create the memory tracker
-    if app_wasm.memories.len() > 1 {
-        // TODO -- make this work with multi-memory
-        panic!("only single memory is supported")
-    };
-    let mut mem_tracker = MemoryTracker {
-        mem_id: 0,                     // Assuming the ID of the first memory is 0!
-        curr_mem_offset: 1_052_576, // Set default memory base address to DEFAULT + 4KB = 1048576 bytes + 4000 bytes = 1052576 bytes
-        required_initial_mem_size: 27, // Size memory must be to account for the added data
-        emitted_strings: HashMap::new(),
+    let mut scripts = vec![];
+    for script_path in script_paths.iter() {
+        match ScriptSource::from_file(script_path) {
+            Ok(script) => scripts.push(script),
+            Err(error) => fail(
+                script_path,
+                format!("Cannot read specified file {}: {}", script_path, error),
+                diagnostics_format,
+            ),
+        }
+    }
+    let script_paths = script_paths.join(", ");
+    let app_wasm = match std::fs::read(&app_wasm_path) {
+        Ok(app_wasm) => app_wasm,
+        Err(error) => fail(
+            &script_paths,
+            format!("Cannot read specified file {}: {}", app_wasm_path, error),
+            diagnostics_format,
+        ),
     };
 
-    // Phase 0 of instrumentation (emit globals and provided fns)
-    let mut init = InitGenerator {
-        emitter: ModuleEmitter::new(&mut app_wasm, &mut symbol_table, &mut mem_tracker),
-        context_name: "".to_string(),
-        err: &mut err,
+    let fn_filter = match FnFilter::new(&include_func, &exclude_func, &skip_imports_of) {
+        Ok(fn_filter) => fn_filter,
+        Err(e) => fail(&script_paths, e, diagnostics_format),
     };
-    init.run(&mut whamm);
-    // If there were any errors encountered, report and exit!
-    err.check_has_errors();
-
-    // Phase 1 of instrumentation (actually emits the instrumentation code)
-    // This structure is necessary since we need to have the fns/globals injected (a single time)
-    // and ready to use in every body/predicate.
-    let mut instr = InstrGenerator::new(
-        VisitingEmitter::new(&mut app_wasm, &mut symbol_table, &mem_tracker),
-        simple_ast,
-        &mut err,
-    );
-    instr.run();
-    // If there were any errors encountered, report and exit!
-    err.check_has_errors();
-
-    try_path(&output_wasm_path);
-    if let Err(e) = app_wasm.emit_wasm(&output_wasm_path) {
-        err.add_error(ErrorGen::get_unexpected_error(
-            true,
-            Some(format!(
-                "Failed to dump instrumented wasm to {} from error: {}",
-                &output_wasm_path, e
-            )),
-            None,
-        ))
+    let opts = Options {
+        core_module,
+        fn_filter,
+        ..Options::default()
+    };
+    match instrument(&app_wasm, &scripts, &opts) {
+        Ok(mut instrumented) => {
+            let output = match emit_format {
+                EmitFormat::Wasm => instrumented.wasm,
+                EmitFormat::Wat => match to_annotated_wat(&app_wasm, &instrumented.wasm) {
+                    Ok(wat) => wat.into_bytes(),
+                    Err(e) => fail(
+                        &script_paths,
+                        format!("Failed to print the instrumented wasm as WAT: {}", e),
+                        diagnostics_format,
+                    ),
+                },
+            };
+            try_path(&output_wasm_path);
+            if let Err(e) = std::fs::write(&output_wasm_path, output) {
+                fail(
+                    &script_paths,
+                    format!(
+                        "Failed to dump instrumented wasm to {} from error: {}",
+                        &output_wasm_path, e
+                    ),
+                    diagnostics_format,
+                );
+            }
+            if let Some(format) = report_format {
+                print!("{}", instrumented.report.render(format));
+            }
+            // Report any warnings, e.g. about probes that were never injected
+            emit_all(&mut instrumented.diagnostics, diagnostics_format);
+        }
+        Err(mut diagnostics) => {
+            // If there were any errors encountered, report and exit!
+            diagnostics.emit(diagnostics_format);
+            exit(1);
+        }
     }
-    // If there were any errors encountered, report and exit!
-    err.check_has_errors();
-}
-
-fn get_symbol_table(ast: &mut Whamm, err: &mut ErrorGen) -> SymbolTable {
-    let mut st = build_symbol_table(ast, err);
-    err.check_too_many();
-    verify_ast(ast, &mut st, err);
-    st
 }
 
-fn verify_ast(ast: &mut Whamm, st: &mut SymbolTable, err: &mut ErrorGen) {
-    if !type_check(ast, st, err) {
-        error!("AST failed verification!");
-    }
-    err.check_too_many();
-}
+fn run_fmt(script_paths: Vec<String>, check: bool, diagnostics_format: DiagnosticsFormat) {
+    let mut unformatted = vec![];
+    for script_path in script_paths {
+        let script = match std::fs::read_to_string(&script_path) {
+            Ok(script) => script,
+            Err(error) => fail(
+                &script_path,
+                format!("Cannot read specified file {}: {}", script_path, error),
+                diagnostics_format,
+            ),
+        };
 
-fn get_script_ast(script_path: &String, err: &mut ErrorGen) -> Whamm {
-    match std::fs::read_to_string(script_path) {
-        Ok(unparsed_str) => {
-            // Parse the script and build the AST
-            match parse_script(&unparsed_str, err) {
-                Some(ast) => {
-                    info!("successfully parsed");
-                    err.check_too_many();
-                    ast
-                }
-                None => {
-                    err.report();
-                    exit(1);
-                }
+        let mut err = ErrorGen::new(script_path.clone(), script.clone(), MAX_ERRORS);
+        let formatted = match format_script(&script, &mut err) {
+            Some(formatted) => formatted,
+            None => {
+                // Only well-formed scripts can be formatted
+                err.take_diagnostics().emit(diagnostics_format);
+                exit(1);
             }
+        };
+        if formatted == script {
+            continue;
         }
-        Err(error) => {
-            error!("Cannot read specified file {}: {}", script_path, error);
-            exit(1);
+
+        if check {
+            unformatted.push(script_path);
+        } else if let Err(e) = std::fs::write(&script_path, formatted) {
+            fail(
+                &script_path,
+                format!("Failed to write formatted script to {}: {}", script_path, e),
+                diagnostics_format,
+            );
+        }
+    }
+
+    Diagnostics::default().emit(diagnostics_format);
+    if !unformatted.is_empty() {
+        for script_path in unformatted.iter() {
+            println!("Not formatted: {}", script_path);
         }
+        exit(1);
     }
 }
+
+/// Report an error that isn't tied to the script's contents and exit.
+fn fail(script_path: &str, message: String, diagnostics_format: DiagnosticsFormat) -> ! {
+    exit_with(
+        &mut [error_diagnostics(script_path, message)],
+        diagnostics_format,
+    );
+}
+
+/// Report the diagnostics collected so far, then exit since they have an error.
+fn exit_with(diagnostics: &mut [Diagnostics], diagnostics_format: DiagnosticsFormat) -> ! {
+    emit_all(diagnostics, diagnostics_format);
+    exit(1);
+}
+
+/// The diagnostics for an error that isn't tied to the script's contents.
+fn error_diagnostics(script_path: &str, message: String) -> Diagnostics {
+    let mut err = ErrorGen::new(script_path.to_string(), "".to_string(), MAX_ERRORS);
+    err.add_error(ErrorGen::get_unexpected_error(false, Some(message), None));
+    err.take_diagnostics()
+}
@@ -1,3 +1,5 @@
+pub mod catalog;
+pub mod formatter;
 pub mod print_visitor;
 pub mod types;
 pub mod whamm_parser;
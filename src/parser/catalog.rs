@@ -0,0 +1,279 @@
+use crate::common::error::ErrorGen;
+use crate::parser::rules::{provider_factory, Event, Package, Probe, Provider, WhammProvider};
+use crate::parser::types::{
+    DataType, Definition, Expr, ProvidedFunction, ProvidedGlobal, SpecPart, Whamm,
+};
+use crate::parser::whamm_parser::parse_probe_spec;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use termcolor::Buffer;
+
+/// How `whamm info` writes out the information for a probe specification.
+#[derive(Clone, Copy, Debug, Default, PartialEq, clap::ValueEnum)]
+pub enum InfoFormat {
+    /// Colored text for a terminal
+    #[default]
+    Text,
+    /// The provider -> package -> event -> mode tree as a JSON object
+    Json,
+    /// The provider -> package -> event -> mode tree as a markdown document
+    Markdown,
+}
+
+/// Build the catalog of everything that the (possibly partial) probe specification matches:
+/// the provider -> package -> event -> mode tree along with the globals and functions that
+/// each part provides. Any parts missing from the end of the spec match everything.
+pub fn catalog(spec: &str, err: &mut ErrorGen) -> Option<Value> {
+    let mut probe_spec = parse_probe_spec(spec, err)?;
+    for part in [
+        &mut probe_spec.provider,
+        &mut probe_spec.package,
+        &mut probe_spec.event,
+        &mut probe_spec.mode,
+    ] {
+        if part.is_none() {
+            *part = Some(SpecPart {
+                name: "*".to_string(),
+                loc: None,
+            });
+        }
+    }
+
+    let mut providers: HashMap<String, Box<dyn Provider>> = HashMap::new();
+    if let Err(e) =
+        provider_factory::<WhammProvider>(&mut providers, &probe_spec, None, None, None, 0, true)
+    {
+        err.add_error(*e);
+        return None;
+    }
+
+    let whamm = Whamm::new();
+    let providers: Vec<Value> = sorted(providers.values(), |provider| provider.name())
+        .into_iter()
+        .map(|provider| {
+            let packages: Vec<Value> = sorted(provider.packages(), |package| package.name())
+                .into_iter()
+                .map(package_json)
+                .collect();
+            json!({
+                "name": provider.name(),
+                "docs": provider.docs(),
+                "globals": globals_json(provider.get_provided_globals()),
+                "functions": fns_json(provider.get_provided_fns()),
+                "packages": packages,
+            })
+        })
+        .collect();
+
+    Some(json!({
+        "whamm": {
+            "globals": globals_json(&whamm.globals),
+            "functions": fns_json(&whamm.fns),
+        },
+        "providers": providers,
+    }))
+}
+
+fn package_json(package: &dyn Package) -> Value {
+    let events: Vec<Value> = sorted(package.events(), |event| event.name())
+        .into_iter()
+        .map(event_json)
+        .collect();
+    json!({
+        "name": package.name(),
+        "docs": package.docs(),
+        "globals": globals_json(package.get_provided_globals()),
+        "functions": fns_json(package.get_provided_fns()),
+        "events": events,
+    })
+}
+
+fn event_json(event: &dyn Event) -> Value {
+    // Each mode holds a single probe when printing info, it documents the mode
+    let mut modes: Vec<&Box<dyn Probe>> = event
+        .probes()
+        .values()
+        .filter_map(|probes| probes.first())
+        .collect();
+    modes.sort_by_key(|probe| probe.mode_name());
+    let modes: Vec<Value> = modes
+        .into_iter()
+        .map(|probe| {
+            json!({
+                "name": probe.mode_name(),
+                "docs": probe.mode_docs(),
+                "globals": globals_json(probe.get_mode_provided_globals()),
+                "functions": fns_json(probe.get_mode_provided_fns()),
+            })
+        })
+        .collect();
+    json!({
+        "name": event.name(),
+        "docs": event.docs(),
+        "globals": globals_json(event.get_provided_globals()),
+        "functions": fns_json(event.get_provided_fns()),
+        "modes": modes,
+    })
+}
+
+fn sorted<T, K: Ord>(items: impl Iterator<Item = T>, key: impl FnMut(&T) -> K) -> Vec<T> {
+    let mut items: Vec<T> = items.collect();
+    items.sort_by_key(key);
+    items
+}
+
+fn globals_json(globals: &HashMap<String, ProvidedGlobal>) -> Vec<Value> {
+    sorted(globals.values(), |global| global.name.clone())
+        .into_iter()
+        .map(|ProvidedGlobal { name, docs, global }| {
+            json!({
+                "name": name,
+                "type": type_str(&global.ty),
+                "docs": docs,
+                "definition": definition_str(&global.def),
+            })
+        })
+        .collect()
+}
+
+fn fns_json(functions: &[ProvidedFunction]) -> Vec<Value> {
+    sorted(functions.iter(), |function| function.name.clone())
+        .into_iter()
+        .map(
+            |ProvidedFunction {
+                 name,
+                 docs,
+                 function,
+             }| {
+                let params: Vec<Value> = function
+                    .params
+                    .iter()
+                    .map(|(param, ty)| {
+                        let name = match param {
+                            Expr::VarId { name, .. } => name.clone(),
+                            _ => "".to_string(),
+                        };
+                        json!({ "name": name, "type": type_str(ty) })
+                    })
+                    .collect();
+                let mut signature = Buffer::no_color();
+                function.print(&mut signature);
+                json!({
+                    "name": name,
+                    "signature": String::from_utf8_lossy(signature.as_slice()).to_string(),
+                    "params": params,
+                    "return_type": type_str(&function.return_ty),
+                    "docs": docs,
+                    "definition": definition_str(&function.def),
+                })
+            },
+        )
+        .collect()
+}
+
+fn type_str(ty: &DataType) -> String {
+    let mut buffer = Buffer::no_color();
+    ty.print(&mut buffer);
+    String::from_utf8_lossy(buffer.as_slice()).to_string()
+}
+
+fn definition_str(def: &Definition) -> &str {
+    match def {
+        Definition::CompilerStatic => "static",
+        Definition::CompilerDynamic => "dynamic",
+        Definition::User => "user",
+    }
+}
+
+/// Render a catalog (see `catalog`) as a markdown document, one section per part of the spec.
+pub fn catalog_markdown(catalog: &Value) -> String {
+    let mut md = String::new();
+    md.push_str("# `whamm` #\n\n");
+    write_provided(&catalog["whamm"], &mut md);
+
+    let empty = vec![];
+    let list = |value: &Value, key: &str| value[key].as_array().unwrap_or(&empty).clone();
+    for provider in list(catalog, "providers") {
+        let provider_name = provider["name"].as_str().unwrap_or_default().to_string();
+        write_section(1, &provider_name, "provider", &provider, &mut md);
+
+        for package in list(&provider, "packages") {
+            let package_name = format!(
+                "{}:{}",
+                provider_name,
+                package["name"].as_str().unwrap_or_default()
+            );
+            write_section(2, &package_name, "package", &package, &mut md);
+
+            for event in list(&package, "events") {
+                let event_name = format!(
+                    "{}:{}",
+                    package_name,
+                    event["name"].as_str().unwrap_or_default()
+                );
+                write_section(3, &event_name, "event", &event, &mut md);
+
+                for mode in list(&event, "modes") {
+                    let mode_name = format!(
+                        "{}:{}",
+                        event_name,
+                        mode["name"].as_str().unwrap_or_default()
+                    );
+                    write_section(4, &mode_name, "mode", &mode, &mut md);
+                }
+            }
+        }
+    }
+    md
+}
+
+fn write_section(level: usize, name: &str, part: &str, value: &Value, md: &mut String) {
+    let hashes = "#".repeat(level);
+    md.push_str(&format!("{hashes} `{name}` {part} {hashes}\n\n"));
+    let docs = value["docs"].as_str().unwrap_or_default();
+    if !docs.is_empty() {
+        md.push_str(&format!("{}\n\n", docs));
+    }
+    write_provided(value, md);
+}
+
+/// Write the globals and functions of a part as tables
+fn write_provided(value: &Value, md: &mut String) {
+    let empty = vec![];
+    let globals = value["globals"].as_array().unwrap_or(&empty);
+    if !globals.is_empty() {
+        md.push_str("| global | type | definition | docs |\n|---|---|---|---|\n");
+        for global in globals {
+            md.push_str(&format!(
+                "| `{}` | `{}` | {} | {} |\n",
+                global["name"].as_str().unwrap_or_default(),
+                global["type"].as_str().unwrap_or_default(),
+                global["definition"].as_str().unwrap_or_default(),
+                table_cell(global["docs"].as_str().unwrap_or_default()),
+            ));
+        }
+        md.push('\n');
+    }
+
+    let functions = value["functions"].as_array().unwrap_or(&empty);
+    if !functions.is_empty() {
+        md.push_str("| function | definition | docs |\n|---|---|---|\n");
+        for function in functions {
+            md.push_str(&format!(
+                "| `{}` | {} | {} |\n",
+                function["signature"].as_str().unwrap_or_default(),
+                function["definition"].as_str().unwrap_or_default(),
+                table_cell(function["docs"].as_str().unwrap_or_default()),
+            ));
+        }
+        md.push('\n');
+    }
+}
+
+/// Docs can span lines and contain pipes, neither of which fit in a table cell
+fn table_cell(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .replace('|', "\\|")
+}
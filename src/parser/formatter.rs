@@ -0,0 +1,424 @@
+use crate::common::error::ErrorGen;
+use crate::parser::types::{Rule, WhammParser};
+use crate::parser::whamm_parser::check_syntax;
+use log::trace;
+use pest::iterators::Pair;
+use pest::Parser;
+
+const INDENT: &str = "    ";
+
+/// Pretty-print a script with canonical indentation and spacing, keeping its comments.
+///
+/// This works on the pest parse tree rather than the `Whamm` AST since the AST doesn't keep
+/// the order of the script's items (probes are distributed across the matched providers).
+/// Comments are skipped by the `script` rule, so they're found separately with the `trivia`
+/// rule and put back in by their position in the source.
+pub fn format_script(script: &str, err: &mut ErrorGen) -> Option<String> {
+    trace!("Entered format_script");
+    err.set_script_text(script.to_owned());
+
+    let parsed = WhammParser::parse(Rule::script, script).and_then(|mut pairs| {
+        let trivia = WhammParser::parse(Rule::trivia, script)?;
+        Ok((pairs.next().unwrap(), trivia))
+    });
+    let (root, trivia) = match parsed {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            err.pest_err(e);
+            return None;
+        }
+    };
+    // Code skipped by the error recovery rules would be lost
+    if !check_syntax(script, &root, err) {
+        return None;
+    }
+
+    let comments = trivia
+        .flatten()
+        .filter(|pair| pair.as_rule() == Rule::comment)
+        .map(|pair| Comment {
+            start: pair.as_span().start(),
+            end: pair.as_span().end(),
+            text: pair.as_str(),
+        })
+        .collect();
+
+    let mut formatter = Formatter {
+        src: script,
+        comments,
+        next_comment: 0,
+        out: "".to_string(),
+        indent: 0,
+        last_end: 0,
+        at_block_start: true,
+    };
+    formatter.script(root);
+    Some(formatter.out)
+}
+
+struct Comment<'a> {
+    start: usize,
+    end: usize,
+    text: &'a str,
+}
+
+struct Formatter<'a> {
+    src: &'a str,
+    comments: Vec<Comment<'a>>,
+    next_comment: usize,
+    out: String,
+    indent: usize,
+    /// Where the last thing written out ended in the source
+    last_end: usize,
+    /// Whether nothing has been written since opening a block (or the file)
+    at_block_start: bool,
+}
+
+impl Formatter<'_> {
+    // =================
+    // = Lines of code =
+    // =================
+
+    /// Write a line of code for the source at `start..end`, after the comments before it.
+    fn line(&mut self, start: usize, end: usize, text: &str) {
+        self.comments_before(start);
+        self.blank_line_before(start);
+        self.push_line(text);
+        self.last_end = end;
+        self.at_block_start = text.ends_with('{');
+    }
+
+    /// Write the line closing a block whose `}` is at `brace`, comments at the end of the
+    /// block's body are kept inside the block.
+    fn close(&mut self, brace: usize, text: &str) {
+        self.indent += 1;
+        self.comments_before(brace);
+        self.indent -= 1;
+        self.push_line(text);
+        self.last_end = brace + 1;
+        self.at_block_start = text.ends_with('{');
+    }
+
+    fn push_line(&mut self, text: &str) {
+        self.out += &INDENT.repeat(self.indent);
+        self.out += text;
+        self.out += "\n";
+    }
+
+    /// Collapse any number of blank lines in the source down to one, but never start a
+    /// block with a blank line.
+    fn blank_line_before(&mut self, start: usize) {
+        if self.at_block_start || start < self.last_end {
+            return;
+        }
+        if self.src[self.last_end..start].matches('\n').count() >= 2 {
+            self.out += "\n";
+        }
+    }
+
+    fn comments_before(&mut self, pos: usize) {
+        while let Some(comment) = self.comments.get(self.next_comment) {
+            if comment.start >= pos {
+                break;
+            }
+            let (start, end, text) = (comment.start, comment.end, comment.text);
+            self.next_comment += 1;
+
+            // A comment following code on the same line stays at the end of that line
+            let line_start = self.src[..start].rfind('\n').map_or(0, |i| i + 1);
+            let is_trailing = !self.src[line_start..start].trim().is_empty();
+            if is_trailing && self.out.ends_with('\n') {
+                self.out.pop();
+                self.out += " ";
+                self.out += text;
+                self.out += "\n";
+            } else {
+                self.blank_line_before(start);
+                self.push_line(text);
+                self.at_block_start = false;
+            }
+            self.last_end = end;
+        }
+    }
+
+    fn has_comments_before(&self, pos: usize) -> bool {
+        self.comments
+            .get(self.next_comment)
+            .is_some_and(|comment| comment.start < pos)
+    }
+
+    // =========
+    // = Items =
+    // =========
+
+    fn script(&mut self, root: Pair<Rule>) {
+        for pair in root.into_inner() {
+            match pair.as_rule() {
+                Rule::statement => self.statement(pair),
+                Rule::fn_def => self.fn_def(pair),
+                Rule::probe_def => self.probe_def(pair),
+                _ => {}
+            }
+        }
+        self.comments_before(self.src.len());
+    }
+
+    fn fn_def(&mut self, pair: Pair<Rule>) {
+        let start = pair.as_span().start();
+        let mut name = "";
+        let mut params = vec![];
+        let mut return_ty = None;
+        let mut body = None;
+        for part in pair.into_inner() {
+            match part.as_rule() {
+                Rule::ID => name = part.as_str(),
+                Rule::param => {
+                    let mut inner = part.into_inner();
+                    let ty = ty(inner.next().unwrap());
+                    params.push(format!("{} {}", ty, inner.next().unwrap().as_str()));
+                }
+                Rule::block => body = Some(part),
+                _ => return_ty = Some(ty(part)),
+            }
+        }
+
+        let mut header = format!("{}({})", name, params.join(", "));
+        if let Some(return_ty) = return_ty {
+            header += &format!(" -> {}", return_ty);
+        }
+        let body = body.unwrap();
+        let span = body.as_span();
+        self.body(
+            start,
+            &header,
+            span.start(),
+            span.end() - 1,
+            body.into_inner(),
+        );
+    }
+
+    fn probe_def(&mut self, pair: Pair<Rule>) {
+        let span = pair.as_span();
+        let mut header = "".to_string();
+        let mut open = span.start();
+        let mut stmts = vec![];
+        for part in pair.into_inner() {
+            match part.as_rule() {
+                Rule::priority => {
+                    let val = part.clone().into_inner().next().unwrap();
+                    header += &format!("@priority({}) ", val.as_str());
+                    open = part.as_span().end();
+                }
+                Rule::PROBE_SPEC => {
+                    header += part.as_str();
+                    open = part.as_span().end();
+                }
+                Rule::predicate => {
+                    let expr = part.clone().into_inner().next().unwrap();
+                    header += &format!(" / {} /", value(expr));
+                    open = part.as_span().end();
+                }
+                _ => stmts.push(part),
+            }
+        }
+
+        // The `{` comes right after the spec and predicate
+        let open = open + self.src[open..].find('{').unwrap_or(0);
+        self.body(
+            span.start(),
+            &header,
+            open,
+            span.end() - 1,
+            stmts.into_iter(),
+        );
+    }
+
+    /// Write a `header { statements }` item, bodies without statements or comments are kept
+    /// on a single line.
+    fn body<'i>(
+        &mut self,
+        start: usize,
+        header: &str,
+        open: usize,
+        close: usize,
+        stmts: impl Iterator<Item = Pair<'i, Rule>>,
+    ) {
+        let mut stmts = stmts.peekable();
+        if stmts.peek().is_none() {
+            self.comments_before(open + 1);
+            if !self.has_comments_before(close) {
+                self.line(start, close + 1, &format!("{} {{}}", header));
+                return;
+            }
+        }
+        self.line(start, open + 1, &format!("{} {{", header));
+        self.indent += 1;
+        stmts.for_each(|stmt| self.statement(stmt));
+        self.indent -= 1;
+        self.close(close, "}");
+    }
+
+    // ==============
+    // = Statements =
+    // ==============
+
+    /// A `statement` holds one or more `;`-terminated statements, each gets its own line.
+    fn statement(&mut self, pair: Pair<Rule>) {
+        for stmt in pair.into_inner() {
+            let span = stmt.as_span();
+            match stmt.as_rule() {
+                Rule::if_stmt => self.if_stmt(stmt),
+                _ => self.line(span.start(), span.end(), &format!("{};", simple_stmt(stmt))),
+            }
+        }
+    }
+
+    fn if_stmt(&mut self, pair: Pair<Rule>) {
+        let mut header_start = pair.as_span().start();
+        let mut keyword = "if";
+        let mut curr = Some(pair);
+        while let Some(branch) = curr.take() {
+            let mut inner = branch.into_inner();
+            let first = inner.next().unwrap();
+            let (header, block) = match first.as_rule() {
+                // else_stmt
+                Rule::block => (keyword.to_string(), first),
+                // if_stmt or elif
+                _ => (
+                    format!("{} ({})", keyword, value(first)),
+                    inner.next().unwrap(),
+                ),
+            };
+
+            let span = block.as_span();
+            let (open, close) = (span.start(), span.end() - 1);
+            if keyword == "if" {
+                self.line(header_start, open + 1, &format!("{} {{", header));
+            } else {
+                self.close(header_start, &format!("}} {} {{", header));
+            }
+            self.indent += 1;
+            block.into_inner().for_each(|stmt| self.statement(stmt));
+            self.indent -= 1;
+
+            curr = inner.next().map(|next| {
+                keyword = match next.as_rule() {
+                    Rule::elif => "elif",
+                    _ => "else",
+                };
+                next
+            });
+            header_start = close;
+        }
+        // The `if` is a statement, so it still needs its `;`
+        self.close(header_start, "};");
+    }
+}
+
+// ==============================
+// = Single-line pieces of code =
+// ==============================
+
+fn simple_stmt(pair: Pair<Rule>) -> String {
+    let rule = pair.as_rule();
+    if rule == Rule::fn_call {
+        return value(pair);
+    }
+    let parts: Vec<Pair<Rule>> = pair.into_inner().collect();
+    match rule {
+        Rule::declaration => format!("{} {}", ty(parts[0].clone()), parts[1].as_str()),
+        Rule::initialize => format!(
+            "{} {} = {}",
+            ty(parts[0].clone()),
+            parts[1].as_str(),
+            value(parts[2].clone())
+        ),
+        Rule::assignment => format!("{} = {}", parts[0].as_str(), value(parts[1].clone())),
+        Rule::incrementor => format!("{}++", parts[0].as_str()),
+        Rule::decrementor => format!("{}--", parts[0].as_str()),
+        Rule::ret => match parts.first() {
+            Some(expr) => format!("return {}", value(expr.clone())),
+            None => "return".to_string(),
+        },
+        Rule::aggregation => {
+            let mut s = "".to_string();
+            for part in parts {
+                match part.as_rule() {
+                    Rule::AGG_ID => s += part.as_str(),
+                    Rule::agg_keys => s += &format!("[{}]", values(part)),
+                    _ => {
+                        let mut call = part.into_inner();
+                        let agg_fn = call.next().unwrap().as_str();
+                        let args: Vec<String> = call.map(value).collect();
+                        s += &format!(" = {}({})", agg_fn, args.join(", "));
+                    }
+                }
+            }
+            s
+        }
+        _ => unreachable!("not a single-line statement: {:?}", rule),
+    }
+}
+
+fn ty(pair: Pair<Rule>) -> String {
+    match pair.as_rule() {
+        Rule::TY_TUPLE => format!(
+            "({})",
+            pair.into_inner().map(ty).collect::<Vec<_>>().join(", ")
+        ),
+        Rule::TY_MAP => {
+            let mut inner = pair.into_inner();
+            let key = ty(inner.next().unwrap());
+            format!("map<{}, {}>", key, ty(inner.next().unwrap()))
+        }
+        _ => pair.as_str().to_string(),
+    }
+}
+
+fn values(pair: Pair<Rule>) -> String {
+    pair.into_inner().map(value).collect::<Vec<_>>().join(", ")
+}
+
+/// Expressions, ternaries, calls and values.
+fn value(pair: Pair<Rule>) -> String {
+    match pair.as_rule() {
+        Rule::expr => {
+            let mut s = "".to_string();
+            for part in pair.into_inner() {
+                match part.as_rule() {
+                    Rule::neg => s += "!",
+                    Rule::and
+                    | Rule::or
+                    | Rule::eq
+                    | Rule::ne
+                    | Rule::ge
+                    | Rule::gt
+                    | Rule::le
+                    | Rule::lt
+                    | Rule::add
+                    | Rule::subtract
+                    | Rule::multiply
+                    | Rule::divide
+                    | Rule::modulo => s += &format!(" {} ", part.as_str()),
+                    // A nested expr is a parenthesized operand
+                    Rule::expr => s += &format!("({})", value(part)),
+                    _ => s += &value(part),
+                }
+            }
+            s
+        }
+        Rule::ternary => {
+            let parts: Vec<String> = pair.into_inner().map(value).collect();
+            format!("{} ? {} : {}", parts[0], parts[1], parts[2])
+        }
+        Rule::arg => value(pair.into_inner().next().unwrap()),
+        Rule::fn_call => {
+            let mut inner = pair.into_inner();
+            let name = inner.next().unwrap().as_str();
+            let args: Vec<String> = inner.map(value).collect();
+            format!("{}({})", name, args.join(", "))
+        }
+        Rule::tuple => format!("({})", values(pair)),
+        _ => pair.as_str().to_string(),
+    }
+}
@@ -3,8 +3,8 @@ use parser_types::WhammVisitor;
 
 use crate::parser::rules::{Event, Package, Probe, Provider};
 use crate::parser::types::{
-    BinOp, Block, DataType, Expr, Global, ProvidedFunction, ProvidedGlobal, Script, Statement,
-    UnOp, Value, Whamm,
+    AggFn, BinOp, Block, DataType, Expr, Global, ProvidedFunction, ProvidedGlobal, Script,
+    Statement, UnOp, Value, Whamm,
 };
 use std::cmp;
 use std::collections::HashMap;
@@ -413,6 +413,25 @@ impl WhammVisitor<String> for AsStrVisitor {
                 s += &format!("{} }}", self.get_indent());
                 s
             }
+            Statement::Aggregate {
+                name,
+                keys,
+                func,
+                args,
+                ..
+            } => {
+                let mut s = format!("@{name}");
+                if !keys.is_empty() {
+                    let keys: Vec<String> = keys.iter().map(|key| self.visit_expr(key)).collect();
+                    s += &format!("[{}]", keys.join(", "));
+                }
+                let mut args: Vec<String> = args.iter().map(|arg| self.visit_expr(arg)).collect();
+                if let AggFn::LQuantize { lo, hi, step } = func {
+                    args.extend([lo.to_string(), hi.to_string(), step.to_string()]);
+                }
+                s += &format!(" = {}({})", func.name(), args.join(", "));
+                s
+            }
         }
     }
 
@@ -497,6 +516,9 @@ impl WhammVisitor<String> for AsStrVisitor {
         match datatype {
             DataType::I32 => "i32".to_string(),
             DataType::U32 => "u32".to_string(),
+            DataType::I64 => "i64".to_string(),
+            DataType::F32 => "f32".to_string(),
+            DataType::F64 => "f64".to_string(),
             DataType::Boolean => "bool".to_string(),
             DataType::Null => "null".to_string(),
             DataType::Str => "str".to_string(),
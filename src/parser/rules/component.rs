@@ -0,0 +1,482 @@
+use crate::parser::rules::{
+    event_factory, mode_factory, Event, EventInfo, FromStr, Mode, NameOptions, Package,
+    PackageInfo, Probe, WhammMode, WhammProbe,
+};
+use crate::parser::types::{
+    Block, DataType, Expr, Location, ProbeSpec, ProvidedFunction, ProvidedGlobal,
+};
+use std::collections::HashMap;
+use termcolor::Buffer;
+
+pub enum ComponentPackageKind {
+    Canon,
+}
+impl ComponentPackageKind {
+    fn name(&self) -> String {
+        match self {
+            Self::Canon => "canon".to_string(),
+        }
+    }
+}
+
+pub struct ComponentPackage {
+    kind: ComponentPackageKind,
+    info: PackageInfo,
+}
+impl NameOptions for ComponentPackage {
+    fn get_name_options() -> Vec<String> {
+        // Violates DRY principle, but works for now.
+        // Maybe make this better some other time.
+        vec!["canon".to_string()]
+    }
+}
+impl FromStr for ComponentPackage {
+    fn from_str(name: String, loc: Option<Location>) -> Self {
+        match name.as_str() {
+            "canon" => Self::canon(loc),
+            _ => panic!("unsupported ComponentPackage: {name}"),
+        }
+    }
+}
+impl ComponentPackage {
+    // =========================
+    // ---- Globals Helpers ----
+    // =========================
+
+    fn canon_globals() -> HashMap<String, ProvidedGlobal> {
+        let mut globals = HashMap::new();
+        globals.insert(
+            "core_module".to_string(),
+            ProvidedGlobal::new(
+                "core_module".to_string(),
+                "The index of the core module containing the probed instruction, \
+                in the order the core modules appear in the component."
+                    .to_string(),
+                DataType::I32,
+                true,
+            ),
+        );
+
+        // the location of the probed instruction in its core module
+        globals.insert(
+            "fn_id".to_string(),
+            ProvidedGlobal::new(
+                "fn_id".to_string(),
+                "The ID of the function containing the probed instruction.".to_string(),
+                DataType::I32,
+                true,
+            ),
+        );
+        globals.insert(
+            "fn_name".to_string(),
+            ProvidedGlobal::new(
+                "fn_name".to_string(),
+                "The name of the function containing the probed instruction. \
+                Taken from the `name` section, falling back to the export name. \
+                Evaluates to an empty string if the function has no name."
+                    .to_string(),
+                DataType::Str,
+                true,
+            ),
+        );
+        globals.insert(
+            "instr_idx".to_string(),
+            ProvidedGlobal::new(
+                "instr_idx".to_string(),
+                "The index of the probed instruction within its function's body.".to_string(),
+                DataType::I32,
+                true,
+            ),
+        );
+        globals.insert(
+            "pc".to_string(),
+            ProvidedGlobal::new(
+                "pc".to_string(),
+                "The byte offset of the probed instruction, \
+                relative to the start of its core module's code section."
+                    .to_string(),
+                DataType::I32,
+                true,
+            ),
+        );
+        globals.insert(
+            "src_file".to_string(),
+            ProvidedGlobal::new(
+                "src_file".to_string(),
+                "The path of the source file that the probed instruction was compiled from. \
+                Taken from the DWARF `.debug_line` section, \
+                evaluates to an empty string if there is no line information for the instruction. \
+                Can only be compared to a whole path, there is no glob or prefix matching."
+                    .to_string(),
+                DataType::Str,
+                true,
+            ),
+        );
+        globals.insert(
+            "src_line".to_string(),
+            ProvidedGlobal::new(
+                "src_line".to_string(),
+                "The line in `src_file` that the probed instruction was compiled from, \
+                0 if it is unknown."
+                    .to_string(),
+                DataType::I32,
+                true,
+            ),
+        );
+        globals.insert(
+            "src_col".to_string(),
+            ProvidedGlobal::new(
+                "src_col".to_string(),
+                "The column in `src_file` that the probed instruction was compiled from, \
+                0 if it is unknown or the instruction is attributed to the whole line."
+                    .to_string(),
+                DataType::I32,
+                true,
+            ),
+        );
+        globals.insert(
+            "local[0:9]+".to_string(),
+            ProvidedGlobal::new(
+                "local[0:9]+".to_string(),
+                "The local at the specific index, e.g. [0:9]+, of the function containing the \
+                probed instruction (params come first). Can be read and assigned to. \
+                Its type is the type declared by that function."
+                    .to_string(),
+                DataType::AssumeGood,
+                false,
+            ),
+        );
+
+        globals
+    }
+
+    // ======================
+    // ---- Constructors ----
+    // ======================
+
+    fn canon(loc: Option<Location>) -> Self {
+        Self {
+            kind: ComponentPackageKind::Canon,
+            info: PackageInfo {
+                docs: "The canonical ABI functions that connect a component to its core modules: \
+                    the imports of the component are lowered into the core modules and their \
+                    exports are lifted to become the exports of the component. \
+                    The instantiations of the component aren't resolved, so every function \
+                    import of a core module is treated as lowered and every function export \
+                    as lifted."
+                    .to_string(),
+                fns: vec![],
+                globals: Self::canon_globals(),
+                loc,
+                events: HashMap::new(),
+            },
+        }
+    }
+}
+impl Package for ComponentPackage {
+    // ==========================
+    // ---- Instance Methods ----
+    // ==========================
+
+    fn name(&self) -> String {
+        self.kind.name()
+    }
+
+    fn docs(&self) -> &String {
+        &self.info.docs
+    }
+
+    fn loc(&self) -> &Option<Location> {
+        &self.info.loc
+    }
+
+    fn has_events(&self) -> bool {
+        !self.info.events.is_empty()
+    }
+
+    fn len_events(&self) -> usize {
+        self.info.events.len()
+    }
+
+    fn events(&self) -> Box<dyn Iterator<Item = &dyn Event> + '_> {
+        Box::new(self.info.events.values().map(|e| e.as_ref() as &dyn Event))
+    }
+
+    fn events_mut(&mut self) -> Box<dyn Iterator<Item = &mut dyn Event> + '_> {
+        Box::new(
+            self.info
+                .events
+                .values_mut()
+                .map(|e| e.as_mut() as &mut dyn Event),
+        )
+    }
+
+    fn print_event_docs(
+        &self,
+        print_globals: bool,
+        print_functions: bool,
+        tabs: &mut usize,
+        buffer: &mut Buffer,
+    ) {
+        for (.., event) in self.info.events.iter() {
+            crate::parser::rules::print_event_docs(
+                event,
+                print_globals,
+                print_functions,
+                tabs,
+                buffer,
+            );
+        }
+    }
+
+    fn print_mode_docs(
+        &self,
+        print_globals: bool,
+        print_functions: bool,
+        tabs: &mut usize,
+        buffer: &mut Buffer,
+    ) {
+        for (.., event) in self.info.events.iter() {
+            event.print_mode_docs(print_globals, print_functions, tabs, buffer);
+        }
+    }
+
+    fn get_provided_fns(&self) -> &Vec<ProvidedFunction> {
+        &self.info.fns
+    }
+
+    fn get_provided_fns_mut(&mut self) -> &mut Vec<ProvidedFunction> {
+        &mut self.info.fns
+    }
+
+    fn get_provided_globals(&self) -> &HashMap<String, ProvidedGlobal> {
+        &self.info.globals
+    }
+
+    fn assign_matching_events(
+        &mut self,
+        probe_spec: &ProbeSpec,
+        loc: Option<Location>,
+        predicate: Option<Expr>,
+        body: Option<Block>,
+        priority: i32,
+        printing_info: bool,
+    ) -> (bool, bool) {
+        match self {
+            Self {
+                kind: ComponentPackageKind::Canon,
+                ..
+            } => event_factory::<CanonEvent>(
+                &mut self.info.events,
+                probe_spec,
+                loc,
+                predicate,
+                body,
+                priority,
+                printing_info,
+            ),
+        }
+    }
+}
+
+pub enum CanonEventKind {
+    Lower,
+    Lift,
+}
+impl CanonEventKind {
+    pub fn name(&self) -> String {
+        match self {
+            Self::Lower => "lower".to_string(),
+            Self::Lift => "lift".to_string(),
+        }
+    }
+
+    /// A lifted function is probed at its entry, there's no instruction to run `after` or
+    /// replace with an `alt` probe.
+    fn supports_mode(&self, mode: &str) -> bool {
+        match self {
+            Self::Lower => true,
+            Self::Lift => mode == "before",
+        }
+    }
+}
+
+pub struct CanonEvent {
+    kind: CanonEventKind,
+    info: EventInfo,
+}
+impl NameOptions for CanonEvent {
+    fn get_name_options() -> Vec<String> {
+        // Violates DRY principle, but works for now.
+        // Maybe make this better some other time.
+        vec!["lower".to_string(), "lift".to_string()]
+    }
+}
+impl FromStr for CanonEvent {
+    fn from_str(name: String, loc: Option<Location>) -> Self {
+        match name.as_str() {
+            "lower" => Self::lower(loc),
+            "lift" => Self::lift(loc),
+            _ => panic!("unsupported CanonEvent: {name}"),
+        }
+    }
+}
+impl CanonEvent {
+    // ======================
+    // ---- Constructors ----
+    // ======================
+
+    fn lower(loc: Option<Location>) -> Self {
+        let mut globals = HashMap::new();
+        globals.insert(
+            "imp_module".to_string(),
+            ProvidedGlobal::new(
+                "imp_module".to_string(),
+                "The module of the called import, for the interfaces of a component this is \
+                the interface name (e.g. `wasi:cli/environment@0.2.0`)."
+                    .to_string(),
+                DataType::Str,
+                true,
+            ),
+        );
+        globals.insert(
+            "imp_name".to_string(),
+            ProvidedGlobal::new(
+                "imp_name".to_string(),
+                "The name of the called import (e.g. `get-environment`).".to_string(),
+                DataType::Str,
+                true,
+            ),
+        );
+        globals.insert(
+            "arg[0:9]+".to_string(),
+            ProvidedGlobal::new(
+                "arg[0:9]+".to_string(),
+                "The argument to the call at the specific index, e.g. [0:9]+, as it is passed \
+                to the lowered function (flattened by the canonical ABI). \
+                Its type is the parameter type of the import called at each probed site."
+                    .to_string(),
+                DataType::AssumeGood,
+                false,
+            ),
+        );
+
+        Self {
+            kind: CanonEventKind::Lower,
+            info: EventInfo {
+                docs: "A call from a core module to a function that the component lowers into \
+                    it, i.e. a call to one of the core module's function imports."
+                    .to_string(),
+                fns: vec![],
+                globals,
+                loc,
+                probe_map: HashMap::new(),
+            },
+        }
+    }
+    fn lift(loc: Option<Location>) -> Self {
+        let mut globals = HashMap::new();
+        globals.insert(
+            "exp_name".to_string(),
+            ProvidedGlobal::new(
+                "exp_name".to_string(),
+                "The name the core function is exported under, \
+                for the interfaces of a component this is prefixed with the interface name \
+                (e.g. `wasi:http/incoming-handler@0.2.0#handle`)."
+                    .to_string(),
+                DataType::Str,
+                true,
+            ),
+        );
+
+        Self {
+            kind: CanonEventKind::Lift,
+            info: EventInfo {
+                docs: "The entry of a core function that the component lifts, i.e. one of the \
+                    core module's function exports. Only `before` probes can be placed here, \
+                    they run before the function's first instruction and can read its params \
+                    with `local[0:9]+`."
+                    .to_string(),
+                fns: vec![],
+                globals,
+                loc,
+                probe_map: HashMap::new(),
+            },
+        }
+    }
+}
+impl Event for CanonEvent {
+    fn name(&self) -> String {
+        self.kind.name()
+    }
+
+    fn loc(&self) -> &Option<Location> {
+        &self.info.loc
+    }
+
+    fn docs(&self) -> &String {
+        &self.info.docs
+    }
+
+    fn probes(&self) -> &HashMap<String, Vec<Box<dyn Probe>>> {
+        &self.info.probe_map
+    }
+
+    fn probes_mut(&mut self) -> &mut HashMap<String, Vec<Box<dyn Probe>>> {
+        &mut self.info.probe_map
+    }
+
+    fn print_mode_docs(
+        &self,
+        print_globals: bool,
+        print_functions: bool,
+        tabs: &mut usize,
+        buffer: &mut Buffer,
+    ) {
+        for (.., probes) in self.info.probe_map.iter() {
+            if let Some(probe) = probes.iter().next() {
+                // only print out the docs for some probe type one time!
+                probe.print_mode_docs(print_globals, print_functions, tabs, buffer);
+            }
+        }
+    }
+
+    fn get_provided_fns(&self) -> &Vec<ProvidedFunction> {
+        &self.info.fns
+    }
+
+    fn get_provided_fns_mut(&mut self) -> &mut Vec<ProvidedFunction> {
+        &mut self.info.fns
+    }
+
+    fn get_provided_globals(&self) -> &HashMap<String, ProvidedGlobal> {
+        &self.info.globals
+    }
+
+    fn assign_matching_modes(
+        &mut self,
+        probe_spec: &ProbeSpec,
+        loc: Option<Location>,
+        predicate: Option<Expr>,
+        body: Option<Block>,
+        priority: i32,
+    ) -> bool {
+        let mut matched_modes = false;
+        let modes: Vec<Box<WhammMode>> = mode_factory(probe_spec, loc.clone());
+        for mode in modes {
+            if !self.kind.supports_mode(&mode.name()) {
+                continue;
+            }
+            matched_modes = true;
+            let modes = self.info.probe_map.entry(mode.name()).or_default();
+            modes.push(Box::new(WhammProbe::new(
+                *mode,
+                loc.clone(),
+                predicate.clone(),
+                body.clone(),
+                priority,
+            )));
+        }
+        matched_modes
+    }
+}
@@ -136,6 +136,7 @@ impl Package for CorePackage {
         loc: Option<Location>,
         predicate: Option<Expr>,
         body: Option<Block>,
+        priority: i32,
         printing_info: bool,
     ) -> (bool, bool) {
         match self {
@@ -148,6 +149,7 @@ impl Package for CorePackage {
                 loc,
                 predicate,
                 body,
+                priority,
                 printing_info,
             ),
         }
@@ -256,6 +258,7 @@ impl Event for CoreEvent {
         loc: Option<Location>,
         predicate: Option<Expr>,
         body: Option<Block>,
+        priority: i32,
     ) -> bool {
         let mut matched_modes = false;
         let probes = self.probes_mut();
@@ -268,6 +271,7 @@ impl Event for CoreEvent {
                 loc.clone(),
                 predicate.clone(),
                 body.clone(),
+                priority,
             )));
         }
         matched_modes
@@ -363,11 +367,21 @@ struct CoreProbe {
 
     pub predicate: Option<Expr>,
     pub body: Option<Block>,
+    pub priority: i32,
 }
 impl Probe for CoreProbe {
     fn mode_name(&self) -> String {
         self.mode.name()
     }
+    fn mode_docs(&self) -> &String {
+        self.mode.docs()
+    }
+    fn loc(&self) -> &Option<Location> {
+        &self.loc
+    }
+    fn priority(&self) -> i32 {
+        self.priority
+    }
     fn predicate(&self) -> &Option<Expr> {
         &self.predicate
     }
@@ -411,12 +425,14 @@ impl CoreProbe {
         loc: Option<Location>,
         predicate: Option<Expr>,
         body: Option<Block>,
+        priority: i32,
     ) -> Self {
         Self {
             mode,
             loc,
             predicate,
             body,
+            priority,
         }
     }
 }
@@ -1,8 +1,11 @@
 #![allow(clippy::borrowed_box)]
+pub mod component;
 pub mod core;
 pub mod wasm;
 use crate::common::error::{ErrorGen, WhammError};
+use crate::common::suggest::closest_match;
 use crate::common::terminal::{magenta_italics, white};
+use crate::parser::rules::component::ComponentPackage;
 use crate::parser::rules::core::CorePackage;
 use crate::parser::rules::wasm::WasmPackage;
 use crate::parser::types::{
@@ -61,6 +64,7 @@ pub trait Provider {
         loc: Option<Location>,
         predicate: Option<Expr>,
         body: Option<Block>,
+        priority: i32,
         printing_info: bool,
     ) -> (bool, bool, bool);
 }
@@ -75,6 +79,7 @@ pub fn provider_factory<P: Provider + NameOptions + FromStr + 'static>(
     loc: Option<Location>,
     predicate: Option<Expr>,
     body: Option<Block>,
+    priority: i32,
     printing_info: bool,
 ) -> Result<(bool, bool, bool, bool), Box<WhammError>> {
     if let Some(SpecPart {
@@ -82,16 +87,20 @@ pub fn provider_factory<P: Provider + NameOptions + FromStr + 'static>(
         loc: provider_loc,
     }) = &probe_spec.provider
     {
-        let matches = get_matches(P::get_name_options(), provider_patt);
+        let options = P::get_name_options();
+        let matches = get_matches(options.clone(), provider_patt);
         if matches.is_empty() {
             let loc = provider_loc.as_ref().map(|loc| loc.line_col.clone());
-            return Err(Box::new(ErrorGen::get_parse_error(
-                true,
-                Some("Could not find any matches for the provider pattern".to_string()),
-                loc,
-                vec![],
-                vec![],
-            )));
+            return Err(Box::new(
+                ErrorGen::get_parse_error(
+                    true,
+                    Some("Could not find any matches for the provider pattern".to_string()),
+                    loc,
+                    vec![],
+                    vec![],
+                )
+                .with_suggestion(closest_match(provider_patt, &options)),
+            ));
         }
 
         let mut matched_providers = false;
@@ -115,6 +124,7 @@ pub fn provider_factory<P: Provider + NameOptions + FromStr + 'static>(
                     package_loc.to_owned(),
                     predicate.clone(),
                     body.clone(),
+                    priority,
                     printing_info,
                 )
             } else {
@@ -141,53 +151,47 @@ pub fn provider_factory<P: Provider + NameOptions + FromStr + 'static>(
                 vec![],
             )));
         }
-        if !matched_packages && probe_spec.package.is_some() {
-            let loc = probe_spec
-                .package
-                .as_ref()
-                .unwrap()
-                .loc
-                .as_ref()
-                .map(|loc| loc.line_col.clone());
-            return Err(Box::new(ErrorGen::get_parse_error(
-                true,
-                Some("Could not find any matches for the package pattern".to_string()),
-                loc,
-                vec![],
-                vec![],
-            )));
+        if let (false, Some(SpecPart { name, loc })) = (matched_packages, &probe_spec.package) {
+            let loc = loc.as_ref().map(|loc| loc.line_col.clone());
+            let options = spec_part_options::<P>(probe_spec, 1);
+            return Err(Box::new(
+                ErrorGen::get_parse_error(
+                    true,
+                    Some("Could not find any matches for the package pattern".to_string()),
+                    loc,
+                    vec![],
+                    vec![],
+                )
+                .with_suggestion(closest_match(name, &options)),
+            ));
         }
-        if !matched_events && probe_spec.event.is_some() {
-            let loc = probe_spec
-                .event
-                .as_ref()
-                .unwrap()
-                .loc
-                .as_ref()
-                .map(|loc| loc.line_col.clone());
-            return Err(Box::new(ErrorGen::get_parse_error(
-                true,
-                Some("Could not find any matches for the event pattern".to_string()),
-                loc,
-                vec![],
-                vec![],
-            )));
+        if let (false, Some(SpecPart { name, loc })) = (matched_events, &probe_spec.event) {
+            let loc = loc.as_ref().map(|loc| loc.line_col.clone());
+            let options = spec_part_options::<P>(probe_spec, 2);
+            return Err(Box::new(
+                ErrorGen::get_parse_error(
+                    true,
+                    Some("Could not find any matches for the event pattern".to_string()),
+                    loc,
+                    vec![],
+                    vec![],
+                )
+                .with_suggestion(closest_match(name, &options)),
+            ));
         }
-        if !matched_modes && probe_spec.mode.is_some() {
-            let loc = probe_spec
-                .mode
-                .as_ref()
-                .unwrap()
-                .loc
-                .as_ref()
-                .map(|loc| loc.line_col.clone());
-            return Err(Box::new(ErrorGen::get_parse_error(
-                true,
-                Some("Could not find any matches for the mode pattern".to_string()),
-                loc,
-                vec![],
-                vec![],
-            )));
+        if let (false, Some(SpecPart { name, loc })) = (matched_modes, &probe_spec.mode) {
+            let loc = loc.as_ref().map(|loc| loc.line_col.clone());
+            let options = spec_part_options::<P>(probe_spec, 3);
+            return Err(Box::new(
+                ErrorGen::get_parse_error(
+                    true,
+                    Some("Could not find any matches for the mode pattern".to_string()),
+                    loc,
+                    vec![],
+                    vec![],
+                )
+                .with_suggestion(closest_match(name, &options)),
+            ));
         }
 
         Ok((
@@ -201,6 +205,70 @@ pub fn provider_factory<P: Provider + NameOptions + FromStr + 'static>(
     }
 }
 
+/// The names that the part of `probe_spec` at `depth` (1: package, 2: event, 3: mode) could
+/// match given the parts before it, these are the candidates to suggest when it matches nothing.
+fn spec_part_options<P: Provider + NameOptions + FromStr + 'static>(
+    probe_spec: &ProbeSpec,
+    depth: usize,
+) -> Vec<String> {
+    // A pattern with wildcards isn't a misspelled name, this also keeps the lookup below
+    // (which uses a wildcard) from recursing
+    let part = [&probe_spec.package, &probe_spec.event, &probe_spec.mode][depth - 1];
+    if part
+        .as_ref()
+        .map_or(true, |part| part.name.contains(['*', '?', '[']))
+    {
+        return vec![];
+    }
+
+    let any = Some(SpecPart {
+        name: "*".to_string(),
+        loc: None,
+    });
+    let mut spec = ProbeSpec::new();
+    spec.provider = probe_spec.provider.clone();
+    spec.package = if depth == 1 {
+        any.clone()
+    } else {
+        probe_spec.package.clone()
+    };
+    if depth >= 2 {
+        spec.event = if depth == 2 {
+            any.clone()
+        } else {
+            probe_spec.event.clone()
+        };
+    }
+    if depth == 3 {
+        spec.mode = any;
+    }
+
+    // Collect the matches like `whamm info` does, without needing a full spec
+    let mut providers = HashMap::new();
+    if provider_factory::<P>(&mut providers, &spec, None, None, None, true).is_err() {
+        return vec![];
+    }
+    let mut options = vec![];
+    for provider in providers.values() {
+        for package in provider.packages() {
+            if depth == 1 {
+                options.push(package.name());
+                continue;
+            }
+            for event in package.events() {
+                if depth == 2 {
+                    options.push(event.name());
+                } else {
+                    options.extend(event.probes().keys().cloned());
+                }
+            }
+        }
+    }
+    options.sort();
+    options.dedup();
+    options
+}
+
 pub fn print_provider_docs(
     provider: &Box<dyn Provider>,
     print_globals: bool,
@@ -274,6 +342,7 @@ pub trait Package {
         loc: Option<Location>,
         predicate: Option<Expr>,
         body: Option<Block>,
+        priority: i32,
         printing_info: bool,
     ) -> (bool, bool);
 }
@@ -300,6 +369,7 @@ fn package_factory<P: Package + NameOptions + FromStr + 'static>(
     loc: Option<Location>,
     predicate: Option<Expr>,
     body: Option<Block>,
+    priority: i32,
     printing_info: bool,
 ) -> (bool, bool, bool) {
     if let Some(SpecPart {
@@ -328,6 +398,7 @@ fn package_factory<P: Package + NameOptions + FromStr + 'static>(
                         event_loc.to_owned(),
                         predicate.clone(),
                         body.clone(),
+                        priority,
                         printing_info,
                     )
                 } else {
@@ -391,6 +462,12 @@ fn print_package_docs(
 
 pub trait Probe {
     fn mode_name(&self) -> String;
+    fn mode_docs(&self) -> &String;
+    /// Where the probe's mode is in the script
+    fn loc(&self) -> &Option<Location>;
+    /// When several `alt` probes match a site, those with a higher priority are tried first
+    /// (see `@priority`)
+    fn priority(&self) -> i32;
     fn predicate(&self) -> &Option<Expr>;
     fn predicate_mut(&mut self) -> &mut Option<Expr>;
     fn body(&self) -> &Option<Block>;
@@ -428,6 +505,7 @@ pub trait Event {
         loc: Option<Location>,
         predicate: Option<Expr>,
         body: Option<Block>,
+        priority: i32,
     ) -> bool;
 }
 
@@ -451,6 +529,7 @@ fn event_factory<E: Event + NameOptions + FromStr + 'static>(
     loc: Option<Location>,
     predicate: Option<Expr>,
     body: Option<Block>,
+    priority: i32,
     printing_info: bool,
 ) -> (bool, bool) {
     if let Some(SpecPart {
@@ -478,6 +557,7 @@ fn event_factory<E: Event + NameOptions + FromStr + 'static>(
                         mode_loc.to_owned(),
                         predicate.clone(),
                         body.clone(),
+                        priority,
                     )
                 } else {
                     false
@@ -630,12 +710,14 @@ pub struct ProviderInfo {
 pub enum WhammProviderKind {
     Core,
     Wasm,
+    Component,
 }
 impl WhammProviderKind {
     fn name(&self) -> String {
         match self {
             Self::Core => "core".to_string(),
             Self::Wasm => "wasm".to_string(),
+            Self::Component => "component".to_string(),
         }
     }
 }
@@ -653,7 +735,11 @@ impl NameOptions for WhammProvider {
     fn get_name_options() -> Vec<String> {
         // Violates DRY principle, but works for now.
         // Maybe make this better some other time.
-        vec!["core".to_string(), "wasm".to_string()]
+        vec![
+            "core".to_string(),
+            "wasm".to_string(),
+            "component".to_string(),
+        ]
     }
 }
 impl FromStr for WhammProvider {
@@ -661,6 +747,7 @@ impl FromStr for WhammProvider {
         match name.as_str() {
             "core" => Self::core(loc),
             "wasm" => Self::wasm(loc),
+            "component" => Self::component(loc),
             _ => panic!("unsupported WhammProvider: {name}"),
         }
     }
@@ -692,6 +779,21 @@ impl WhammProvider {
             },
         }
     }
+    fn component(loc: Option<Location>) -> Self {
+        Self {
+            kind: WhammProviderKind::Component,
+            info: ProviderInfo {
+                docs: "This provides events to instrument that are specific to the \
+                    WebAssembly component model, these only match in applications that are \
+                    components."
+                    .to_string(),
+                fns: vec![],
+                globals: HashMap::new(),
+                loc,
+                packages: HashMap::new(),
+            },
+        }
+    }
 }
 impl Provider for WhammProvider {
     fn name(&self) -> String {
@@ -782,6 +884,7 @@ impl Provider for WhammProvider {
         loc: Option<Location>,
         predicate: Option<Expr>,
         body: Option<Block>,
+        priority: i32,
         printing_info: bool,
     ) -> (bool, bool, bool) {
         match self {
@@ -794,6 +897,7 @@ impl Provider for WhammProvider {
                 loc,
                 predicate,
                 body,
+                priority,
                 printing_info,
             ),
             Self {
@@ -805,6 +909,19 @@ impl Provider for WhammProvider {
                 loc,
                 predicate,
                 body,
+                priority,
+                printing_info,
+            ),
+            Self {
+                kind: WhammProviderKind::Component,
+                ..
+            } => package_factory::<ComponentPackage>(
+                &mut self.info.packages,
+                probe_spec,
+                loc,
+                predicate,
+                body,
+                priority,
                 printing_info,
             ),
         }
@@ -936,11 +1053,21 @@ pub struct WhammProbe {
 
     pub predicate: Option<Expr>,
     pub body: Option<Block>,
+    pub priority: i32,
 }
 impl Probe for WhammProbe {
     fn mode_name(&self) -> String {
         self.mode.name()
     }
+    fn mode_docs(&self) -> &String {
+        self.mode.docs()
+    }
+    fn loc(&self) -> &Option<Location> {
+        &self.loc
+    }
+    fn priority(&self) -> i32 {
+        self.priority
+    }
     fn predicate(&self) -> &Option<Expr> {
         &self.predicate
     }
@@ -984,12 +1111,14 @@ impl WhammProbe {
         loc: Option<Location>,
         predicate: Option<Expr>,
         body: Option<Block>,
+        priority: i32,
     ) -> Self {
         Self {
             mode,
             loc,
             predicate,
             body,
+            priority,
         }
     }
 }
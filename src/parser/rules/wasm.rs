@@ -39,6 +39,130 @@ impl FromStr for WasmPackage {
     }
 }
 impl WasmPackage {
+    // =========================
+    // ---- Globals Helpers ----
+    // =========================
+
+    fn opcode_globals() -> HashMap<String, ProvidedGlobal> {
+        let mut globals = HashMap::new();
+        globals.insert(
+            "wasm_bytecode_loc".to_string(),
+            ProvidedGlobal::new(
+                "wasm_bytecode_loc".to_string(),
+                "A unique identifier tied to the probe's location in the Wasm bytecode."
+                    .to_string(),
+                DataType::I32,
+                true,
+            ),
+        );
+
+        // the location of the probed instruction in the application
+        globals.insert(
+            "fn_id".to_string(),
+            ProvidedGlobal::new(
+                "fn_id".to_string(),
+                "The ID of the function containing the probed instruction.".to_string(),
+                DataType::I32,
+                true,
+            ),
+        );
+        globals.insert(
+            "fn_name".to_string(),
+            ProvidedGlobal::new(
+                "fn_name".to_string(),
+                "The name of the function containing the probed instruction. \
+                Taken from the `name` section, falling back to the export name. \
+                Evaluates to an empty string if the function has no name."
+                    .to_string(),
+                DataType::Str,
+                true,
+            ),
+        );
+        globals.insert(
+            "instr_idx".to_string(),
+            ProvidedGlobal::new(
+                "instr_idx".to_string(),
+                "The index of the probed instruction within its function's body.".to_string(),
+                DataType::I32,
+                true,
+            ),
+        );
+        globals.insert(
+            "pc".to_string(),
+            ProvidedGlobal::new(
+                "pc".to_string(),
+                "The byte offset of the probed instruction, \
+                relative to the start of the code section."
+                    .to_string(),
+                DataType::I32,
+                true,
+            ),
+        );
+        globals.insert(
+            "src_file".to_string(),
+            ProvidedGlobal::new(
+                "src_file".to_string(),
+                "The path of the source file that the probed instruction was compiled from. \
+                Taken from the DWARF `.debug_line` section, \
+                evaluates to an empty string if there is no line information for the instruction. \
+                Can only be compared to a whole path, there is no glob or prefix matching."
+                    .to_string(),
+                DataType::Str,
+                true,
+            ),
+        );
+        globals.insert(
+            "src_line".to_string(),
+            ProvidedGlobal::new(
+                "src_line".to_string(),
+                "The line in `src_file` that the probed instruction was compiled from, \
+                0 if it is unknown."
+                    .to_string(),
+                DataType::I32,
+                true,
+            ),
+        );
+        globals.insert(
+            "src_col".to_string(),
+            ProvidedGlobal::new(
+                "src_col".to_string(),
+                "The column in `src_file` that the probed instruction was compiled from, \
+                0 if it is unknown or the instruction is attributed to the whole line."
+                    .to_string(),
+                DataType::I32,
+                true,
+            ),
+        );
+
+        // the state of the application at the probed instruction, typed per site
+        globals.insert(
+            "tos".to_string(),
+            ProvidedGlobal::new(
+                "tos".to_string(),
+                "The value on top of the operand stack: before the probed instruction executes \
+                for `before` and `alt` probes, after it executes for `after` probes. \
+                Its type is the type of that stack value at each probed site."
+                    .to_string(),
+                DataType::AssumeGood,
+                false,
+            ),
+        );
+        globals.insert(
+            "local[0:9]+".to_string(),
+            ProvidedGlobal::new(
+                "local[0:9]+".to_string(),
+                "The local at the specific index, e.g. [0:9]+, of the function containing the \
+                probed instruction (params come first). Can be read and assigned to. \
+                Its type is the type declared by that function."
+                    .to_string(),
+                DataType::AssumeGood,
+                false,
+            ),
+        );
+
+        globals
+    }
+
     // ======================
     // ---- Constructors ----
     // ======================
@@ -51,16 +175,7 @@ impl WasmPackage {
                     instrumentation of WebAssembly bytecode instructions."
                     .to_string(),
                 fns: vec![],
-                globals: HashMap::from([(
-                    "wasm_bytecode_loc".to_string(),
-                    ProvidedGlobal::new(
-                        "wasm_bytecode_loc".to_string(),
-                        "A unique identifier tied to the probe's location in the Wasm bytecode."
-                            .to_string(),
-                        DataType::I32,
-                        true,
-                    ),
-                )]),
+                globals: Self::opcode_globals(),
                 loc,
                 events: HashMap::new(),
             },
@@ -153,6 +268,7 @@ impl Package for WasmPackage {
         loc: Option<Location>,
         predicate: Option<Expr>,
         body: Option<Block>,
+        priority: i32,
         printing_info: bool,
     ) -> (bool, bool) {
         match self {
@@ -165,6 +281,7 @@ impl Package for WasmPackage {
                 loc,
                 predicate,
                 body,
+                priority,
                 printing_info,
             ),
         }
@@ -557,7 +674,8 @@ impl OpcodeEvent {
             ProvidedGlobal::new(
                 "arg[0:9]+".to_string(),
                 "The argument to the call at the specific index, e.g. [0:9]+.\
-                Keep in mind, the number of arguments to a call changes based on the targeted function.".to_string(),
+                Keep in mind, the number and types of arguments to a call change based on the targeted function, \
+                so its type is the parameter type of the function targeted at each probed site.".to_string(),
                 DataType::AssumeGood,
                 false
             )
@@ -1602,6 +1720,7 @@ impl Event for OpcodeEvent {
         loc: Option<Location>,
         predicate: Option<Expr>,
         body: Option<Block>,
+        priority: i32,
     ) -> bool {
         let mut matched_modes = false;
         let probes = self.probes_mut();
@@ -1614,6 +1733,7 @@ impl Event for OpcodeEvent {
                 loc.clone(),
                 predicate.clone(),
                 body.clone(),
+                priority,
             )));
         }
         matched_modes
@@ -4,8 +4,11 @@ use crate::parser::whamm_parser::*;
 use glob::{glob, glob_with};
 
 use crate::common::error::ErrorGen;
+use crate::parser::catalog::{catalog, catalog_markdown};
+use crate::parser::formatter::format_script;
 use crate::parser::print_visitor::AsStrVisitor;
 use log::{debug, error, info, warn};
+use pest::error::LineColLocation;
 
 // =================
 // = Setup Logging =
@@ -41,7 +44,7 @@ wasm:opcode:br:before {
     "wasm:opcode::alt { }",
     // Predicates
     "wasm:opcode:br:before / i / { }",
-    r#"wasm:opcode:br:before / "i" <= 1 / { }"#, // TODO make invalid in type checking
+    r#"wasm:opcode:br:before / "i" <= 1 / { }"#, // rejected by the type checker
     "wasm:opcode:br:before / i54 < r77 / { }",
     "wasm:opcode:br:before / i54 < r77 / { }",
     "wasm:opcode:br:before / i != 7 / { }",
@@ -71,6 +74,13 @@ wasm::call:alt /
 }
     "#,
     r#"wasm:::alt / (i == "1") && (b == "2") / { i = 0; }"#,
+    // priorities
+    "@priority(2) wasm:opcode:call:alt { }",
+    "@priority(-1) wasm:opcode:call:alt / arg0 == 1 / { } wasm:opcode:call:alt { }",
+    // the canonical ABI of a component
+    r#"component:canon:lower:before / imp_name == "get-arguments" / { }"#,
+    "component:canon:lower:alt / core_module == 0 / { }",
+    "component:canon:lift:before / exp_name == \"run\" / { }",
     // globals
     r#"
 map<i32, i32> count;
@@ -226,6 +236,37 @@ wasm:opcode:br:before {
     "wasm:opcode:call:alt { i32 arg; }",
     "wasm:opcode:call:alt { arg = 1; }",
     "wasm:opcode:call:alt { arg0 = 1; }",
+    "wasm:opcode:br_if:before { i32 local; local = tos; }",
+    "wasm:opcode:local_get:after { local2 = tos; }",
+    // wasm value types
+    "i64 a; f32 b; f64 c; wasm:opcode:br_if:before { }",
+    // aggregations
+    "wasm:opcode:call:before { @calls = count(); }",
+    "wasm:opcode:call:before { @calls[fn_id, imm0] = count(); }",
+    r#"
+wasm:opcode:br_if:before {
+    @taken[fn_id, tos != 0] = count();
+    @total = sum(tos);
+    @lo[fn_id] = min(tos);
+    @hi[fn_id] = max(tos);
+    @mean = avg(tos);
+    @dist = quantize(tos);
+    @lin[fn_id] = lquantize(tos, -10, 10, 5);
+}
+    "#,
+    // scoped variables
+    "i32 this.start; wasm:opcode:call:before { this.start = instr_idx; }",
+    r#"
+i32 frame.depth;
+bool this.taken;
+wasm:opcode:br_if:before {
+    frame.depth++;
+    this.taken = tos != 0;
+}
+wasm:opcode:br_if:after / this.taken / {
+    frame.depth--;
+}
+    "#,
 ];
 
 const FATAL_SCRIPTS: &[&str] = &[
@@ -244,14 +285,29 @@ map<i32, i32> count;
     "wasm:opcode:call:alt: { }",
     "wasm:opcode:call:alt",
     "wasm:opcode:call:dne",
+    // bad priorities
+    "@priority wasm:opcode:call:alt { }",
+    "@priority() wasm:opcode:call:alt { }",
+    "@priority(0x1) wasm:opcode:call:alt { }",
+    // a lifted function is only probed before it runs
+    "component:canon:lift:after { }",
+    "component:canon:lift:alt { }",
     // Empty predicate
     "wasm:opcode:call:alt  // { }",
     "wasm:opcode:call:alt / 5i < r77 / { }",
-    //            "wasm:opcode:call:alt / i < 1 < 2 / { }", // TODO -- make invalid on semantic pass
-    //            "wasm:opcode:call:alt / (1 + 3) / { i }", // TODO -- make invalid on type check
     r#"wasm:opcode:call:alt  / i == """" / { }"#,
     // bad statement
     "wasm:opcode:call:alt / i == 1 / { i; }",
+    // bad aggregations
+    "wasm:opcode:call:before { @ = count(); }",
+    "wasm:opcode:call:before { @calls[] = count(); }",
+    "wasm:opcode:call:before { @calls = counts(); }",
+    "wasm:opcode:call:before { @calls = 1; }",
+    "wasm:opcode:call:before { calls[fn_id] = count(); }",
+    // bad scoped variables
+    "i32 that.x; wasm:opcode:call:before { }",
+    "i32 frame.; wasm:opcode:call:before { }",
+    "wasm:opcode:call:before { this . x = 1; }",
     r#"
     wasm:opcode:br:before {
         i32 return;
@@ -309,6 +365,7 @@ map<i32, i32> count;
         }
     // reserved keywords
     "wasm:opcode:call:alt { i32 arg0; }",
+    "wasm:opcode:call:alt { i32 local0; }",
     r#"
 map<i32, i32> arg0;
     "#,
@@ -415,18 +472,11 @@ pub fn test_parse_fatal_scripts() {
     setup_logger();
     for script in FATAL_SCRIPTS {
         info!("Parsing: {}", script);
-        let result = std::panic::catch_unwind(|| {
-            let mut err = ErrorGen::new("".to_string(), "".to_string(), 0);
-            is_valid_script(script, &mut err)
-        });
-        match result {
-            Ok(_) => {
-                panic!("Expected a fatal error, but got Ok");
-            }
-            Err(_) => {
-                //this means the function properly exited with a fatal error
-            }
-        }
+        let mut err = ErrorGen::new("".to_string(), "".to_string(), 0);
+        is_valid_script(script, &mut err);
+        // the error is collected rather than exiting, the caller has to stop here
+        assert!(err.has_fatal, "Expected a fatal error for: {script}");
+        assert!(err.check_fatal().is_err());
     }
 }
 
@@ -485,7 +535,7 @@ wasm::call:alt /
     assert_eq!(1, provider.len_packages());
     let package = provider.packages().next().unwrap();
     assert_eq!("opcode", package.name());
-    assert_eq!(1, package.get_provided_globals().len());
+    assert_eq!(5, package.get_provided_globals().len());
     assert_eq!(0, package.get_provided_fns().len());
 
     assert_eq!(1, package.len_events());
@@ -539,6 +589,26 @@ pub fn testing_strcmp() {
     assert!(is_valid_script(script, &mut err));
 }
 
+#[test]
+pub fn test_parse_aggregation_errors() {
+    setup_logger();
+    // grammatically fine, but the arguments don't fit the aggregating function
+    for script in [
+        "wasm:opcode:call:before { @calls = count(imm0); }",
+        "wasm:opcode:call:before { @total = sum(); }",
+        "wasm:opcode:call:before { @dist = lquantize(imm0, 0, 10); }",
+        "wasm:opcode:call:before { @dist = lquantize(imm0, 10, 0, 1); }",
+        "wasm:opcode:call:before { @dist = lquantize(imm0, 0, 10, 0); }",
+        "wasm:opcode:call:before { @dist = lquantize(imm0, 0, fn_id, 1); }",
+        "wasm:opcode:call:before { @dist = lquantize(imm0, 0, 1000000, 1); }",
+    ] {
+        info!("Parsing: {}", script);
+        let mut err = ErrorGen::new("".to_string(), "".to_string(), 0);
+        parse_script(&script.to_string(), &mut err);
+        assert!(err.has_errors);
+    }
+}
+
 #[test]
 fn test_global_stmts() {
     setup_logger();
@@ -598,6 +668,222 @@ pub fn testing_global_def() {
 
     assert!(is_valid_script(script, &mut err));
 }
+// ==================
+// = Error Recovery =
+// ==================
+
+fn error_lines(script: &str) -> Vec<usize> {
+    let mut err = ErrorGen::new("".to_string(), "".to_string(), 10);
+    assert!(!is_valid_script(script, &mut err));
+    err.take_diagnostics()
+        .errors
+        .iter()
+        .map(|e| match &e.err_loc.as_ref().unwrap().line_col {
+            LineColLocation::Pos((line, _)) | LineColLocation::Span((line, _), _) => *line,
+        })
+        .collect()
+}
+
+#[test]
+pub fn test_recover_from_syntax_errors() {
+    setup_logger();
+    let script = r#"
+i32 count = ;
+wasm:opcode:br_if:before {
+    count = count +;
+    count++;
+    if (count) { count = ; };
+}
+wasm:opcode:call:before / = / {
+    count--;
+}
+my_fn( {
+}
+wasm:opcode:br:before {
+    count = 1;
+}
+    "#;
+    assert_eq!(error_lines(script), vec![2, 4, 6, 8, 11]);
+}
+
+#[test]
+pub fn test_recover_at_end_of_script() {
+    setup_logger();
+    // an unclosed probe body is skipped to the end of the script
+    let script = r#"
+i32 count = ;
+wasm:opcode:br_if:before {
+    count++;
+    "#;
+    let lines = error_lines(script);
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0], 2);
+    assert!(lines[1] >= 3);
+
+    // no syntax errors, but there isn't a probe
+    assert_eq!(error_lines("i32 count;"), vec![1]);
+}
+
+fn suggestion_for(script: &str) -> Option<String> {
+    let mut err = ErrorGen::new("".to_string(), "".to_string(), 10);
+    is_valid_script(script, &mut err);
+    assert!(err.has_errors);
+    err.take_diagnostics()
+        .errors
+        .into_iter()
+        .find_map(|e| e.info_loc.and_then(|loc| loc.message))
+}
+
+#[test]
+pub fn test_suggest_spec_parts() {
+    setup_logger();
+    let did_you_mean = |name: &str| Some(format!("did you mean `{}`?", name));
+    assert_eq!(
+        suggestion_for("wams:opcode:br_if:before { }"),
+        did_you_mean("wasm")
+    );
+    assert_eq!(
+        suggestion_for("wasm:opcod:br_if:before { }"),
+        did_you_mean("opcode")
+    );
+    assert_eq!(
+        suggestion_for("wasm:opcode:br_fi:before { }"),
+        did_you_mean("br_if")
+    );
+    assert_eq!(
+        suggestion_for("wasm:opcode:br_if:befor { }"),
+        did_you_mean("before")
+    );
+
+    // nothing close enough to suggest, and globs aren't typos
+    assert_eq!(
+        suggestion_for("wasm:opcode:nothing_like_it:before { }"),
+        None
+    );
+    assert_eq!(suggestion_for("wasm:opcode:xyz*:before { }"), None);
+}
+
+// ==============
+// = Formatting =
+// ==============
+
+#[test]
+pub fn test_fmt() {
+    setup_logger();
+    let script = r#"
+// counts the branches
+i32   count ;  /* a global */
+
+
+wasm:opcode:br_if:before / count<5 / {
+  // increment
+  count=count+1;   // trailing
+  if(count==2){ count--; } else { count++; };
+  @hits[fn_id , pc]=count( );
+}
+BEGIN{ }
+    "#;
+    let expected = r#"// counts the branches
+i32 count; /* a global */
+
+wasm:opcode:br_if:before / count < 5 / {
+    // increment
+    count = count + 1; // trailing
+    if (count == 2) {
+        count--;
+    } else {
+        count++;
+    };
+    @hits[fn_id, pc] = count();
+}
+BEGIN {}
+"#;
+    let mut err = ErrorGen::new("".to_string(), "".to_string(), 0);
+    assert_eq!(format_script(script, &mut err).unwrap(), expected);
+}
+
+#[test]
+pub fn test_fmt_keeps_scripts_valid() {
+    setup_logger();
+    let mut scripts: Vec<String> = VALID_SCRIPTS.iter().map(|s| s.to_string()).collect();
+    scripts.extend(get_test_scripts("fault_injection"));
+    scripts.extend(get_test_scripts("wizard_monitors"));
+
+    for script in scripts {
+        let mut err = ErrorGen::new("".to_string(), "".to_string(), 0);
+        let formatted = format_script(&script, &mut err).unwrap();
+        debug!("Formatted:\n{}", formatted);
+
+        // Formatting is idempotent and doesn't change what parses
+        assert_eq!(format_script(&formatted, &mut err).unwrap(), formatted);
+        assert!(is_valid_script(&formatted, &mut err));
+        let count_comments = |s: &str| s.matches("//").count() + s.matches("/*").count();
+        assert_eq!(count_comments(&script), count_comments(&formatted));
+    }
+}
+
+#[test]
+pub fn test_fmt_invalid_script() {
+    setup_logger();
+    let mut err = ErrorGen::new("".to_string(), "".to_string(), 0);
+    assert!(format_script("wasm:opcode:br:before { i = ; }", &mut err).is_none());
+    assert!(err.has_errors);
+}
+
+// ===========
+// = Catalog =
+// ===========
+
+#[test]
+pub fn test_catalog() {
+    setup_logger();
+    let mut err = ErrorGen::new("".to_string(), "".to_string(), 0);
+    let catalog = catalog("wasm:opcode:call", &mut err).unwrap();
+    assert!(!err.has_errors);
+
+    let providers = catalog["providers"].as_array().unwrap();
+    assert_eq!(providers.len(), 1);
+    let event = &providers[0]["packages"][0]["events"][0];
+    assert_eq!(event["name"], "call");
+
+    // The missing mode matches every mode
+    let modes: Vec<&str> = event["modes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|mode| mode["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(modes, vec!["after", "alt", "before"]);
+
+    let target_fn_type = event["globals"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|global| global["name"] == "target_fn_type")
+        .unwrap();
+    assert_eq!(target_fn_type["type"], "str");
+    assert_eq!(target_fn_type["definition"], "static");
+    assert!(catalog["whamm"]["functions"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|function| function["name"] == "strcmp"));
+
+    let md = catalog_markdown(&catalog);
+    assert!(md.contains("### `wasm:opcode:call` event ###"));
+    assert!(md.contains("#### `wasm:opcode:call:before` mode ####"));
+    assert!(md.contains("| `target_fn_type` | `str` | static |"));
+
+    // A lifted function only has the `before` mode
+    let catalog = catalog("component:canon:lift", &mut err).unwrap();
+    let modes = &catalog["providers"][0]["packages"][0]["events"][0]["modes"];
+    assert_eq!(modes.as_array().unwrap().len(), 1);
+    assert_eq!(modes[0]["name"], "before");
+
+    assert!(catalog("nope:opcode", &mut err).is_none());
+    assert!(err.has_errors);
+}
+
 // ===================
 // = Full File Tests =
 // ===================
@@ -84,6 +84,9 @@ impl PartialEq for DataType {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
             (DataType::I32, DataType::I32)
+            | (DataType::I64, DataType::I64)
+            | (DataType::F32, DataType::F32)
+            | (DataType::F64, DataType::F64)
             | (DataType::Boolean, DataType::Boolean)
             | (DataType::Null, DataType::Null)
             | (DataType::Str, DataType::Str)
@@ -117,6 +120,9 @@ impl Eq for DataType {}
 pub enum DataType {
     I32,
     U32,
+    I64,
+    F32,
+    F64,
     Boolean,
     Null,
     Str,
@@ -138,6 +144,15 @@ impl DataType {
             DataType::U32 => {
                 yellow(true, "u32".to_string(), buffer);
             }
+            DataType::I64 => {
+                yellow(true, "i64".to_string(), buffer);
+            }
+            DataType::F32 => {
+                yellow(true, "f32".to_string(), buffer);
+            }
+            DataType::F64 => {
+                yellow(true, "f64".to_string(), buffer);
+            }
             DataType::Boolean => {
                 yellow(true, "bool".to_string(), buffer);
             }
@@ -227,6 +242,14 @@ pub enum Statement {
         alt: Block,
         loc: Option<Location>,
     },
+    /// `@name[keys] = func(args);`
+    Aggregate {
+        name: String,
+        keys: Vec<Expr>,
+        func: AggFn,
+        args: Vec<Expr>,
+        loc: Option<Location>,
+    },
 }
 impl Statement {
     pub fn loc(&self) -> &Option<Location> {
@@ -235,6 +258,7 @@ impl Statement {
             | Statement::If { loc, .. }
             | Statement::Return { loc, .. }
             | Statement::Assign { loc, .. }
+            | Statement::Aggregate { loc, .. }
             | Statement::Expr { loc, .. } => loc,
         }
     }
@@ -255,6 +279,94 @@ impl Statement {
     }
 }
 
+/// The aggregating functions that can be used to update an aggregation.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum AggFn {
+    Count,
+    Sum,
+    Min,
+    Max,
+    Avg,
+    /// Power-of-two histogram
+    Quantize,
+    /// Linear histogram over `[lo, hi)` with buckets of width `step`
+    LQuantize {
+        lo: i32,
+        hi: i32,
+        step: i32,
+    },
+}
+impl AggFn {
+    /// The max number of buckets that `lquantize` may have between `lo` and `hi`, each one
+    /// takes up space in every entry of the aggregation's table.
+    pub const MAX_LQUANTIZE_BUCKETS: i64 = 1024;
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "count" => Some(Self::Count),
+            "sum" => Some(Self::Sum),
+            "min" => Some(Self::Min),
+            "max" => Some(Self::Max),
+            "avg" => Some(Self::Avg),
+            "quantize" => Some(Self::Quantize),
+            // the bounds are filled in by the parser
+            "lquantize" => Some(Self::LQuantize {
+                lo: 0,
+                hi: 0,
+                step: 0,
+            }),
+            _ => None,
+        }
+    }
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Count => "count",
+            Self::Sum => "sum",
+            Self::Min => "min",
+            Self::Max => "max",
+            Self::Avg => "avg",
+            Self::Quantize => "quantize",
+            Self::LQuantize { .. } => "lquantize",
+        }
+    }
+    /// The number of values passed to the function, not counting the `lquantize` bounds.
+    pub fn num_args(&self) -> usize {
+        match self {
+            Self::Count => 0,
+            _ => 1,
+        }
+    }
+    /// The number of histogram buckets, 0 if this isn't a histogram.
+    ///
+    /// `quantize` has a bucket for negative values, one for zero, then one per power of two.
+    /// `lquantize` has an underflow bucket, `(hi - lo) / step` (rounded up) buckets
+    /// and an overflow bucket.
+    pub fn num_buckets(&self) -> usize {
+        match self {
+            Self::Quantize => 33,
+            Self::LQuantize { lo, hi, step } => {
+                ((*hi as i64 - *lo as i64 + *step as i64 - 1) / *step as i64) as usize + 2
+            }
+            _ => 0,
+        }
+    }
+}
+
+/// An aggregation declared (implicitly) by a script, see `Statement::Aggregate`.
+#[derive(Clone, Debug)]
+pub struct Aggregation {
+    pub name: String,
+    pub func: AggFn,
+    pub num_keys: usize,
+    pub loc: Option<Location>,
+}
+impl Aggregation {
+    /// The name of the symbol that the aggregation's update function is bound to.
+    pub fn symbol_name(name: &str) -> String {
+        format!("@{name}")
+    }
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum Expr {
     UnOp {
@@ -675,6 +787,7 @@ pub struct Script {
     pub fns: Vec<Fn>,                     // User-provided
     pub globals: HashMap<String, Global>, // User-provided, should be VarId
     pub global_stmts: Vec<Statement>,
+    pub aggregations: HashMap<String, Aggregation>, // User-provided, populated by the SymbolTableBuilder
 }
 impl Default for Script {
     fn default() -> Self {
@@ -689,6 +802,7 @@ impl Script {
             fns: vec![],
             globals: HashMap::new(),
             global_stmts: vec![],
+            aggregations: HashMap::new(),
         }
     }
 
@@ -700,86 +814,100 @@ impl Script {
     ) -> Result<(), Box<WhammError>> {
         let writer = BufferWriter::stderr(ColorChoice::Always);
         let mut buffer = writer.buffer();
+        self.write_info(probe_spec, print_globals, print_functions, &mut buffer)?;
+
+        writer
+            .print(&buffer)
+            .expect("Uh oh, something went wrong while printing to terminal");
+        buffer
+            .reset()
+            .expect("Uh oh, something went wrong while printing to terminal");
+
+        Ok(())
+    }
 
+    /// Write the information printed by `whamm info` to the passed buffer.
+    pub fn write_info(
+        &mut self,
+        probe_spec: &ProbeSpec,
+        print_globals: bool,
+        print_functions: bool,
+        buffer: &mut Buffer,
+    ) -> Result<(), Box<WhammError>> {
         // Print `whamm` info
         let mut tabs = 0;
         if print_globals || print_functions {
-            white(true, "\nCORE ".to_string(), &mut buffer);
-            magenta(true, "`whamm`".to_string(), &mut buffer);
-            white(true, " FUNCTIONALITY\n\n".to_string(), &mut buffer);
+            white(true, "\nCORE ".to_string(), buffer);
+            magenta(true, "`whamm`".to_string(), buffer);
+            white(true, " FUNCTIONALITY\n\n".to_string(), buffer);
 
             // Print the globals
             if print_globals {
                 let globals = Whamm::get_provided_globals();
-                print_global_vars(&mut tabs, &globals, &mut buffer);
+                print_global_vars(&mut tabs, &globals, buffer);
             }
 
             // Print the functions
             if print_functions {
                 let functions = Whamm::get_provided_fns();
-                print_fns(&mut tabs, &functions, &mut buffer);
+                print_fns(&mut tabs, &functions, buffer);
             }
         }
 
-        long_line(&mut buffer);
-        white(true, "\n\n".to_string(), &mut buffer);
+        long_line(buffer);
+        white(true, "\n\n".to_string(), buffer);
 
         let mut providers: HashMap<String, Box<dyn Provider>> = HashMap::new();
         let (matched_providers, matched_packages, matched_events, matched_modes) =
-            provider_factory::<WhammProvider>(&mut providers, probe_spec, None, None, None, true)?;
+            provider_factory::<WhammProvider>(
+                &mut providers,
+                probe_spec,
+                None,
+                None,
+                None,
+                0,
+                true,
+            )?;
 
         // Print the matched provider information
         if matched_providers {
-            probe_spec.print_bold_provider(&mut buffer);
+            probe_spec.print_bold_provider(buffer);
         }
         for (.., provider) in providers.iter() {
-            print_provider_docs(
-                provider,
-                print_globals,
-                print_functions,
-                &mut tabs,
-                &mut buffer,
-            );
+            print_provider_docs(provider, print_globals, print_functions, &mut tabs, buffer);
         }
-        long_line(&mut buffer);
-        white(true, "\n\n".to_string(), &mut buffer);
+        long_line(buffer);
+        white(true, "\n\n".to_string(), buffer);
 
         // Print the matched package information
         if matched_packages {
-            probe_spec.print_bold_package(&mut buffer);
+            probe_spec.print_bold_package(buffer);
         }
         for (.., provider) in providers.iter() {
-            provider.print_package_docs(print_globals, print_functions, &mut tabs, &mut buffer);
+            provider.print_package_docs(print_globals, print_functions, &mut tabs, buffer);
         }
-        long_line(&mut buffer);
-        white(true, "\n\n".to_string(), &mut buffer);
+        long_line(buffer);
+        white(true, "\n\n".to_string(), buffer);
 
         // Print the matched event information
         if matched_events {
-            probe_spec.print_bold_event(&mut buffer);
+            probe_spec.print_bold_event(buffer);
         }
         for (.., provider) in providers.iter() {
-            provider.print_event_docs(print_globals, print_functions, &mut tabs, &mut buffer);
+            provider.print_event_docs(print_globals, print_functions, &mut tabs, buffer);
         }
-        long_line(&mut buffer);
-        white(true, "\n\n".to_string(), &mut buffer);
+        long_line(buffer);
+        white(true, "\n\n".to_string(), buffer);
 
         // Print the matched mode information
         if matched_modes {
-            probe_spec.print_bold_mode(&mut buffer);
+            probe_spec.print_bold_mode(buffer);
         }
         for (.., provider) in providers.iter() {
-            provider.print_mode_docs(print_globals, print_functions, &mut tabs, &mut buffer);
+            provider.print_mode_docs(print_globals, print_functions, &mut tabs, buffer);
         }
-        long_line(&mut buffer);
-        white(true, "\n\n".to_string(), &mut buffer);
-
-        writer
-            .print(&buffer)
-            .expect("Uh oh, something went wrong while printing to terminal");
-        buffer
-            .reset()
-            .expect("Uh oh, something went wrong while printing to terminal");
+        long_line(buffer);
+        white(true, "\n\n".to_string(), buffer);
 
         Ok(())
     }
@@ -797,6 +925,7 @@ impl Script {
         probe_spec: &ProbeSpec,
         predicate: Option<Expr>,
         body: Option<Block>,
+        priority: i32,
     ) -> Result<(), Box<WhammError>> {
         let (matched_providers, matched_packages, matched_events, matched_modes): (
             bool,
@@ -809,6 +938,7 @@ impl Script {
             None,
             predicate,
             body,
+            priority,
             false,
         )?;
 
@@ -3,40 +3,66 @@ use types::{BinOp, Block, FnId, Rule, UnOp, WhammParser, PRATT_PARSER};
 
 use crate::common::error::{ErrorGen, WhammError};
 use crate::parser::types::{
-    DataType, Definition, Expr, Location, ProbeSpec, Script, SpecPart, Statement, Value, Whamm,
+    AggFn, DataType, Definition, Expr, Location, ProbeSpec, Script, SpecPart, Statement, Value,
+    Whamm,
 };
 use log::trace;
-use pest::error::{Error, LineColLocation};
+use pest::error::{Error, InputLocation, LineColLocation};
 use pest::iterators::Pair;
-use pest::Parser;
+use pest::{Parser, Position, Span};
+use termcolor::{Buffer, BufferWriter, ColorChoice};
 
 const UNEXPECTED_ERR_MSG: &str =
     "WhammParser: Looks like you've found a bug...please report this behavior! Exiting now...";
 
 pub fn print_info(spec: String, print_globals: bool, print_functions: bool, err: &mut ErrorGen) {
     trace!("Entered print_info");
+    let writer = BufferWriter::stderr(ColorChoice::Always);
+    let mut buffer = writer.buffer();
+    write_info(&spec, print_globals, print_functions, &mut buffer, err);
+
+    writer
+        .print(&buffer)
+        .expect("Uh oh, something went wrong while printing to terminal");
+}
+
+/// Write the information for the passed probe specification to `buffer`, this is what
+/// `whamm info` prints.
+pub fn write_info(
+    spec: &str,
+    print_globals: bool,
+    print_functions: bool,
+    buffer: &mut Buffer,
+    err: &mut ErrorGen,
+) {
+    if let Some(probe_spec) = parse_probe_spec(spec, err) {
+        // Write the information for the passed probe specification
+        let mut whamm = Whamm::new();
+        let id = whamm.add_script(Script::new());
+        let script: &mut Script = whamm.scripts.get_mut(id).unwrap();
+        if let Err(e) = script.write_info(&probe_spec, print_globals, print_functions, buffer) {
+            err.add_error(*e);
+        }
+    }
+}
+
+/// Parse a (possibly partial) probe specification on its own, like the ones passed to `whamm info`.
+pub fn parse_probe_spec(spec: &str, err: &mut ErrorGen) -> Option<ProbeSpec> {
     err.set_script_text(spec.to_owned());
 
-    let res = WhammParser::parse(Rule::PROBE_SPEC, &spec);
+    let res = WhammParser::parse(Rule::PROBE_SPEC, spec);
     match res {
         Ok(mut pairs) => {
             // Create the probe specification from the input string
-            let probe_spec = probe_spec_from_rule(
+            Some(probe_spec_from_rule(
                 // inner of script
                 pairs.next().unwrap(),
                 err,
-            );
-
-            // Print the information for the passed probe specification
-            let mut whamm = Whamm::new();
-            let id = whamm.add_script(Script::new());
-            let script: &mut Script = whamm.scripts.get_mut(id).unwrap();
-            if let Err(e) = script.print_info(&probe_spec, print_globals, print_functions) {
-                err.add_error(*e);
-            }
+            ))
         }
         Err(e) => {
             err.pest_err(e);
+            None
         }
     }
 }
@@ -48,11 +74,12 @@ pub fn parse_script(script: &String, err: &mut ErrorGen) -> Option<Whamm> {
     let res = WhammParser::parse(Rule::script, script);
     match res {
         Ok(mut pairs) => {
-            let res = to_ast(
-                // inner of script
-                pairs.next().unwrap(),
-                err,
-            );
+            // inner of script
+            let root = pairs.next().unwrap();
+            if !check_syntax(script, &root, err) {
+                return None;
+            }
+            let res = to_ast(root, err);
 
             match res {
                 Ok(ast) => Some(ast),
@@ -69,6 +96,75 @@ pub fn parse_script(script: &String, err: &mut ErrorGen) -> Option<Whamm> {
     }
 }
 
+/// Report the syntax errors in a parsed script. The grammar skips over code that doesn't
+/// parse (see the `bad_item` and `bad_stmt` rules) so that all of these can be found in one
+/// run, each skipped piece is parsed again on its own here to get the actual error.
+/// Returns whether the script is free of syntax errors.
+pub(crate) fn check_syntax(script: &str, root: &Pair<Rule>, err: &mut ErrorGen) -> bool {
+    let mut has_probe = false;
+    let mut is_valid = true;
+    for pair in root.clone().into_inner().flatten() {
+        let entry = match pair.as_rule() {
+            Rule::probe_def => {
+                has_probe = true;
+                continue;
+            }
+            Rule::bad_item => Rule::lone_item,
+            Rule::bad_stmt => Rule::lone_stmt,
+            _ => continue,
+        };
+        is_valid = false;
+
+        let span = pair.as_span();
+        match WhammParser::parse(entry, span.as_str()) {
+            Err(e) => match shift_error(script, span.start(), e) {
+                Some(e) => err.pest_err(e),
+                None => skipped_code_error(span, err),
+            },
+            // Shouldn't happen, the grammar only skips code that doesn't parse
+            Ok(_) => skipped_code_error(span, err),
+        }
+    }
+
+    if is_valid && !has_probe {
+        let end = Position::new(script, script.len()).unwrap();
+        err.parse_error(
+            false,
+            Some("A script needs at least one probe definition".to_string()),
+            Some(LineColLocation::Pos(end.line_col())),
+            vec![Rule::probe_def],
+            vec![],
+        );
+        is_valid = false;
+    }
+    is_valid
+}
+
+/// Move an error found while parsing a piece of the script that starts at `offset` to where
+/// that piece is in the whole script.
+fn shift_error(script: &str, offset: usize, e: Error<Rule>) -> Option<Error<Rule>> {
+    match e.location {
+        InputLocation::Pos(pos) => Some(Error::new_from_pos(
+            e.variant,
+            Position::new(script, offset + pos)?,
+        )),
+        InputLocation::Span((start, end)) => Some(Error::new_from_span(
+            e.variant,
+            Span::new(script, offset + start, offset + end)?,
+        )),
+    }
+}
+
+fn skipped_code_error(span: Span, err: &mut ErrorGen) {
+    err.parse_error(
+        false,
+        Some("Unable to parse this code".to_string()),
+        Some(LineColLocation::from(span)),
+        vec![],
+        vec![],
+    );
+}
+
 // ====================
 // = AST Constructors =
 // ====================
@@ -92,8 +188,6 @@ pub fn to_ast(pair: Pair<Rule>, err: &mut ErrorGen) -> Result<Whamm, Box<Error<R
                 vec![Rule::script],
                 vec![rule],
             );
-            // should have exited above (since it's a fatal error)
-            unreachable!()
         }
     }
 
@@ -136,7 +230,13 @@ pub fn process_pair(whamm: &mut Whamm, script_count: usize, pair: Pair<Rule>, er
         Rule::probe_def => {
             trace!("Entering probe_def");
             let mut pair = pair.into_inner();
-            let spec_rule = pair.next().unwrap();
+            let mut spec_rule = pair.next().unwrap();
+            // Get out the priority, if there is one
+            let mut priority = 0;
+            if spec_rule.as_rule() == Rule::priority {
+                priority = priority_from_rule(spec_rule, err);
+                spec_rule = pair.next().unwrap();
+            }
             // Get out the spec info
             let probe_spec = probe_spec_from_rule(spec_rule, err);
 
@@ -203,7 +303,7 @@ pub fn process_pair(whamm: &mut Whamm, script_count: usize, pair: Pair<Rule>, er
 
             // Add probe definition to the script
             let script: &mut Script = whamm.scripts.get_mut(script_count).unwrap();
-            if let Err(e) = script.add_probe(&probe_spec, this_predicate, this_body) {
+            if let Err(e) = script.add_probe(&probe_spec, this_predicate, this_body, priority) {
                 err.add_error(*e);
             }
 
@@ -293,8 +393,6 @@ pub fn process_pair(whamm: &mut Whamm, script_count: usize, pair: Pair<Rule>, er
                 vec![Rule::script, Rule::probe_def, Rule::EOI],
                 vec![rule],
             );
-            // should have exited above (since it's a fatal error)
-            unreachable!()
         }
     }
 }
@@ -721,6 +819,150 @@ fn stmt_from_rule(pair: Pair<Rule>, err: &mut ErrorGen) -> Vec<Statement> {
             });
             output
         }
+        Rule::aggregation => {
+            trace!("Entering aggregation");
+            let agg_line_col = LineColLocation::from(pair.as_span());
+            let mut pair = pair.into_inner();
+            let name = pair
+                .next()
+                .unwrap()
+                .into_inner()
+                .next()
+                .unwrap()
+                .as_str()
+                .to_string();
+
+            let mut keys = vec![];
+            let mut next = pair.next().unwrap();
+            if next.as_rule() == Rule::agg_keys {
+                for key_rule in next.into_inner() {
+                    match expr_from_pair(key_rule) {
+                        Ok(key) => keys.push(key),
+                        Err(errors) => err.add_errors(errors),
+                    }
+                }
+                next = pair.next().unwrap();
+            }
+
+            // agg_call
+            let call_line_col = LineColLocation::from(next.as_span());
+            let mut call = next.into_inner();
+            let fn_name = call.next().unwrap().as_str().to_string();
+            let mut args = vec![];
+            for arg_rule in call {
+                match expr_from_pair(arg_rule) {
+                    Ok(arg) => args.push(arg),
+                    Err(errors) => err.add_errors(errors),
+                }
+            }
+
+            let mut func = match AggFn::from_name(&fn_name) {
+                Some(func) => func,
+                None => {
+                    err.parse_error(
+                        false,
+                        Some(format!("Unknown aggregating function: `{fn_name}`")),
+                        Some(call_line_col),
+                        vec![Rule::AGG_FN],
+                        vec![],
+                    );
+                    return vec![];
+                }
+            };
+            if let AggFn::LQuantize { lo, hi, step } = &mut func {
+                // lquantize(value, lo, hi, step), the bounds size the table so must be literals
+                if args.len() == 4 {
+                    let bounds: Vec<Option<i32>> = args
+                        .drain(1..)
+                        .map(|bound| match bound {
+                            Expr::Primitive {
+                                val: Value::Integer { val, .. },
+                                ..
+                            } => Some(val),
+                            _ => None,
+                        })
+                        .collect();
+                    match bounds[..] {
+                        [Some(l), Some(h), Some(st)]
+                            if st > 0
+                                && h > l
+                                && (h as i64 - l as i64 + st as i64 - 1) / st as i64
+                                    > AggFn::MAX_LQUANTIZE_BUCKETS =>
+                        {
+                            err.parse_error(
+                                false,
+                                Some(format!(
+                                    "`lquantize(value, lo, hi, step)` can have at most {} \
+                                    buckets between `lo` and `hi`, use a larger `step`",
+                                    AggFn::MAX_LQUANTIZE_BUCKETS
+                                )),
+                                Some(call_line_col),
+                                vec![],
+                                vec![],
+                            );
+                            return vec![];
+                        }
+                        [Some(l), Some(h), Some(st)] if st > 0 && h > l => {
+                            *lo = l;
+                            *hi = h;
+                            *step = st;
+                        }
+                        _ => {
+                            err.parse_error(
+                                false,
+                                Some(
+                                    "The bounds of `lquantize(value, lo, hi, step)` must be \
+                                    integer literals, with `lo < hi` and `step > 0`"
+                                        .to_string(),
+                                ),
+                                Some(call_line_col),
+                                vec![],
+                                vec![],
+                            );
+                            return vec![];
+                        }
+                    }
+                } else {
+                    err.parse_error(
+                        false,
+                        Some(format!(
+                            "`lquantize` takes 4 arguments (value, lo, hi, step), found {}",
+                            args.len()
+                        )),
+                        Some(call_line_col),
+                        vec![],
+                        vec![],
+                    );
+                    return vec![];
+                }
+            } else if args.len() != func.num_args() {
+                err.parse_error(
+                    false,
+                    Some(format!(
+                        "`{}` takes {} argument(s), found {}",
+                        func.name(),
+                        func.num_args(),
+                        args.len()
+                    )),
+                    Some(call_line_col),
+                    vec![],
+                    vec![],
+                );
+                return vec![];
+            }
+
+            trace!("Exiting aggregation");
+            vec![Statement::Aggregate {
+                name,
+                keys,
+                func,
+                args,
+                loc: Some(Location {
+                    line_col: agg_line_col,
+                    path: None,
+                }),
+            }]
+        }
         Rule::ret => {
             trace!("Entering return_stmt");
             let mut output: Vec<Statement> = vec![];
@@ -901,17 +1143,19 @@ fn stmt_from_rule(pair: Pair<Rule>, err: &mut ErrorGen) -> Vec<Statement> {
                 vec![Rule::statement, Rule::assignment, Rule::fn_call],
                 vec![rule],
             );
-            // should have exited above (since it's a fatal error)
-            unreachable!();
+            vec![]
         }
     }
 }
 
 fn type_from_rule(pair: Pair<Rule>, err: &mut ErrorGen) -> DataType {
     trace!("Entering type_from_rule");
-    // TYPE = _{ TY_I32 | TY_BOOL | TY_STRING | TY_TUPLE | TY_MAP }
+    // TYPE = _{ TY_I32 | TY_I64 | TY_F32 | TY_F64 | TY_BOOL | TY_STRING | TY_TUPLE | TY_MAP }
     return match pair.as_rule() {
         Rule::TY_I32 => DataType::I32,
+        Rule::TY_I64 => DataType::I64,
+        Rule::TY_F32 => DataType::F32,
+        Rule::TY_F64 => DataType::F64,
         Rule::TY_BOOL => DataType::Boolean,
         Rule::TY_STRING => DataType::Str,
         Rule::TY_TUPLE => {
@@ -947,6 +1191,9 @@ fn type_from_rule(pair: Pair<Rule>, err: &mut ErrorGen) -> DataType {
                 Some(LineColLocation::from(pair.as_span())),
                 vec![
                     Rule::TY_I32,
+                    Rule::TY_I64,
+                    Rule::TY_F32,
+                    Rule::TY_F64,
                     Rule::TY_BOOL,
                     Rule::TY_STRING,
                     Rule::TY_TUPLE,
@@ -954,8 +1201,7 @@ fn type_from_rule(pair: Pair<Rule>, err: &mut ErrorGen) -> DataType {
                 ],
                 vec![rule],
             );
-            // should have exited above (since it's a fatal error)
-            unreachable!();
+            DataType::AssumeGood
         }
     };
 }
@@ -988,8 +1234,28 @@ fn probe_spec_part_from_rule(pair: Pair<Rule>, err: &mut ErrorGen) -> SpecPart {
                 vec![Rule::PROBE_ID, Rule::PROBE_ID],
                 vec![rule],
             );
-            // should have exited above (since it's a fatal error)
-            unreachable!();
+            SpecPart {
+                name: "".to_string(),
+                loc: None,
+            }
+        }
+    }
+}
+
+fn priority_from_rule(pair: Pair<Rule>, err: &mut ErrorGen) -> i32 {
+    trace!("Entered priority_from_rule");
+    let val = pair.into_inner().next().unwrap();
+    match val.as_str().parse::<i32>() {
+        Ok(priority) => priority,
+        Err(_) => {
+            err.parse_error(
+                false,
+                Some("A probe's priority must be a decimal integer".to_string()),
+                Some(LineColLocation::from(val.as_span())),
+                vec![],
+                vec![],
+            );
+            0
         }
     }
 }
@@ -1080,8 +1346,7 @@ fn probe_spec_from_rule(pair: Pair<Rule>, err: &mut ErrorGen) -> ProbeSpec {
                 vec![Rule::PROBE_SPEC],
                 vec![rule],
             );
-            // should have exited above (since it's a fatal error)
-            unreachable!();
+            ProbeSpec::new()
         }
     }
 }
@@ -1089,7 +1354,7 @@ fn probe_spec_from_rule(pair: Pair<Rule>, err: &mut ErrorGen) -> ProbeSpec {
 fn expr_primary(pair: Pair<Rule>) -> Result<Expr, Vec<WhammError>> {
     match pair.as_rule() {
         Rule::fn_call => fn_call_from_rule(pair),
-        Rule::ID => {
+        Rule::ID | Rule::SCOPED_ID => {
             return Ok(Expr::VarId {
                 is_comp_provided: false,
                 name: pair.as_str().parse().unwrap(),
@@ -1,4 +1,5 @@
 pub mod builder_visitor;
+pub mod lints;
 pub mod types;
 #[allow(clippy::module_inception)]
 // TODO: rename this
@@ -1,8 +1,10 @@
 use crate::parser::types as parser_types;
 use crate::verifier::builder_visitor::parser_types::Location;
 use crate::verifier::types::{Record, ScopeType, SymbolTable};
-use crate::verifier::verifier::check_duplicate_id;
-use parser_types::{BinOp, Block, DataType, Expr, Fn, Script, Statement, UnOp, Value, Whamm};
+use crate::verifier::verifier::{check_duplicate_id, is_scoped_var};
+use parser_types::{
+    Aggregation, BinOp, Block, DataType, Expr, Fn, FnId, Script, Statement, UnOp, Value, Whamm,
+};
 use std::collections::HashMap;
 
 use crate::common::error::ErrorGen;
@@ -22,6 +24,8 @@ pub struct SymbolTableBuilder<'a> {
     pub curr_event: Option<usize>,  // indexes into this::table::records
     pub curr_probe: Option<usize>,  // indexes into this::table::records
     pub curr_fn: Option<usize>,     // indexes into this::table::records
+    /// The aggregations updated by the probes of the current script
+    pub curr_aggregations: HashMap<String, Aggregation>,
 }
 impl SymbolTableBuilder<'_> {
     fn add_script(&mut self, script: &Script) {
@@ -69,12 +73,13 @@ impl SymbolTableBuilder<'_> {
             .set_curr_scope_info(script.name.clone(), ScopeType::Script);
         self.table.set_curr_script(id);
     }
-    fn add_provider(&mut self, provider: &dyn Provider) {
+    /// Returns whether the provider could be added, its contents should be skipped otherwise.
+    fn add_provider(&mut self, provider: &dyn Provider) -> bool {
         /*check_duplicate_id is necessary to make sure we don't try to have 2 records with the same string pointing to them in the hashmap.
         In some cases, it gives a non-fatal error, but in others, it is fatal. Thats why if it finds any error, we return here ->
         just in case it is non-fatal to avoid having 2 strings w/same name in record */
         if check_duplicate_id(&provider.name(), &None, true, &self.table, self.err) {
-            return;
+            return false;
         }
 
         // create record
@@ -112,6 +117,7 @@ impl SymbolTableBuilder<'_> {
         // set scope name and type
         self.table
             .set_curr_scope_info(provider.name().clone(), ScopeType::Provider);
+        true
     }
 
     fn add_package(&mut self, package: &dyn Package) {
@@ -289,10 +295,11 @@ impl SymbolTableBuilder<'_> {
                 // This should never be the case -> ID is in the table but doesn't have a record associated with it
                 self.err
                     .unexpected_error(true, Some(UNEXPECTED_ERR_MSG.to_string()), None);
-                unreachable!()
             };
         }
-        //This MUST run if the above wasn't a fatal error -> otherwise there are scoping errors
+        // This runs even if the above reported an error (errors don't exit), the fn still needs
+        // its record and scope so that the rest of the script is visited with the right scoping.
+        // The error stops the compilation once verification is done.
         // create record
         let fn_rec = Record::Fn {
             name: f.name.clone(),
@@ -365,8 +372,7 @@ impl SymbolTableBuilder<'_> {
             _ => {
                 self.err
                     .unexpected_error(true, Some(UNEXPECTED_ERR_MSG.to_string()), None);
-                // should have exited above (since it's a fatal error)
-                unreachable!()
+                return;
             }
         };
 
@@ -426,6 +432,78 @@ impl SymbolTableBuilder<'_> {
         self.add_global_id_to_curr_rec(id);
     }
 
+    /// Collect the aggregations updated in `stmts`, they must be updated consistently across
+    /// the script (same aggregating function, same number of keys).
+    fn collect_aggregations(&mut self, stmts: &[Statement]) {
+        for stmt in stmts.iter() {
+            match stmt {
+                Statement::Aggregate {
+                    name,
+                    keys,
+                    func,
+                    loc,
+                    ..
+                } => {
+                    if let Some(other) = self.curr_aggregations.get(name) {
+                        if other.func != *func || other.num_keys != keys.len() {
+                            self.err.type_check_error(
+                                false,
+                                format!(
+                                    "Aggregation `@{name}` is updated with `{}` over {} key(s), \
+                                    but was previously updated with `{}` over {} key(s)",
+                                    func.name(),
+                                    keys.len(),
+                                    other.func.name(),
+                                    other.num_keys
+                                ),
+                                &loc.clone().map(|l| l.line_col),
+                            );
+                        }
+                    } else {
+                        self.curr_aggregations.insert(
+                            name.clone(),
+                            Aggregation {
+                                name: name.clone(),
+                                func: func.clone(),
+                                num_keys: keys.len(),
+                                loc: loc.clone(),
+                            },
+                        );
+                    }
+                }
+                Statement::If { conseq, alt, .. } => {
+                    self.collect_aggregations(&conseq.stmts);
+                    self.collect_aggregations(&alt.stmts);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Insert a record for each of the current script's aggregations, the record
+    /// holds the address of the aggregation's update function post-injection.
+    fn add_aggregations(&mut self, script: &mut Script) {
+        for (name, agg) in self.curr_aggregations.drain() {
+            let symbol = Aggregation::symbol_name(&name);
+            let id = self.table.put(
+                symbol.clone(),
+                Record::Fn {
+                    name: FnId {
+                        name: symbol,
+                        loc: agg.loc.clone(),
+                    },
+                    params: vec![],
+                    ret_ty: DataType::Tuple { ty_info: vec![] },
+                    def: Definition::CompilerDynamic,
+                    addr: None,
+                    loc: agg.loc.clone(),
+                },
+            );
+            self.add_fn_id_to_curr_rec(id);
+            script.aggregations.insert(name, agg);
+        }
+    }
+
     fn visit_provided_globals(&mut self, globals: &HashMap<String, ProvidedGlobal>) {
         for (name, ProvidedGlobal { global, .. }) in globals.iter() {
             self.add_global(global.ty.clone(), name.clone(), true, None);
@@ -481,15 +559,18 @@ impl WhammVisitorMut<()> for SymbolTableBuilder<'_> {
             if let Statement::Decl { ty, var_id, .. } = stmt {
                 if let Expr::VarId { name, .. } = &var_id {
                     // Add global variable to script globals (triggers the init_generator to emit them!)
-                    script.globals.insert(
-                        name.clone(),
-                        Global {
-                            def: Definition::User,
-                            ty: ty.clone(),
-                            var_name: var_id.clone(),
-                            value: None,
-                        },
-                    );
+                    // `this.x`/`frame.x` get a local in each instrumented function instead
+                    if !is_scoped_var(name) {
+                        script.globals.insert(
+                            name.clone(),
+                            Global {
+                                def: Definition::User,
+                                ty: ty.clone(),
+                                var_name: var_id.clone(),
+                                value: None,
+                            },
+                        );
+                    }
                 } else {
                     self.err.unexpected_error(
                         true,
@@ -509,6 +590,7 @@ impl WhammVisitorMut<()> for SymbolTableBuilder<'_> {
             .providers
             .iter_mut()
             .for_each(|(_name, provider)| self.visit_provider(provider));
+        self.add_aggregations(script);
 
         trace!("Exiting: visit_script");
         if let Err(e) = self.table.exit_scope() {
@@ -520,7 +602,10 @@ impl WhammVisitorMut<()> for SymbolTableBuilder<'_> {
     fn visit_provider(&mut self, provider: &mut Box<dyn Provider>) {
         trace!("Entering: visit_provider");
 
-        self.add_provider(provider.as_ref());
+        if !self.add_provider(provider.as_ref()) {
+            // the provider's name is taken, there's no scope to add its contents to
+            return;
+        }
         provider
             .get_provided_fns_mut()
             .iter_mut()
@@ -591,7 +676,10 @@ impl WhammVisitorMut<()> for SymbolTableBuilder<'_> {
             .for_each(|f| self.visit_fn(&mut f.function));
         self.visit_provided_globals(probe.get_mode_provided_globals());
 
-        // Will not visit predicate/body at this stage
+        // Will not visit predicate/body at this stage, other than to find the aggregations
+        if let Some(body) = probe.body() {
+            self.collect_aggregations(&body.stmts);
+        }
 
         trace!("Exiting: visit_probe");
         if let Err(e) = self.table.exit_scope() {
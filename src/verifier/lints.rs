@@ -0,0 +1,352 @@
+use crate::common::error::{CodeLocation, ErrorGen, WhammWarning};
+use crate::parser::rules::{Event, Package, Probe, Provider};
+use crate::parser::types::{
+    BinOp, Block, DataType, Expr, Fn, Location, Script, Statement, UnOp, Value, Whamm, WhammVisitor,
+};
+use log::trace;
+use pest::error::LineColLocation;
+use std::collections::HashSet;
+
+/// Look for code that is valid, but likely a mistake, reporting each as a warning.
+/// This should only be run on scripts that type check.
+pub fn lint(ast: &Whamm, err: &mut ErrorGen) {
+    trace!("Entered lint");
+    let mut linter = Linter {
+        scopes: vec![],
+        in_script_global: false,
+        warnings: vec![],
+        seen: HashSet::new(),
+    };
+    linter.visit_whamm(ast);
+
+    // Probe bodies are copied into each event their spec matches, so the warnings are
+    // de-duplicated above and sorted here to be reported in the order of the script.
+    linter.warnings.sort_by_key(|(line_col, _)| *line_col);
+    for (_, warning) in linter.warnings {
+        err.add_warn(warning);
+    }
+}
+
+/// A variable declared in the script
+struct Var {
+    name: String,
+    /// global, local or parameter
+    kind: &'static str,
+    loc: Option<Location>,
+    is_read: bool,
+    is_written: bool,
+}
+
+struct Linter {
+    /// The variables in scope, the innermost scope is last
+    scopes: Vec<Vec<Var>>,
+    in_script_global: bool,
+    /// The warnings to report, keyed by where they start in the script
+    warnings: Vec<((usize, usize), WhammWarning)>,
+    seen: HashSet<(String, (usize, usize))>,
+}
+
+impl Linter {
+    fn warn(&mut self, message: String, loc: &Option<Location>, info: Option<(String, &Location)>) {
+        let line_col = match loc {
+            Some(loc) => loc.line_col.clone(),
+            None => return,
+        };
+        let start = match &line_col {
+            LineColLocation::Pos(pos) | LineColLocation::Span(pos, _) => *pos,
+        };
+        if !self.seen.insert((message.clone(), start)) {
+            return;
+        }
+
+        let mut warning = ErrorGen::get_lint_warn(message, Some(line_col));
+        warning.info_loc = info.map(|(message, loc)| CodeLocation {
+            is_err: false,
+            message: Some(message),
+            line_col: loc.line_col.clone(),
+            line_str: None,
+            line2_str: None,
+        });
+        self.warnings.push((start, warning));
+    }
+
+    fn declare(&mut self, name: &str, kind: &'static str, loc: &Option<Location>) {
+        let shadowed = self.scopes.iter().rev().skip(1).find_map(|scope| {
+            scope
+                .iter()
+                .find(|var| var.name == name)
+                .map(|var| (var.kind, var.loc.clone()))
+        });
+        if let Some((shadowed_kind, Some(shadowed_loc))) = shadowed {
+            self.warn(
+                format!("The {kind} `{name}` shadows the {shadowed_kind} with the same name"),
+                loc,
+                Some((
+                    format!("{shadowed_kind} `{name}` declared here"),
+                    &shadowed_loc,
+                )),
+            );
+        }
+
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.push(Var {
+                name: name.to_string(),
+                kind,
+                loc: loc.clone(),
+                is_read: false,
+                is_written: false,
+            });
+        }
+    }
+
+    fn lookup(&mut self, name: &str) -> Option<&mut Var> {
+        self.scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.iter_mut().find(|var| var.name == name))
+    }
+
+    fn enter_scope(&mut self) {
+        self.scopes.push(vec![]);
+    }
+
+    /// Leave the current scope, warning about the variables in it that were never used.
+    /// Locals have to be read to be used, a global could just be written (the instrumented
+    /// program can read it).
+    fn exit_scope(&mut self) {
+        let scope = self.scopes.pop().unwrap_or_default();
+        for var in scope {
+            let is_used = match var.kind {
+                "global" => var.is_read || var.is_written,
+                "local" => var.is_read,
+                // the signature of a function is up to the user
+                _ => true,
+            };
+            if !is_used {
+                self.warn(
+                    format!("The {} `{}` is never used", var.kind, var.name),
+                    &var.loc,
+                    None,
+                );
+            }
+        }
+    }
+
+    fn mark_read(&mut self, name: &str) {
+        if let Some(var) = self.lookup(name) {
+            var.is_read = true;
+        }
+    }
+
+    fn mark_written(&mut self, name: &str) {
+        // initializing a global doesn't count as using it
+        if self.in_script_global {
+            return;
+        }
+        if let Some(var) = self.lookup(name) {
+            var.is_written = true;
+        }
+    }
+
+    fn check_fn_returns(&mut self, function: &Fn) {
+        let returns_unit =
+            matches!(&function.return_ty, DataType::Tuple { ty_info } if ty_info.is_empty());
+        if !returns_unit && !block_returns(&function.body) {
+            self.warn(
+                format!(
+                    "Not all paths through `{}` return a value",
+                    function.name.name
+                ),
+                &function.name.loc,
+                None,
+            );
+        }
+    }
+}
+
+/// Whether every path through this block ends in a `return`
+fn block_returns(block: &Block) -> bool {
+    block.stmts.iter().any(stmt_returns)
+}
+
+fn stmt_returns(stmt: &Statement) -> bool {
+    match stmt {
+        Statement::Return { .. } => true,
+        Statement::If { conseq, alt, .. } => block_returns(conseq) && block_returns(alt),
+        _ => false,
+    }
+}
+
+impl WhammVisitor<()> for Linter {
+    fn visit_whamm(&mut self, whamm: &Whamm) {
+        // the compiler provided functions and globals are always there to use
+        whamm
+            .scripts
+            .iter()
+            .for_each(|script| self.visit_script(script));
+    }
+
+    fn visit_script(&mut self, script: &Script) {
+        self.enter_scope();
+        self.in_script_global = true;
+        script
+            .global_stmts
+            .iter()
+            .for_each(|stmt| self.visit_stmt(stmt));
+        self.in_script_global = false;
+
+        script
+            .fns
+            .iter()
+            .for_each(|function| self.visit_fn(function));
+        script
+            .providers
+            .iter()
+            .for_each(|(_name, provider)| self.visit_provider(provider));
+        self.exit_scope();
+    }
+
+    fn visit_provider(&mut self, provider: &Box<dyn Provider>) {
+        provider
+            .packages()
+            .for_each(|package| self.visit_package(package));
+    }
+
+    fn visit_package(&mut self, package: &dyn Package) {
+        package.events().for_each(|event| self.visit_event(event));
+    }
+
+    fn visit_event(&mut self, event: &dyn Event) {
+        event.probes().iter().for_each(|(_mode, probe_list)| {
+            probe_list.iter().for_each(|probe| self.visit_probe(probe));
+        });
+    }
+
+    fn visit_probe(&mut self, probe: &Box<dyn Probe>) {
+        self.enter_scope();
+        if let Some(predicate) = probe.predicate() {
+            self.visit_expr(predicate);
+        }
+        if let Some(body) = probe.body() {
+            self.visit_block(body);
+        }
+        self.exit_scope();
+    }
+
+    fn visit_fn(&mut self, function: &Fn) {
+        self.enter_scope();
+        function
+            .params
+            .iter()
+            .for_each(|param| self.visit_formal_param(param));
+        self.visit_block(&function.body);
+        self.check_fn_returns(function);
+        self.exit_scope();
+    }
+
+    fn visit_formal_param(&mut self, param: &(Expr, DataType)) {
+        if let Expr::VarId { name, loc, .. } = &param.0 {
+            self.declare(name, "parameter", loc);
+        }
+    }
+
+    fn visit_block(&mut self, block: &Block) {
+        let mut returned = false;
+        for (i, stmt) in block.stmts.iter().enumerate() {
+            if returned {
+                let first = stmt.loc();
+                let last = block.stmts.last().and_then(|last| last.loc().as_ref());
+                let loc = match (first, last) {
+                    (Some(first), Some(last)) => {
+                        Some(Location::from(&first.line_col, &last.line_col, None))
+                    }
+                    _ => first.clone(),
+                };
+                self.warn(
+                    "Unreachable code detected, these statement(s) will not be executed"
+                        .to_string(),
+                    &loc,
+                    None,
+                );
+                // the rest of the block is still visited to see which variables it uses
+                block.stmts[i..]
+                    .iter()
+                    .for_each(|stmt| self.visit_stmt(stmt));
+                return;
+            }
+            self.visit_stmt(stmt);
+            returned = stmt_returns(stmt);
+        }
+    }
+
+    fn visit_stmt(&mut self, stmt: &Statement) {
+        match stmt {
+            Statement::Decl { var_id, .. } => {
+                if let Expr::VarId { name, loc, .. } = var_id {
+                    let kind = if self.in_script_global {
+                        "global"
+                    } else {
+                        "local"
+                    };
+                    self.declare(name, kind, loc);
+                }
+            }
+            Statement::Assign { var_id, expr, .. } => {
+                self.visit_expr(expr);
+                if let Expr::VarId { name, .. } = var_id {
+                    self.mark_written(name);
+                }
+            }
+            Statement::Expr { expr, .. } | Statement::Return { expr, .. } => self.visit_expr(expr),
+            Statement::If {
+                cond, conseq, alt, ..
+            } => {
+                self.visit_expr(cond);
+                self.visit_block(conseq);
+                self.visit_block(alt);
+            }
+            Statement::Aggregate { keys, args, .. } => {
+                keys.iter()
+                    .chain(args.iter())
+                    .for_each(|expr| self.visit_expr(expr));
+            }
+        }
+    }
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::UnOp { expr, .. } => self.visit_expr(expr),
+            Expr::Ternary {
+                cond, conseq, alt, ..
+            } => {
+                self.visit_expr(cond);
+                self.visit_expr(conseq);
+                self.visit_expr(alt);
+            }
+            Expr::BinOp { lhs, rhs, .. } => {
+                self.visit_expr(lhs);
+                self.visit_expr(rhs);
+            }
+            // the target is a function, not a variable
+            Expr::Call { args, .. } => {
+                if let Some(args) = args {
+                    args.iter().for_each(|arg| self.visit_expr(arg));
+                }
+            }
+            Expr::VarId { name, .. } => self.mark_read(name),
+            Expr::Primitive { val, .. } => self.visit_value(val),
+        }
+    }
+
+    fn visit_unop(&mut self, _unop: &UnOp) {}
+
+    fn visit_binop(&mut self, _binop: &BinOp) {}
+
+    fn visit_datatype(&mut self, _datatype: &DataType) {}
+
+    fn visit_value(&mut self, val: &Value) {
+        if let Value::Tuple { vals, .. } = val {
+            vals.iter().for_each(|val| self.visit_expr(val));
+        }
+    }
+}
@@ -1,4 +1,8 @@
+use crate::parser::rules::Probe;
 use crate::parser::tests;
+use crate::parser::types::{AggFn, BinOp, Block, DataType, Expr, Value, Whamm};
+use crate::verifier::lints::lint;
+use crate::verifier::types::{Record, SymbolTable};
 use crate::verifier::verifier;
 
 use crate::common::error::ErrorGen;
@@ -17,7 +21,6 @@ pub fn setup_logger() {
 // ====================
 
 const VALID_SCRIPTS: &[&str] = &[
-    "wasm:opcode:call:alt { new_target_fn_name = redirect_to_fault_injector; }",
     r#"
         bool a;
         i32 b;
@@ -68,6 +71,30 @@ const VALID_SCRIPTS: &[&str] = &[
             i32 b = my_fn(a);
         }
     "#,
+    // aggregations
+    r#"
+        wasm:opcode:call:before {
+            @calls[fn_id, imm0] = count();
+            @args[fn_id] = quantize(arg0);
+        }
+        wasm:opcode:br_if:before {
+            @taken[tos != 0] = count();
+            if (fn_id > 2) {
+                @calls[fn_id, 0] = count();
+            }
+        }
+    "#,
+    // scoped variables
+    r#"
+        i32 this.cond;
+        i32 frame.taken;
+        wasm:opcode:br_if:before {
+            this.cond = tos;
+        }
+        wasm:opcode:br_if:after / this.cond != 0 / {
+            frame.taken++;
+        }
+    "#,
 ];
 
 const TYPE_ERROR_SCRIPTS: &[&str] = &[
@@ -84,7 +111,12 @@ wasm::call:alt /
     "#,
     r#"wasm:opcode:br:before / "i" <= 1 / { }"#,
     r#"wasm::call:alt / (1 + 3) / {  }"#, // final type in predicate
+    r#"i32 i; wasm::call:alt / i < 1 < 2 / {  }"#, // comparing a bool to an int
     r#"wasm::call:alt / !1 / { }"#,       // unop
+    r#"wasm:opcode:br_if:before { tos = 1; }"#, // tos is read-only
+    r#"f64 x; wasm::call:alt { x = x % 2; }"#, // no remainder for floats
+    // undefined variable
+    "wasm:opcode:call:alt { new_target_fn_name = redirect_to_fault_injector; }",
     // stmt
     // Compiler provided global
     r#"
@@ -303,6 +335,52 @@ wasm::call:alt /
             i32 strcmp;
         }
     "#,
+    // aggregations
+    r#"
+        wasm::call:alt {
+            @calls[fn_name] = count();
+        }
+    "#,
+    r#"
+        i64 x;
+        wasm::call:alt {
+            @total = sum(x);
+        }
+    "#,
+    r#"
+        wasm:opcode:call:before {
+            @calls[fn_id] = count();
+        }
+        wasm:opcode:br:before {
+            @calls[fn_id] = sum(instr_idx);
+        }
+    "#,
+    r#"
+        wasm:opcode:call:before {
+            @calls[fn_id] = count();
+        }
+        wasm:opcode:br:before {
+            @calls[fn_id, pc] = count();
+        }
+    "#,
+    // scoped variables
+    r#"
+        wasm:opcode:call:before {
+            i32 this.x;
+        }
+    "#,
+    r#"
+        i32 frame.x = 1;
+    "#,
+    r#"
+        str this.name;
+    "#,
+    r#"
+        i32 frame.x;
+        my_fn() -> i32 {
+            return frame.x;
+        }
+    "#,
 ];
 
 // =============
@@ -342,8 +420,8 @@ wasm::call:alt /
 
     // 7 scopes: whamm, strcmp, script0, wasm, alt_call_by_name, alt_call_by_id, opcode, call, alt
     let num_scopes = 9;
-    // records: num_scopes PLUS (str_addr, func_id, func_name, value, wasm_opcode_loc, target_imp_name, target_fn_type, target_imp_module, imm0, arg[0:9]+)
-    let num_recs = num_scopes + 10;
+    // records: num_scopes PLUS (str_addr, func_id, func_name, value, wasm_opcode_loc, fn_id, fn_name, instr_idx, pc, src_file, src_line, src_col, tos, local[0:9]+, target_imp_name, target_fn_type, target_imp_module, imm0, arg[0:9]+)
+    let num_recs = num_scopes + 19;
 
     // asserts on very high level table structure
     assert_eq!(num_scopes, table.scopes.len());
@@ -352,6 +430,50 @@ wasm::call:alt /
     assert_eq!(num_recs, table.records.len());
 }
 
+fn get_alt_probe(whamm: &Whamm) -> &dyn Probe {
+    whamm
+        .scripts
+        .first()
+        .unwrap()
+        .providers
+        .get("wasm")
+        .unwrap()
+        .packages()
+        .next()
+        .unwrap()
+        .events()
+        .next()
+        .unwrap()
+        .probes()
+        .get("alt")
+        .unwrap()
+        .first()
+        .unwrap()
+        .as_ref()
+}
+
+fn get_alt_pred(whamm: &Whamm) -> Expr {
+    get_alt_probe(whamm).predicate().clone().unwrap()
+}
+
+fn get_alt_body(whamm: &Whamm) -> Block {
+    get_alt_probe(whamm).body().clone().unwrap()
+}
+
+fn define_arg(table: &mut SymbolTable, name: &str, ty: DataType) {
+    table.put(
+        name.to_string(),
+        Record::Var {
+            ty,
+            name: name.to_string(),
+            value: None,
+            is_comp_provided: false,
+            addr: None,
+            loc: None,
+        },
+    );
+}
+
 fn is_valid_script(script: &str, err: &mut ErrorGen) -> bool {
     let mut ast = tests::get_ast(script, err);
     let mut table = verifier::build_symbol_table(&mut ast, err);
@@ -396,20 +518,205 @@ pub fn test_template() {
     assert!(res);
 }
 #[test]
-pub fn test_expect_fatal() {
-    let result = std::panic::catch_unwind(|| {
-        expect_fatal_error();
-    });
-    match result {
-        Ok(_) => {
-            panic!("Expected a fatal error, but got Ok");
+pub fn test_location_globals() {
+    setup_logger();
+    let mut err = ErrorGen::new("".to_string(), "".to_string(), 0);
+    let script = r#"
+        i32 site;
+        wasm:opcode:br_if:before /
+            fn_name == "main" && fn_id > 0
+        / {
+            site = pc + instr_idx;
+        }
+    "#;
+    let mut ast = tests::get_ast(script, &mut err);
+    let mut table = verifier::build_symbol_table(&mut ast, &mut err);
+    let res = verifier::type_check(&mut ast, &mut table, &mut err);
+    err.report();
+    assert!(!err.has_errors);
+    assert!(res);
+}
+#[test]
+pub fn test_aggregations() {
+    setup_logger();
+    let mut err = ErrorGen::new("".to_string(), "".to_string(), 0);
+    let script = r#"
+        wasm:opcode:call:before {
+            @calls[fn_id] = count();
+        }
+        wasm:opcode:br_if:before {
+            @calls[fn_id] = count();
+            @depth = lquantize(imm0, 0, 8, 2);
+        }
+    "#;
+    let mut ast = tests::get_ast(script, &mut err);
+    let mut table = verifier::build_symbol_table(&mut ast, &mut err);
+    let res = verifier::type_check(&mut ast, &mut table, &mut err);
+    err.report();
+    assert!(!err.has_errors);
+    assert!(res);
+
+    // declared once for the script, bound to the (to-be-emitted) update function
+    let aggs = &ast.scripts.first().unwrap().aggregations;
+    assert_eq!(2, aggs.len());
+    assert_eq!(1, aggs.get("calls").unwrap().num_keys);
+    assert_eq!(
+        AggFn::LQuantize {
+            lo: 0,
+            hi: 8,
+            step: 2
+        },
+        aggs.get("depth").unwrap().func
+    );
+    // underflow, [0, 2), [2, 4), [4, 6), [6, 8), overflow
+    assert_eq!(6, aggs.get("depth").unwrap().func.num_buckets());
+
+    table.reset();
+    assert!(table.enter_named_scope("script0"));
+    for name in ["@calls", "@depth"] {
+        let rec_id = table.lookup(name).unwrap();
+        assert!(matches!(
+            table.get_record(rec_id),
+            Some(Record::Fn { addr: None, .. })
+        ));
+    }
+}
+#[test]
+pub fn test_scoped_vars() {
+    setup_logger();
+    let mut err = ErrorGen::new("".to_string(), "".to_string(), 0);
+    let script = r#"
+        i32 count;
+        i64 frame.calls;
+        wasm:opcode:call:before {
+            frame.calls++;
+            count = count + 1;
         }
-        Err(_) => {
-            //this means the function properly exited with a fatal error
+    "#;
+    let mut ast = tests::get_ast(script, &mut err);
+    let mut table = verifier::build_symbol_table(&mut ast, &mut err);
+    let res = verifier::type_check(&mut ast, &mut table, &mut err);
+    err.report();
+    assert!(!err.has_errors);
+    assert!(res);
+
+    // only bound to a local once it's used in an instrumented function
+    let globals = &ast.scripts.first().unwrap().globals;
+    assert!(globals.contains_key("count"));
+    assert!(!globals.contains_key("frame.calls"));
+
+    table.reset();
+    assert!(table.enter_named_scope("script0"));
+    let rec_id = table.lookup("frame.calls").unwrap();
+    assert!(matches!(
+        table.get_record(rec_id),
+        Some(Record::Var {
+            ty: DataType::I64,
+            addr: None,
+            ..
+        })
+    ));
+}
+#[test]
+pub fn test_site_vars() {
+    setup_logger();
+    let mut err = ErrorGen::new("".to_string(), "".to_string(), 0);
+    // typed once the probe's site is known, so these all pass the initial type check
+    let script = r#"
+        i64 total;
+        wasm:opcode:br_if:before / tos != 0 / {
+            total = total + local1;
+            local0 = local0 + 1;
         }
+    "#;
+    let mut ast = tests::get_ast(script, &mut err);
+    let mut table = verifier::build_symbol_table(&mut ast, &mut err);
+    let res = verifier::type_check(&mut ast, &mut table, &mut err);
+    err.report();
+    assert!(!err.has_errors);
+    assert!(res);
+}
+#[test]
+pub fn test_site_type_check() {
+    setup_logger();
+    let mut err = ErrorGen::new("".to_string(), "".to_string(), 0);
+    let script = "wasm:opcode:call:alt / arg0 == 1 && arg1 > 2 / { }";
+    let mut ast = tests::get_ast(script, &mut err);
+    let mut table = verifier::build_symbol_table(&mut ast, &mut err);
+    assert!(verifier::type_check(&mut ast, &mut table, &mut err));
+
+    // the site's callee takes (i64, f64)
+    define_arg(&mut table, "arg0", DataType::I64);
+    define_arg(&mut table, "arg1", DataType::F64);
+    let mut pred = Some(get_alt_pred(&ast));
+    let res = verifier::type_check_site(&mut pred, &mut None, "fn_id 1", &mut table, &mut err);
+    err.report();
+    assert!(res);
+    assert!(!err.has_errors);
+
+    // the integer literals take on the type of the args
+    if let Some(Expr::BinOp { lhs, .. }) = &pred {
+        if let Expr::BinOp { rhs, .. } = lhs.as_ref() {
+            assert!(matches!(
+                rhs.as_ref(),
+                Expr::Primitive {
+                    val: Value::Integer {
+                        ty: DataType::I64,
+                        ..
+                    },
+                    ..
+                }
+            ));
+        } else {
+            panic!("Expected a binary operation, got: {:?}", lhs);
+        }
+    } else {
+        panic!("Expected a binary operation, got: {:?}", pred);
+    }
+}
+#[test]
+pub fn test_site_type_errors() {
+    setup_logger();
+    let mut err = ErrorGen::new("".to_string(), "".to_string(), 0);
+    let script = r#"wasm:opcode:call:alt / arg0 == "str" / { }"#;
+    let mut ast = tests::get_ast(script, &mut err);
+    let mut table = verifier::build_symbol_table(&mut ast, &mut err);
+    // not caught until we know the type of `arg0`
+    assert!(verifier::type_check(&mut ast, &mut table, &mut err));
+
+    define_arg(&mut table, "arg0", DataType::I32);
+    let mut pred = Some(get_alt_pred(&ast));
+    let res = verifier::type_check_site(&mut pred, &mut None, "fn_id 1", &mut table, &mut err);
+    err.report();
+    assert!(!res);
+    assert!(err.has_errors);
+    assert!(matches!(pred, Some(Expr::BinOp { op: BinOp::EQ, .. })));
+}
+#[test]
+pub fn test_site_aggregation_type_errors() {
+    setup_logger();
+    let mut err = ErrorGen::new("".to_string(), "".to_string(), 0);
+    let script = "wasm:opcode:call:alt { @args[arg0] = sum(arg1); }";
+    let mut ast = tests::get_ast(script, &mut err);
+    let mut table = verifier::build_symbol_table(&mut ast, &mut err);
+    // not caught until we know the types of the args
+    assert!(verifier::type_check(&mut ast, &mut table, &mut err));
+
+    // the site's callee takes (i64, f64)
+    define_arg(&mut table, "arg0", DataType::I64);
+    define_arg(&mut table, "arg1", DataType::F64);
+    let mut body = Some(get_alt_body(&ast));
+    let res = verifier::type_check_site(&mut None, &mut body, "fn_id 1", &mut table, &mut err);
+    assert!(!res);
+    let errors = err.take_diagnostics().errors;
+    assert_eq!(2, errors.len());
+    for (error, ty) in errors.iter().zip(["I64", "F64"]) {
+        let message = error.ty.message();
+        assert!(message.contains(&format!("found {ty}")), "{message}");
     }
 }
-pub fn expect_fatal_error() {
+#[test]
+pub fn test_expect_fatal() {
     setup_logger();
     let mut err = ErrorGen::new("".to_string(), "".to_string(), 0);
     let script = r#"
@@ -426,12 +733,14 @@ pub fn expect_fatal_error() {
             i32 strcmp;
         }
     "#;
+    // the errors are collected (and the `wasm` provider skipped) instead of exiting
     let mut ast = tests::get_ast(script, &mut err);
     let mut table = verifier::build_symbol_table(&mut ast, &mut err);
     let res = verifier::type_check(&mut ast, &mut table, &mut err);
     err.report();
     assert!(err.has_errors);
     assert!(!res);
+    assert!(err.check_has_errors().is_err());
 }
 #[test]
 pub fn test_recursive_calls() {
@@ -476,3 +785,70 @@ pub fn test_recursive_calls() {
 //     assert!(!err.has_errors);
 //     assert!(res);
 // }
+#[test]
+pub fn test_suggest_names() {
+    setup_logger();
+    let mut err = ErrorGen::new("".to_string(), "".to_string(), 0);
+    let script = r#"
+        i32 count;
+        add_one(i32 a) -> i32 {
+            return a + 1;
+        }
+        wasm:opcode:br_if:before {
+            cuont = add_on(count);
+        }
+    "#;
+    assert!(!is_valid_script(script, &mut err));
+
+    let notes: Vec<String> = err
+        .take_diagnostics()
+        .errors
+        .iter()
+        .filter_map(|e| e.info_loc.as_ref().and_then(|loc| loc.message.clone()))
+        .collect();
+    assert!(notes.contains(&"did you mean `count`?".to_string()));
+    assert!(notes.contains(&"did you mean `add_one`?".to_string()));
+}
+#[test]
+pub fn test_lints() {
+    setup_logger();
+    let mut err = ErrorGen::new("".to_string(), "".to_string(), 0);
+    let script = r#"
+        i32 unused_global;
+        i32 count;
+        add_one(i32 count) -> i32 {
+            i32 unused_local;
+            return count + 1;
+            count = 2;
+        }
+        maybe(i32 a) -> i32 {
+            if (a > 0) {
+                return a;
+            };
+        }
+        wasm:opcode:br_if:before {
+            count = add_one(count) + maybe(count);
+        }
+    "#;
+    let mut ast = tests::get_ast(script, &mut err);
+    let mut table = verifier::build_symbol_table(&mut ast, &mut err);
+    assert!(verifier::type_check(&mut ast, &mut table, &mut err));
+    lint(&ast, &mut err);
+
+    let warnings: Vec<String> = err
+        .take_diagnostics()
+        .warnings
+        .iter()
+        .map(|warning| warning.ty.message().to_string())
+        .collect();
+    assert_eq!(
+        warnings,
+        vec![
+            "The global `unused_global` is never used",
+            "The parameter `count` shadows the global with the same name",
+            "The local `unused_local` is never used",
+            "Unreachable code detected, these statement(s) will not be executed",
+            "Not all paths through `maybe` return a value",
+        ]
+    );
+}
@@ -6,7 +6,7 @@ use std::fmt;
 const UNEXPECTED_ERR_MSG: &str =
     "SymbolTable: Looks like you've found a bug...please report this behavior!";
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct SymbolTable {
     pub scopes: Vec<Scope>,
     curr_scope: usize, // indexes into this::scopes
@@ -250,9 +250,28 @@ impl SymbolTable {
             }
         }
     }
+
+    /// The names of the records visible from the current scope (in it or one of its parents)
+    /// that satisfy `filter`.
+    pub fn visible_names(&self, filter: impl Fn(&Record) -> bool) -> Vec<&String> {
+        let mut names = vec![];
+        let mut next = self.curr_scope;
+        while let Some(scope) = self.scopes.get(next) {
+            for (name, rec_id) in scope.records.iter() {
+                if self.get_record(rec_id).is_some_and(&filter) {
+                    names.push(name);
+                }
+            }
+            next = match scope.parent {
+                Some(parent) => parent,
+                None => break,
+            };
+        }
+        names
+    }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Scope {
     pub id: usize, // indexes into SymbolTable::scopes
     pub name: String,
@@ -324,7 +343,7 @@ impl Scope {
     }
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub enum ScopeType {
     Whamm,
     Script,
@@ -367,7 +386,7 @@ impl fmt::Display for ScopeType {
 }
 
 /// The usize values in the record fields index into the SymbolTable::records Vec.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum Record {
     Whamm {
         name: String,
@@ -453,7 +472,7 @@ impl Record {
     }
 }
 
-#[derive(Debug, Eq, Hash, PartialEq)]
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
 /// the index of the variables (global/local) in app.wasm
 /// This is the relative index that's dependent on which function/module you're in.
 pub enum VarAddr {
@@ -1,6 +1,7 @@
 use std::vec;
 
 use crate::common::error::ErrorGen;
+use crate::common::suggest::closest_match;
 use crate::parser::rules::{Event, Package, Probe, Provider};
 use crate::parser::types::{
     BinOp, Block, DataType, Definition, Expr, Fn, Location, Script, Statement, UnOp, Value, Whamm,
@@ -8,6 +9,8 @@ use crate::parser::types::{
 };
 use crate::verifier::builder_visitor::SymbolTableBuilder;
 use crate::verifier::types::{Record, SymbolTable};
+use pest::error::LineColLocation;
+use std::collections::HashMap;
 
 const UNEXPECTED_ERR_MSG: &str =
     "TypeChecker: Looks like you've found a bug...please report this behavior! Exiting now...";
@@ -23,6 +26,7 @@ pub fn build_symbol_table(ast: &mut Whamm, err: &mut ErrorGen) -> SymbolTable {
         curr_event: None,
         curr_probe: None,
         curr_fn: None,
+        curr_aggregations: HashMap::new(),
     };
     visitor.visit_whamm(ast);
     visitor.table
@@ -75,13 +79,98 @@ pub fn check_duplicate_id(
     }
     false
 }
+/// Whether this is a variable bound to the state of the application at a specific
+/// site (`tos`, `local<N>` and `arg<N>`), which can only be typed once that site is known.
+pub fn is_site_var(name: &str) -> bool {
+    name == "tos"
+        || (name.starts_with("local") && name[5..].parse::<u32>().is_ok())
+        || (name.starts_with("arg") && name[3..].parse::<u32>().is_ok())
+}
+
+/// Whether this is a variable scoped to a single probe firing (`this.x`) or to an activation
+/// of the instrumented function (`frame.x`) rather than to the whole script.
+/// These are stored in locals of the instrumented function instead of wasm globals.
+pub fn is_scoped_var(name: &str) -> bool {
+    name.starts_with("this.") || name.starts_with("frame.")
+}
+
+/// Integer literals take on the type of the (wider) numeric value they're used with,
+/// e.g. the `1` in `arg0 == 1` becomes an i64 if `arg0` is an i64.
+/// Returns the type of `expr` after the coercion.
+fn coerce_int_literal(expr: &mut Expr, ty: DataType, other_ty: &DataType) -> DataType {
+    if let Expr::Primitive {
+        val: Value::Integer { ty: lit_ty, .. },
+        ..
+    } = expr
+    {
+        if matches!(other_ty, DataType::I64 | DataType::F32 | DataType::F64) {
+            *lit_ty = other_ty.clone();
+            return other_ty.clone();
+        }
+    }
+    ty
+}
+
+fn is_numeric(ty: &DataType) -> bool {
+    matches!(
+        ty,
+        DataType::I32 | DataType::I64 | DataType::F32 | DataType::F64 | DataType::AssumeGood
+    )
+}
+
+fn is_float(ty: &DataType) -> bool {
+    matches!(ty, DataType::F32 | DataType::F64)
+}
+
 struct TypeChecker<'a> {
     table: &'a mut SymbolTable,
     err: &'a mut ErrorGen,
     in_script_global: bool,
+    in_fn: bool,
+    /// Set when re-checking a probe at a specific site in the application (see `type_check_site`)
+    site: Option<&'a str>,
+    num_errors: usize,
 }
 
 impl TypeChecker<'_> {
+    fn type_check_error(&mut self, message: String, loc: &Option<LineColLocation>) {
+        self.type_check_error_with_suggestion(message, loc, None);
+    }
+
+    fn type_check_error_with_suggestion(
+        &mut self,
+        message: String,
+        loc: &Option<LineColLocation>,
+        suggestion: Option<&String>,
+    ) {
+        let message = match self.site {
+            Some(site) => format!("{message} (at {site})"),
+            None => message,
+        };
+        self.num_errors += 1;
+        self.err.add_error(
+            ErrorGen::get_type_check_error(false, message, loc).with_suggestion(suggestion),
+        );
+    }
+
+    /// Report a use of `name` that isn't in scope, suggesting the closest variable (or function,
+    /// if `is_fn`) that is.
+    fn not_found_error(
+        &mut self,
+        message: String,
+        name: &str,
+        is_fn: bool,
+        loc: &Option<LineColLocation>,
+    ) {
+        let names = self.table.visible_names(|rec| match rec {
+            Record::Var { .. } => !is_fn,
+            Record::Fn { .. } => is_fn,
+            _ => false,
+        });
+        let suggestion = closest_match(name, names).cloned();
+        self.type_check_error_with_suggestion(message, loc, suggestion.as_ref());
+    }
+
     fn add_local(
         &mut self,
         ty: DataType,
@@ -194,8 +283,7 @@ impl WhammVisitorMut<Option<DataType>> for TypeChecker<'_> {
             let predicate_loc = predicate.loc().clone().unwrap();
             if let Some(ty) = self.visit_expr(predicate) {
                 if ty != DataType::Boolean {
-                    self.err.type_check_error(
-                        false,
+                    self.type_check_error(
                         "Predicate must be of type boolean".to_owned(),
                         &Some(predicate_loc.line_col),
                     );
@@ -218,11 +306,13 @@ impl WhammVisitorMut<Option<DataType>> for TypeChecker<'_> {
         // type check body
 
         self.table.enter_named_scope(&function.name.name);
-        if let Some(check_ret_type) = self.visit_block(&mut function.body) {
+        self.in_fn = true;
+        let body_ret_type = self.visit_block(&mut function.body);
+        self.in_fn = false;
+        if let Some(check_ret_type) = body_ret_type {
             //figure out how to deal with void functions (return type is ())
             if check_ret_type != function.return_ty {
-                self.err.type_check_error(
-                    false,
+                self.type_check_error(
                     format!(
                         "The function signature for '{}' returns '{:?}', but the body returns '{:?}'",
                         function.name.name, function.return_ty, check_ret_type
@@ -243,29 +333,12 @@ impl WhammVisitorMut<Option<DataType>> for TypeChecker<'_> {
 
     fn visit_block(&mut self, block: &mut Block) -> Option<DataType> {
         let mut ret_type = None;
-        let num_statements = block.stmts.len();
-        let start_of_range: usize;
-        for i in 0..num_statements {
-            let temp = self.visit_stmt(&mut block.stmts[i]);
+        for stmt in block.stmts.iter_mut() {
+            let temp = self.visit_stmt(stmt);
             if temp.is_some() && ret_type.is_none() {
                 ret_type = temp;
             } else if ret_type.is_some() {
-                start_of_range = i;
-                //get the span for the first statement to the last one
-                let loc = Location::from(
-                    &block.stmts[start_of_range].loc().clone().unwrap().line_col,
-                    &block.stmts[num_statements - 1]
-                        .loc()
-                        .clone()
-                        .unwrap()
-                        .line_col,
-                    None,
-                );
-                self.err.add_typecheck_warn(
-                    "Unreachable code detected, these statement(s) will not be executed"
-                        .to_string(),
-                    Some(loc.line_col),
-                );
+                // the rest is unreachable, this is reported by the lint pass (see `lints.rs`)
                 block.return_ty = ret_type.clone();
                 return ret_type;
             }
@@ -281,8 +354,7 @@ impl WhammVisitorMut<Option<DataType>> for TypeChecker<'_> {
                 //allow declarations and assignment
                 Statement::Decl { .. } | Statement::Assign { .. } => {}
                 _ => {
-                    self.err.type_check_error(
-                        false,
+                    self.type_check_error(
                         "Only variable declarations and assignment are allowed in the global scope"
                             .to_owned(),
                         &stmt.loc().clone().map(|l| l.line_col),
@@ -295,18 +367,27 @@ impl WhammVisitorMut<Option<DataType>> for TypeChecker<'_> {
             Statement::Assign { var_id, expr, .. } => {
                 // change type in symbol table?
                 let lhs_loc = var_id.loc().clone().unwrap();
+                if let Expr::VarId { name, .. } = var_id {
+                    if name == "tos" {
+                        self.type_check_error(
+                            "`tos` is read-only, it can't be assigned to".to_owned(),
+                            &Some(lhs_loc.line_col.clone()),
+                        );
+                        return None;
+                    }
+                }
                 let rhs_loc = expr.loc().clone().unwrap();
                 let lhs_ty_op = self.visit_expr(var_id);
                 let rhs_ty_op = self.visit_expr(expr);
 
                 if let (Some(lhs_ty), Some(rhs_ty)) = (lhs_ty_op, rhs_ty_op) {
+                    let rhs_ty = coerce_int_literal(expr, rhs_ty, &lhs_ty);
                     if lhs_ty == rhs_ty {
                         None
                     } else {
                         // using a struct in parser to merge two locations
                         let loc = Location::from(&lhs_loc.line_col, &rhs_loc.line_col, None);
-                        self.err.type_check_error(
-                            false,
+                        self.type_check_error(
                             format! {"Type Mismatch, lhs:{:?}, rhs:{:?}", lhs_ty, rhs_ty},
                             &Some(loc.line_col),
                         );
@@ -315,8 +396,7 @@ impl WhammVisitorMut<Option<DataType>> for TypeChecker<'_> {
                     }
                 } else {
                     let loc = Location::from(&lhs_loc.line_col, &rhs_loc.line_col, None);
-                    self.err.type_check_error(
-                        false,
+                    self.type_check_error(
                         "Can't get type of lhs or rhs of this assignment".to_string(),
                         &Some(loc.line_col),
                     );
@@ -331,7 +411,31 @@ impl WhammVisitorMut<Option<DataType>> for TypeChecker<'_> {
                 ty, var_id, loc, ..
             } => {
                 if let Expr::VarId { name, .. } = var_id {
-                    if !self.in_script_global {
+                    if is_scoped_var(name) && !self.in_script_global {
+                        self.type_check_error(
+                            format!("`{name}` must be declared in the global scope of the script"),
+                            &loc.clone().map(|l| l.line_col),
+                        );
+                        return None;
+                    }
+                    if is_scoped_var(name)
+                        && !matches!(
+                            ty,
+                            DataType::I32
+                                | DataType::I64
+                                | DataType::F32
+                                | DataType::F64
+                                | DataType::Boolean
+                        )
+                    {
+                        self.type_check_error(
+                            format!("`{name}` is stored in a local, it must be a number or a bool, found {ty:?}"),
+                            &loc.clone().map(|l| l.line_col),
+                        );
+                        return None;
+                    }
+                    // at a site, the probe's locals have already been added
+                    if !self.in_script_global && self.site.is_none() {
                         self.add_local(ty.to_owned(), name.to_owned(), false, loc);
                     }
                 } else {
@@ -348,13 +452,44 @@ impl WhammVisitorMut<Option<DataType>> for TypeChecker<'_> {
                 None
             }
             Statement::Return { expr, loc: _loc } => self.visit_expr(expr),
+            Statement::Aggregate {
+                name, keys, args, ..
+            } => {
+                // the tables hold i32 keys and values
+                for key in keys.iter_mut() {
+                    let key_loc = key.loc().clone().map(|l| l.line_col);
+                    match self.visit_expr(key) {
+                        Some(DataType::I32 | DataType::Boolean | DataType::AssumeGood) | None => {}
+                        Some(ty) => self.type_check_error(
+                            format!(
+                                "Keys of aggregation `@{name}` must be i32 or bool, found {ty:?} \
+                                (its table only holds i32 keys)"
+                            ),
+                            &key_loc,
+                        ),
+                    }
+                }
+                for arg in args.iter_mut() {
+                    let arg_loc = arg.loc().clone().map(|l| l.line_col);
+                    match self.visit_expr(arg) {
+                        Some(DataType::I32 | DataType::AssumeGood) | None => {}
+                        Some(ty) => self.type_check_error(
+                            format!(
+                                "Values aggregated by `@{name}` must be i32, found {ty:?} \
+                                (its table only holds i32 values)"
+                            ),
+                            &arg_loc,
+                        ),
+                    }
+                }
+                None
+            }
             Statement::If {
                 cond, conseq, alt, ..
             } => {
                 let cond_ty = self.visit_expr(cond);
                 if cond_ty != Some(DataType::Boolean) {
-                    self.err.type_check_error(
-                        false,
+                    self.type_check_error(
                         format!(
                             "Condition must be of type boolean, found {:?}",
                             cond_ty.unwrap()
@@ -381,8 +516,7 @@ impl WhammVisitorMut<Option<DataType>> for TypeChecker<'_> {
                     }
                     //check that they are not returning different types if neither is () or None
                     //error here
-                    self.err.type_check_error(
-                        false,
+                    self.type_check_error(
                         "Return type of if and else blocks do not match".to_owned(),
                         &Some(
                             Location::from(
@@ -408,19 +542,27 @@ impl WhammVisitorMut<Option<DataType>> for TypeChecker<'_> {
                 let lhs_ty_op = self.visit_expr(lhs);
                 let rhs_ty_op = self.visit_expr(rhs);
                 if let (Some(lhs_ty), Some(rhs_ty)) = (lhs_ty_op, rhs_ty_op) {
+                    let lhs_ty = coerce_int_literal(lhs, lhs_ty, &rhs_ty);
+                    let rhs_ty = coerce_int_literal(rhs, rhs_ty, &lhs_ty);
                     match op {
                         BinOp::Add
                         | BinOp::Subtract
                         | BinOp::Multiply
                         | BinOp::Divide
                         | BinOp::Modulo => {
-                            if lhs_ty == DataType::I32 && rhs_ty == DataType::I32 {
-                                Some(DataType::I32)
+                            let is_float_rem = matches!(op, BinOp::Modulo)
+                                && (is_float(&lhs_ty) || is_float(&rhs_ty));
+                            if is_numeric(&lhs_ty) && lhs_ty == rhs_ty && !is_float_rem {
+                                // the result has the type of its operands
+                                if let DataType::AssumeGood = lhs_ty {
+                                    Some(rhs_ty)
+                                } else {
+                                    Some(lhs_ty)
+                                }
                             } else {
                                 let loc =
                                     Location::from(&lhs_loc.line_col, &rhs_loc.line_col, None);
-                                self.err.type_check_error(
-                                    false,
+                                self.type_check_error(
                                     format! {"Type Mismatch, lhs:{:?}, rhs:{:?}", lhs_ty, rhs_ty},
                                     &Some(loc.line_col),
                                 );
@@ -431,8 +573,7 @@ impl WhammVisitorMut<Option<DataType>> for TypeChecker<'_> {
                             if lhs_ty == DataType::Boolean && rhs_ty == DataType::Boolean {
                                 Some(DataType::Boolean)
                             } else {
-                                self.err.type_check_error(
-                                    false,
+                                self.type_check_error(
                                     "Different types for lhs and rhs".to_owned(),
                                     &None,
                                 );
@@ -447,8 +588,7 @@ impl WhammVisitorMut<Option<DataType>> for TypeChecker<'_> {
                                 // using a struct in parser to merge two locations
                                 let loc =
                                     Location::from(&lhs_loc.line_col, &rhs_loc.line_col, None);
-                                self.err.type_check_error(
-                                    false,
+                                self.type_check_error(
                                     format! {"Type Mismatch, lhs:{:?}, rhs:{:?}", lhs_ty, rhs_ty},
                                     &Some(loc.line_col),
                                 );
@@ -457,14 +597,13 @@ impl WhammVisitorMut<Option<DataType>> for TypeChecker<'_> {
                             }
                         }
                         BinOp::GT | BinOp::LT | BinOp::GE | BinOp::LE => {
-                            if lhs_ty == DataType::I32 && rhs_ty == DataType::I32 {
+                            if is_numeric(&lhs_ty) && lhs_ty == rhs_ty {
                                 Some(DataType::Boolean)
                             } else {
                                 // using a struct in parser to merge two locations
                                 let loc =
                                     Location::from(&lhs_loc.line_col, &rhs_loc.line_col, None);
-                                self.err.type_check_error(
-                                    false,
+                                self.type_check_error(
                                     format! {"Type Mismatch, lhs:{:?}, rhs:{:?}", lhs_ty, rhs_ty},
                                     &Some(loc.line_col),
                                 );
@@ -475,8 +614,7 @@ impl WhammVisitorMut<Option<DataType>> for TypeChecker<'_> {
                     }
                 } else {
                     let loc = Location::from(&lhs_loc.line_col, &rhs_loc.line_col, None);
-                    self.err.type_check_error(
-                        false,
+                    self.type_check_error(
                         "Can't get type of lhs or rhs of this binary operation".to_string(),
                         &Some(loc.line_col),
                     );
@@ -485,11 +623,42 @@ impl WhammVisitorMut<Option<DataType>> for TypeChecker<'_> {
                 }
             }
             Expr::VarId { name, loc, .. } => {
-                // TODO: may have a more principled way to handle this (with SymbolTable)
-                // if name is prefixed with arg, report error
-                if name.starts_with("arg") && name[3..].parse::<u32>().is_ok() {
+                // these live in the locals of the instrumented function
+                if is_scoped_var(name) && (self.in_script_global || self.in_fn) {
+                    self.type_check_error(
+                        format!("`{name}` only has a value while a probe is running, it can only be used in a probe"),
+                        &loc.clone().map(|l| l.line_col),
+                    );
                     return Some(DataType::AssumeGood);
                 }
+                // these can only be typed once we know the site they're used at
+                if is_site_var(name) {
+                    if self.site.is_none() {
+                        return Some(DataType::AssumeGood);
+                    }
+                    let ty = match self
+                        .table
+                        .lookup(name)
+                        .and_then(|id| self.table.get_record(id))
+                    {
+                        Some(Record::Var { ty, .. }) => ty.clone(),
+                        _ => DataType::AssumeGood,
+                    };
+                    if matches!(ty, DataType::AssumeGood) {
+                        let msg = if name == "tos" {
+                            "`tos` is not available here, there is no value on the operand stack"
+                                .to_string()
+                        } else if name.starts_with("arg") {
+                            format!(
+                                "`{name}` is not available here, the probed instruction has no such argument"
+                            )
+                        } else {
+                            format!("`{name}` is not a local of this function")
+                        };
+                        self.type_check_error(msg, &loc.clone().map(|l| l.line_col));
+                    }
+                    return Some(ty);
+                }
 
                 // get type from symbol table
                 if let Some(id) = self.table.lookup(name) {
@@ -505,12 +674,18 @@ impl WhammVisitorMut<Option<DataType>> for TypeChecker<'_> {
                             )
                         }
                     } else {
-                        self.err.type_check_error(
-                            false,
+                        self.type_check_error(
                             format! {"Can't look up {} in symbol table", name},
                             &loc.clone().map(|l| l.line_col),
                         );
                     }
+                } else {
+                    self.not_found_error(
+                        format!("`{name}` is not defined"),
+                        name,
+                        false,
+                        &loc.clone().map(|l| l.line_col),
+                    );
                 }
 
                 Some(DataType::AssumeGood)
@@ -523,8 +698,7 @@ impl WhammVisitorMut<Option<DataType>> for TypeChecker<'_> {
                             if expr_ty == DataType::Boolean {
                                 Some(DataType::Boolean)
                             } else {
-                                self.err.type_check_error(
-                                    false,
+                                self.type_check_error(
                                     "Not operator can only be applied to boolean".to_owned(),
                                     &loc.clone().map(|l| l.line_col),
                                 );
@@ -533,8 +707,7 @@ impl WhammVisitorMut<Option<DataType>> for TypeChecker<'_> {
                         }
                     }
                 } else {
-                    self.err.type_check_error(
-                        false,
+                    self.type_check_error(
                         "Can't get type of expr of this unary operation".to_owned(),
                         &loc.clone().map(|l| l.line_col),
                     );
@@ -555,8 +728,7 @@ impl WhammVisitorMut<Option<DataType>> for TypeChecker<'_> {
                         match self.visit_expr(arg) {
                             Some(ty) => actual_param_tys.push(Some(ty)),
                             _ => {
-                                self.err.type_check_error(
-                                    false,
+                                self.type_check_error(
                                     "Can't get type of argument".to_owned(),
                                     &loc.clone().map(|l| l.line_col),
                                 );
@@ -569,8 +741,7 @@ impl WhammVisitorMut<Option<DataType>> for TypeChecker<'_> {
                 let fn_name = match fn_target.as_ref() {
                     Expr::VarId { name, .. } => name,
                     _ => {
-                        self.err.type_check_error(
-                            false,
+                        self.type_check_error(
                             "Function target must be a valid identifier.".to_owned(),
                             &loc.clone().map(|l| l.line_col),
                         );
@@ -593,8 +764,7 @@ impl WhammVisitorMut<Option<DataType>> for TypeChecker<'_> {
                             && !(*def == Definition::CompilerDynamic
                                 || *def == Definition::CompilerStatic)
                         {
-                            self.err.type_check_error(
-                                false,
+                            self.type_check_error(
                                 "Function calls to user def functions are not allowed in the global state of the script"
                                     .to_owned(),
                                 &loc.clone().map(|l| l.line_col),
@@ -619,16 +789,14 @@ impl WhammVisitorMut<Option<DataType>> for TypeChecker<'_> {
                                 (Some(expected), Some(actual)) => {
                                     // if actual is a tuple, it's not structural equality
                                     if expected != actual {
-                                        self.err.type_check_error(
-                                            false,
+                                        self.type_check_error(
                                             format! {"Expected type {:?} for the {} param, got {:?}", expected, i+1, actual},
                                             &args.clone().map(|a| a[i].loc().clone().unwrap().line_col),
                                         );
                                     }
                                 }
                                 _ => {
-                                    self.err.type_check_error(
-                                        false,
+                                    self.type_check_error(
                                         "Can't get type of argument".to_owned(),
                                         &loc.clone().map(|l| l.line_col),
                                     );
@@ -638,16 +806,16 @@ impl WhammVisitorMut<Option<DataType>> for TypeChecker<'_> {
 
                         return Some(ret_ty.clone());
                     } else {
-                        self.err.type_check_error(
-                            false,
+                        self.type_check_error(
                             format! {"Can't look up {} in symbol table", fn_name},
                             &loc.clone().map(|l| l.line_col),
                         );
                     }
                 } else {
-                    self.err.type_check_error(
-                        false,
+                    self.not_found_error(
                         format! {"Function {} not found in symbol table", fn_name},
+                        fn_name,
+                        true,
                         &loc.clone().map(|l| l.line_col),
                     );
                 }
@@ -662,8 +830,7 @@ impl WhammVisitorMut<Option<DataType>> for TypeChecker<'_> {
                 let cond_ty_clone = cond_ty.clone();
                 if let Some(ty) = cond_ty {
                     if ty != DataType::Boolean {
-                        self.err.type_check_error(
-                            false,
+                        self.type_check_error(
                             format!(
                                 "Condition must be of type boolean, found {:?}",
                                 cond_ty_clone.unwrap()
@@ -682,8 +849,7 @@ impl WhammVisitorMut<Option<DataType>> for TypeChecker<'_> {
                         if alt_t == conseq_t {
                             conseq_ty
                         } else {
-                            self.err.type_check_error(
-                                false,
+                            self.type_check_error(
                                 "Consequent and alternative must have the same type".to_owned(),
                                 &Some(
                                     Location::from(
@@ -698,8 +864,7 @@ impl WhammVisitorMut<Option<DataType>> for TypeChecker<'_> {
                         }
                     }
                     _ => {
-                        self.err.type_check_error(
-                            false,
+                        self.type_check_error(
                             "Can't get type of consequent or alternative".to_owned(),
                             &Some(
                                 Location::from(
@@ -767,8 +932,46 @@ pub fn type_check(ast: &mut Whamm, st: &mut SymbolTable, err: &mut ErrorGen) ->
         table: st,
         err,
         in_script_global: false,
+        in_fn: false,
+        site: None,
+        num_errors: 0,
     };
     type_checker.visit_whamm(ast);
     // note that parser errors might propagate here
     !err.has_errors
 }
+
+/// Re-check a probe once it has matched a specific site in the application.
+/// The site variables (see `is_site_var`) must already be defined in the probe's scope
+/// with their types at this site.
+/// Errors are reported with the passed `site` description.
+pub fn type_check_site(
+    predicate: &mut Option<Expr>,
+    body: &mut Option<Block>,
+    site: &str,
+    st: &mut SymbolTable,
+    err: &mut ErrorGen,
+) -> bool {
+    let mut type_checker = TypeChecker {
+        table: st,
+        err,
+        in_script_global: false,
+        in_fn: false,
+        site: Some(site),
+        num_errors: 0,
+    };
+    if let Some(predicate) = predicate {
+        if let Some(ty) = type_checker.visit_expr(predicate) {
+            if ty != DataType::Boolean {
+                type_checker.type_check_error(
+                    "Predicate must be of type boolean".to_owned(),
+                    &predicate.loc().clone().map(|l| l.line_col),
+                );
+            }
+        }
+    }
+    if let Some(body) = body {
+        type_checker.visit_block(body);
+    }
+    type_checker.num_errors == 0
+}
@@ -0,0 +1,24 @@
+(component
+  (core module $main
+    (type (;0;) (func (param i32)))
+    (type (;1;) (func (param i32) (result i32)))
+    (import "wasi:cli/environment@0.2.0" "get-arguments" (func $get_arguments (type 0)))
+    (memory (;0;) 1)
+    (func $run (type 1) (param i32) (result i32)
+      local.get 0
+      call $get_arguments
+      local.get 0
+      call $double)
+    (func $double (type 1) (param i32) (result i32)
+      local.get 0
+      i32.const 2
+      i32.mul)
+    (export "memory" (memory 0))
+    (export "wasi:cli/run@0.2.0#run" (func $run)))
+  (core module $adapter
+    (type (;0;) (func))
+    (memory (;0;) 1)
+    (func $init (type 0)
+      nop)
+    (export "init" (func $init)))
+)
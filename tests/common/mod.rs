@@ -12,6 +12,7 @@ use log::{error, info, warn};
 use orca::Module;
 use wabt::wat2wasm;
 use whamm::common::error::ErrorGen;
+use whamm::emitter::rewriting::app_info::AppInfo;
 use whamm::emitter::rewriting::module_emitter::{MemoryTracker, ModuleEmitter};
 use whamm::emitter::rewriting::visiting_emitter::VisitingEmitter;
 use whamm::generator::init_generator::InitGenerator;
@@ -74,8 +75,17 @@ fn get_ast(script: &str, err: &mut ErrorGen) -> Option<Whamm> {
     }
 }
 
+/// Fail the test if any errors were reported so far
+pub fn check_errors(err: &mut ErrorGen) {
+    if let Err(mut diagnostics) = err.check_has_errors() {
+        diagnostics.report();
+        panic!("Reached an error. Exiting...");
+    }
+}
+
 const TEST_DEBUG_DIR: &str = "output/tests/debug_me/";
-pub fn run_whamm(app_wasm: &mut Module, whamm_script: &String, script_path: &str) -> Vec<u8> {
+pub fn run_whamm(app: &[u8], whamm_script: &String, script_path: &str) -> Vec<u8> {
+    let mut app_wasm = Module::parse(app, false).unwrap();
     let mut err = ErrorGen::new(script_path.to_string(), whamm_script.clone(), 0);
 
     let ast_res = get_ast(whamm_script, &mut err);
@@ -85,13 +95,13 @@ pub fn run_whamm(app_wasm: &mut Module, whamm_script: &String, script_path: &str
         &whamm_script
     );
     let mut whamm = ast_res.unwrap();
-    err.fatal_report("IntegrationTest");
+    check_errors(&mut err);
 
     // Verify phase
     let mut symbol_table = build_symbol_table(&mut whamm, &mut err);
     symbol_table.reset();
     type_check(&mut whamm, &mut symbol_table, &mut err);
-    err.fatal_report("IntegrationTest");
+    check_errors(&mut err);
 
     // Translate to the simple AST
     let simple_ast = build_simple_ast(&whamm, &mut err);
@@ -106,27 +116,38 @@ pub fn run_whamm(app_wasm: &mut Module, whamm_script: &String, script_path: &str
         curr_mem_offset: 1_052_576, // Set default memory base address to DEFAULT + 4KB = 1048576 bytes + 4000 bytes = 1052576 bytes
         required_initial_mem_size: 27, // Size memory must be to account for the added data
         emitted_strings: HashMap::new(),
+        emitted_aggregations: HashMap::new(),
     };
 
     // Phase 0 of instrumentation (emit globals and provided fns)
     let mut init = InitGenerator {
-        emitter: ModuleEmitter::new(app_wasm, &mut symbol_table, &mut mem_tracker),
+        emitter: ModuleEmitter::new(&mut app_wasm, &mut symbol_table, &mut mem_tracker),
         context_name: "".to_string(),
         err: &mut err,
+        errors: vec![],
     };
     assert!(init.run(&mut whamm));
-    err.fatal_report("IntegrationTest");
+    let script_errors = std::mem::take(&mut init.errors);
+    err.add_errors(script_errors.into_iter().map(|(_, e)| e).collect());
+    check_errors(&mut err);
 
     // Phase 1 of instrumentation (actually emits the instrumentation code)
     // This structure is necessary since we need to have the fns/globals injected (a single time)
     // and ready to use in every body/predicate.
     let mut instr = InstrGenerator::new(
-        VisitingEmitter::new(app_wasm, &mut symbol_table, &mem_tracker),
+        VisitingEmitter::new(
+            &mut app_wasm,
+            AppInfo::new(app),
+            &mut symbol_table,
+            &mem_tracker,
+        ),
         simple_ast,
         &mut err,
     );
     instr.run();
-    err.fatal_report("IntegrationTest");
+    let script_errors = std::mem::take(&mut instr.errors);
+    err.add_errors(script_errors.into_iter().map(|(_, e)| e).collect());
+    check_errors(&mut err);
 
     // make sure that this is a valid file by running wasm2wat through CLI
     let wasm_file_path = format!(
@@ -1,6 +1,5 @@
 use crate::common::{run_whamm, setup_logger, try_path};
 use log::{debug, error};
-use orca::Module;
 use std::fs::{remove_dir_all, File};
 use std::io::{BufRead, BufReader, Write};
 use std::path::{Path, PathBuf};
@@ -197,12 +196,8 @@ fn generate_instrumented_bin_wast(
         let cloned_module = test_setup.target_module_wat.clone();
         let buff = wat::parse_bytes(cloned_module.as_slice())
             .expect("couldn't convert the input wat to Wasm");
-        let mut module_to_instrument = Module::parse(&buff, false).unwrap();
-        let instrumented_module_wasm = run_whamm(
-            &mut module_to_instrument,
-            &test_case.whamm_script,
-            &format!("{:?}", wast_path),
-        );
+        let instrumented_module_wasm =
+            run_whamm(&buff, &test_case.whamm_script, &format!("{:?}", wast_path));
 
         // create the wast
         // call.wast -> call.idx.bin.wast
@@ -2,9 +2,16 @@ mod common;
 
 use crate::common::{run_basic_instrumentation, run_whamm, run_whamm_bin, wasm2wat_on_file};
 use orca::Module;
+use pest::error::LineColLocation;
 use std::fs;
 use std::process::Command;
 use whamm::common::error::ErrorGen;
+use whamm::emitter::rewriting::app_info::AppInfo;
+use whamm::emitter::text::{to_annotated_wat, GENERATED, REGION_END, REGION_START};
+use whamm::generator::fn_filter::FnFilter;
+use whamm::generator::report::{PredicateKind, ReportFormat};
+use whamm::parser::types::{DataType, Value};
+use whamm::{Options, ScriptSource};
 
 const APP_WASM_PATH: &str = "tests/apps/dfinity/users.wasm";
 
@@ -21,20 +28,881 @@ fn instrument_dfinity_with_fault_injection() {
     let mut err = ErrorGen::new("".to_string(), "".to_string(), 0);
     let processed_scripts = common::setup_fault_injection("dfinity");
     assert!(!processed_scripts.is_empty());
-    err.fatal_report("Integration Test");
+    common::check_errors(&mut err);
 
     for (script_path, script_text) in processed_scripts {
         let wasm = fs::read(APP_WASM_PATH).unwrap();
-        let mut module_to_instrument = Module::parse(&wasm, false).unwrap();
         let _ = run_whamm(
-            &mut module_to_instrument,
+            &wasm,
             &script_text,
             &format!("{:?}", script_path.clone().as_path()),
         );
-        err.fatal_report("Integration Test");
+        common::check_errors(&mut err);
     }
 }
 
+/// The library entrypoint returns the instrumented module, or the errors instead of exiting.
+#[test]
+fn instrument_with_library_api() {
+    common::setup_logger();
+    let app = wat::parse_file("tests/apps/handwritten/add.wat").unwrap();
+
+    let script = ScriptSource::new(
+        "call.mm".to_string(),
+        "i32 count; wasm:opcode:call:before { count++; }".to_string(),
+    );
+    let instrumented = whamm::instrument(&app, &[script], &Options::default())
+        .unwrap_or_else(|_| panic!("Failed to instrument with a valid script"));
+    assert!(Module::parse(&instrumented.wasm, false).is_ok());
+    assert!(instrumented.diagnostics[0].warnings.is_empty());
+
+    let script = ScriptSource::new(
+        "type_error.mm".to_string(),
+        r#"wasm:opcode:call:before / "i" <= 1 / { }"#.to_string(),
+    );
+    match whamm::instrument(&app, &[script], &Options::default()) {
+        Ok(_) => panic!("Instrumented with a script that doesn't type check"),
+        Err(diagnostics) => assert!(diagnostics.has_errors()),
+    }
+}
+
+/// Every injection is recorded, with how its predicate was emitted.
+#[test]
+fn report_injection_sites() {
+    common::setup_logger();
+    let app = wat::parse_file("tests/apps/handwritten/add.wat").unwrap();
+
+    let script = ScriptSource::new(
+        "report.mm".to_string(),
+        r#"
+i32 count;
+wasm:opcode:call:before / imm0 == 0 / { count++; }
+wasm:opcode:call:after / count > 1 / { count++; }
+"#
+        .to_string(),
+    );
+    let instrumented = whamm::instrument(&app, &[script], &Options::default())
+        .unwrap_or_else(|_| panic!("Failed to instrument with a valid script"));
+    let injections = &instrumented.report.injections;
+    assert_eq!(injections.len(), 6);
+    assert!(injections.iter().all(|injection| injection.site.fn_id == 1));
+
+    let mut first_call: Vec<(&str, &str, PredicateKind)> = injections
+        .iter()
+        .filter(|injection| injection.site.instr_idx == 0)
+        .map(|injection| {
+            (
+                injection.probe_spec.as_str(),
+                injection.mode.as_str(),
+                injection.predicate,
+            )
+        })
+        .collect();
+    first_call.sort_by_key(|(spec, ..)| *spec);
+    assert_eq!(
+        first_call,
+        vec![
+            (
+                "wasm:opcode:call:after",
+                "after",
+                PredicateKind::RuntimeCheck
+            ),
+            (
+                "wasm:opcode:call:before",
+                "before",
+                PredicateKind::FoldedTrue
+            ),
+        ]
+    );
+
+    let json = instrumented.report.render(ReportFormat::Json);
+    assert!(json.contains("\"predicate\": \"folded_true\""));
+    let table = instrumented.report.render(ReportFormat::Table);
+    assert!(table.starts_with("script"));
+    assert!(table.ends_with("6 injection(s)\n"));
+}
+
+/// The sites a spec would match are listed without instrumenting anything.
+#[test]
+fn preview_spec_matches() {
+    common::setup_logger();
+    let app = wat::parse_file("tests/apps/handwritten/add.wat").unwrap();
+
+    let sites = whamm::preview_matches(&app, "wasm:opcode:call", &Options::default())
+        .unwrap_or_else(|_| panic!("Failed to preview a valid spec"));
+    assert_eq!(sites.len(), 3);
+    for (i, site) in sites.iter().enumerate() {
+        assert_eq!(site.site.fn_id, 1);
+        assert_eq!(site.site.instr_idx, i as u32);
+        assert!(site.specs.contains(&"wasm:opcode:call:before".to_string()));
+        let imm0 = site
+            .static_data
+            .iter()
+            .find(|(name, _)| name == "imm0")
+            .and_then(|(_, val)| val.clone());
+        assert_eq!(
+            imm0,
+            Some(Value::Integer {
+                ty: DataType::I32,
+                val: 0
+            })
+        );
+    }
+
+    let sites = whamm::preview_matches(&app, "wasm:opcode:drop:before", &Options::default())
+        .unwrap_or_else(|_| panic!("Failed to preview a valid spec"));
+    assert!(sites.is_empty());
+
+    assert!(whamm::preview_matches(&app, "wasm:opcode:nope", &Options::default()).is_err());
+
+    // The sites that wouldn't be instrumented aren't listed either
+    let opts = Options {
+        fn_filter: FnFilter::new(&[], &["1".to_string()], &[]).unwrap(),
+        ..Options::default()
+    };
+    let sites = whamm::preview_matches(&app, "wasm:opcode:call", &opts)
+        .unwrap_or_else(|_| panic!("Failed to preview a valid spec"));
+    assert!(sites.is_empty());
+}
+
+/// With `--format json` or `--format markdown`, the sites are part of the export on stdout.
+#[test]
+fn export_spec_matches() {
+    common::setup_logger();
+    let export = |format: &str| {
+        let res = Command::new("target/debug/whamm")
+            .arg("info")
+            .arg("--spec")
+            .arg("wasm:opcode:call")
+            .arg("--app")
+            .arg("tests/apps/handwritten/add.wat")
+            .arg("--format")
+            .arg(format)
+            .output()
+            .expect("failed to execute process");
+        assert!(res.status.success());
+        String::from_utf8(res.stdout).unwrap()
+    };
+
+    let json: serde_json::Value = serde_json::from_str(&export("json")).unwrap();
+    let sites = json["matched_sites"].as_array().unwrap();
+    assert_eq!(sites.len(), 3);
+    assert_eq!(sites[0]["fn_id"], 1);
+    assert_eq!(sites[0]["static_data"]["imm0"], 0);
+
+    let md = export("markdown");
+    assert!(md.contains("# Matched 3 site(s) in the application #"));
+}
+
+/// Probes that are never injected are reported, along with why.
+#[test]
+fn warn_for_probes_never_injected() {
+    common::setup_logger();
+    let app = wat::parse_file("tests/apps/handwritten/no_matched_events.wat").unwrap();
+
+    let script = ScriptSource::new(
+        "never_injected.mm".to_string(),
+        r#"
+i32 count;
+wasm:opcode:call:before { count++; }
+wasm:opcode:drop:before / fn_id == 7 / { count++; }
+wasm:opcode:drop:after { count++; }
+"#
+        .to_string(),
+    );
+    let instrumented = whamm::instrument(&app, &[script], &Options::default())
+        .unwrap_or_else(|_| panic!("Failed to instrument with a valid script"));
+    let warnings: Vec<String> = instrumented.diagnostics[0]
+        .warnings
+        .iter()
+        .map(|warning| warning.ty.message().to_string())
+        .collect();
+    assert_eq!(
+        warnings,
+        vec![
+            "This probe was never injected, no instruction in the application matches `wasm:opcode:call:before`",
+            "This probe was never injected, its predicate is false at all 1 matching site(s)",
+        ]
+    );
+}
+
+/// Scripts instrumented together each get their own namespace, and are injected in order.
+#[test]
+fn instrument_with_multiple_scripts() {
+    common::setup_logger();
+    let app = wat::parse_file("tests/apps/handwritten/add.wat").unwrap();
+
+    let scripts = [
+        ScriptSource::new(
+            "first.mm".to_string(),
+            "i32 count; wasm:opcode:call:before { count++; }".to_string(),
+        ),
+        ScriptSource::new(
+            "second.mm".to_string(),
+            "i32 count; wasm:opcode:call:before { count = count + 2; }".to_string(),
+        ),
+    ];
+    let instrumented = whamm::instrument(&app, &scripts, &Options::default())
+        .unwrap_or_else(|_| panic!("Failed to instrument with valid scripts"));
+    assert_eq!(instrumented.diagnostics.len(), 2);
+
+    let mut exports = vec![];
+    for payload in wasmparser::Parser::new(0).parse_all(&instrumented.wasm) {
+        if let wasmparser::Payload::ExportSection(reader) = payload.unwrap() {
+            for export in reader {
+                exports.push(export.unwrap().name.to_string());
+            }
+        }
+    }
+    for export in ["script0_get_count", "script1_get_count"] {
+        assert!(
+            exports.iter().any(|name| name == export),
+            "missing export {export}"
+        );
+    }
+
+    // At each site, the first script's probe is injected before the second's
+    let script_ids: Vec<&str> = instrumented
+        .report
+        .injections
+        .iter()
+        .map(|injection| injection.script_id.as_str())
+        .collect();
+    assert_eq!(script_ids.len(), 6);
+    for pair in script_ids.chunks(2) {
+        assert_eq!(pair, ["script0", "script1"]);
+    }
+}
+
+/// Scoped variables with the same name in different scripts each get their own local, with
+/// the type their own script declares.
+#[test]
+fn instrument_with_scoped_vars_of_several_scripts() {
+    common::setup_logger();
+    let app = wat::parse_str(
+        r#"
+(module
+  (func $log (param i64))
+  (func $main
+    i64.const 5
+    call $log))
+"#,
+    )
+    .unwrap();
+
+    let scripts = [
+        ScriptSource::new(
+            "first.mm".to_string(),
+            "i64 frame.t; wasm:opcode:call:before { frame.t = arg0; }".to_string(),
+        ),
+        ScriptSource::new(
+            "second.mm".to_string(),
+            "i32 frame.t; wasm:opcode:call:before { frame.t = fn_id; }".to_string(),
+        ),
+    ];
+    // the instrumented module is validated, sharing one local would make it invalid
+    let instrumented = whamm::instrument(&app, &scripts, &Options::default())
+        .unwrap_or_else(|_| panic!("Failed to instrument with valid scripts"));
+
+    let mut scoped_locals = vec![];
+    for payload in wasmparser::Parser::new(0).parse_all(&instrumented.wasm) {
+        let wasmparser::Payload::CustomSection(reader) = payload.unwrap() else {
+            continue;
+        };
+        let wasmparser::KnownCustom::Name(names) = reader.as_known() else {
+            continue;
+        };
+        for name in names {
+            if let wasmparser::Name::Local(map) = name.unwrap() {
+                for fn_locals in map {
+                    let fn_locals = fn_locals.unwrap();
+                    for naming in fn_locals.names {
+                        let naming = naming.unwrap();
+                        if naming.name == "whamm_frame.t" {
+                            scoped_locals.push((fn_locals.index, naming.index));
+                        }
+                    }
+                }
+            }
+        }
+    }
+    assert_eq!(scoped_locals.len(), 2);
+    assert!(scoped_locals.iter().all(|(fn_id, _)| *fn_id == 1));
+    assert_ne!(scoped_locals[0].1, scoped_locals[1].1);
+}
+
+/// The aggregations are written out when a WASI command exits, with its own `fd_write` import.
+#[test]
+fn report_aggregations_when_the_app_exits() {
+    common::setup_logger();
+    let app_wat = |imports: &str| {
+        format!(
+            r#"
+(module
+  {imports}
+  (memory (export "memory") 1)
+  (func $branch (param i32)
+    (block (br_if 0 (local.get 0))))
+  (func $start (export "_start")
+    i32.const 1
+    call $branch))
+"#
+        )
+    };
+    let script = || {
+        ScriptSource::new(
+            "aggs.mm".to_string(),
+            "wasm:opcode:br_if:before { @taken[tos] = count(); }".to_string(),
+        )
+    };
+
+    let app = wat::parse_str(app_wat(
+        r#"(import "wasi_snapshot_preview1" "fd_write" (func (param i32 i32 i32 i32) (result i32)))"#,
+    ))
+    .unwrap();
+    let instrumented = whamm::instrument(&app, &[script()], &Options::default())
+        .unwrap_or_else(|_| panic!("Failed to instrument with a valid script"));
+    assert!(instrumented.diagnostics[0].warnings.is_empty());
+
+    let mut report_id = None;
+    let mut fn_bodies = vec![];
+    for payload in wasmparser::Parser::new(0).parse_all(&instrumented.wasm) {
+        match payload.unwrap() {
+            wasmparser::Payload::ExportSection(reader) => {
+                for export in reader {
+                    let export = export.unwrap();
+                    if export.name == "whamm_agg_report" {
+                        report_id = Some(export.index);
+                    }
+                }
+            }
+            wasmparser::Payload::CodeSectionEntry(body) => {
+                let ops: Vec<wasmparser::Operator> = body
+                    .get_operators_reader()
+                    .unwrap()
+                    .into_iter()
+                    .map(|op| op.unwrap())
+                    .collect();
+                fn_bodies.push(format!("{ops:?}"));
+            }
+            _ => {}
+        }
+    }
+    let report_id = report_id.expect("missing export whamm_agg_report");
+    // `_start` (fn 2, after the import) calls the report right before it returns
+    let start_body = &fn_bodies[1];
+    assert!(
+        start_body.ends_with(&format!("Call {{ function_index: {report_id} }}, End]")),
+        "{start_body}"
+    );
+
+    // without `fd_write`, the tables can only be read through the exports
+    let app = wat::parse_str(app_wat("")).unwrap();
+    let instrumented = whamm::instrument(&app, &[script()], &Options::default())
+        .unwrap_or_else(|_| panic!("Failed to instrument with a valid script"));
+    let warnings = &instrumented.diagnostics[0].warnings;
+    assert_eq!(warnings.len(), 1);
+    assert!(warnings[0].ty.message().contains("fd_write"));
+}
+
+/// An error at a site is reported with the script of the probe that caused it, even if there
+/// are several scripts.
+#[test]
+fn report_site_errors_with_their_script() {
+    common::setup_logger();
+    let app = wat::parse_file("tests/apps/handwritten/add.wat").unwrap();
+
+    let scripts = [
+        ScriptSource::new(
+            "first.mm".to_string(),
+            "i32 count; wasm:opcode:call:before { count++; }".to_string(),
+        ),
+        // the calls in the app don't pass any args
+        ScriptSource::new(
+            "second.mm".to_string(),
+            "i32 count; wasm:opcode:call:before { count = count + arg0; }".to_string(),
+        ),
+    ];
+    let diagnostics = match whamm::instrument(&app, &scripts, &Options::default()) {
+        Ok(_) => panic!("Instrumented with a probe that doesn't type check at its sites"),
+        Err(diagnostics) => diagnostics,
+    };
+    assert_eq!(diagnostics.script_path, "second.mm");
+    assert_eq!(diagnostics.script_text, scripts[1].text);
+    assert!(diagnostics.has_errors());
+    assert!(diagnostics
+        .errors
+        .iter()
+        .all(|error| error.err_loc.is_some()));
+}
+
+/// If the injected code makes the module invalid, the error points at the probe that was injected
+/// there. If the application was invalid to begin with, no probe is blamed for it.
+#[test]
+fn report_invalid_injections_at_their_probe() {
+    common::setup_logger();
+    let app =
+        wat::parse_str("(module (func (result i32) i32.const 1) (func call 0 drop))").unwrap();
+    // replacing the call leaves nothing on the stack for the `drop`
+    let script = || {
+        ScriptSource::new(
+            "invalid.mm".to_string(),
+            "i32 count;\nwasm:opcode:call:alt { count++; }".to_string(),
+        )
+    };
+    let diagnostics = match whamm::instrument(&app, &[script()], &Options::default()) {
+        Ok(_) => panic!("Instrumenting made an invalid module"),
+        Err(diagnostics) => diagnostics,
+    };
+    assert_eq!(diagnostics.errors.len(), 1);
+    let error = &diagnostics.errors[0];
+    assert!(error
+        .ty
+        .message()
+        .contains("the probe `wasm:opcode:call:alt` was injected into the invalid function"));
+    let start_line = match &error.err_loc.as_ref().unwrap().line_col {
+        LineColLocation::Pos((line, _)) | LineColLocation::Span((line, _), _) => *line,
+    };
+    assert_eq!(start_line, 2);
+
+    let app = wat::parse_str("(module (func i32.const 0))").unwrap();
+    let diagnostics = match whamm::instrument(&app, &[script()], &Options::default()) {
+        Ok(_) => panic!("Instrumented an invalid application"),
+        Err(diagnostics) => diagnostics,
+    };
+    assert_eq!(diagnostics.errors.len(), 1);
+    let error = &diagnostics.errors[0];
+    assert!(error
+        .ty
+        .message()
+        .starts_with("The application is invalid before instrumenting"));
+    assert!(error.err_loc.is_none());
+}
+
+/// The CLI writes the diagnostics of all the scripts as a single JSON or SARIF document, with
+/// one SARIF run per script.
+#[test]
+fn write_diagnostics_of_several_scripts_as_one_document() {
+    common::setup_logger();
+    // the app has no `i32.add`, so this probe is never injected
+    let never_injected_path = "output/integration-never_injected.mm";
+    fs::create_dir_all("output").unwrap();
+    fs::write(never_injected_path, "wasm:opcode:i32.add:before { }").unwrap();
+
+    let run = |format: &str| {
+        let res = Command::new("target/debug/whamm")
+            .arg("instr")
+            .arg("--script")
+            .arg("tests/scripts/instr.mm")
+            .arg("--script")
+            .arg(never_injected_path)
+            .arg("--app")
+            .arg("tests/apps/handwritten/add.wat")
+            .arg("--output-path")
+            .arg("output/integration-diagnostics_of_several_scripts.wasm")
+            .arg("--diagnostics-format")
+            .arg(format)
+            .output()
+            .expect("failed to execute process");
+        assert!(res.status.success());
+        serde_json::from_slice::<serde_json::Value>(&res.stderr)
+            .unwrap_or_else(|_| panic!("stderr isn't a single JSON document"))
+    };
+
+    let sarif = run("sarif");
+    let runs = sarif["runs"].as_array().unwrap();
+    assert_eq!(runs.len(), 2);
+    assert!(!runs[1]["results"].as_array().unwrap().is_empty());
+
+    let json = run("json");
+    let diagnostics = json["diagnostics"].as_array().unwrap();
+    assert!(diagnostics
+        .iter()
+        .any(|diagnostic| diagnostic["script_path"] == never_injected_path));
+}
+
+/// `alt` probes of several scripts that match the same site are chained in the order of the
+/// scripts, the ones after an `alt` probe that always runs are never reached.
+#[test]
+fn warn_for_unreachable_alt_probes() {
+    common::setup_logger();
+    let app = wat::parse_file("tests/apps/handwritten/add.wat").unwrap();
+
+    let scripts = [
+        ScriptSource::new(
+            "first.mm".to_string(),
+            "wasm:opcode:call:alt { }".to_string(),
+        ),
+        ScriptSource::new(
+            "second.mm".to_string(),
+            "wasm:opcode:call:alt { }".to_string(),
+        ),
+    ];
+    let instrumented = whamm::instrument(&app, &scripts, &Options::default())
+        .unwrap_or_else(|_| panic!("Failed to instrument with valid scripts"));
+    assert!(instrumented.diagnostics[0].warnings.is_empty());
+
+    let warnings = &instrumented.diagnostics[1].warnings;
+    assert!(!warnings.is_empty());
+    assert!(warnings.iter().all(|warning| warning
+        .ty
+        .message()
+        .starts_with("This `alt` probe is never reached at")));
+    assert!(instrumented
+        .report
+        .injections
+        .iter()
+        .all(|injection| injection.script_id == "script0"));
+
+    // With a higher priority, the second script's probe is tried first instead
+    let scripts = [
+        ScriptSource::new(
+            "first.mm".to_string(),
+            "wasm:opcode:call:alt { }".to_string(),
+        ),
+        ScriptSource::new(
+            "second.mm".to_string(),
+            "@priority(1) wasm:opcode:call:alt { }".to_string(),
+        ),
+    ];
+    let instrumented = whamm::instrument(&app, &scripts, &Options::default())
+        .unwrap_or_else(|_| panic!("Failed to instrument with valid scripts"));
+    assert!(instrumented.diagnostics[1].warnings.is_empty());
+    assert!(!instrumented.diagnostics[0].warnings.is_empty());
+    assert!(instrumented
+        .report
+        .injections
+        .iter()
+        .all(|injection| injection.script_id == "script1"));
+}
+
+/// Applications can be passed as WAT, and the instrumented module printed as WAT with the
+/// injected code marked.
+#[test]
+fn instrument_and_emit_wat() {
+    common::setup_logger();
+    let app = fs::read("tests/apps/handwritten/add.wat").unwrap();
+
+    let script = ScriptSource::new(
+        "call.mm".to_string(),
+        "i32 count; wasm:opcode:call:before { count++; }".to_string(),
+    );
+    let instrumented = whamm::instrument(&app, &[script], &Options::default())
+        .unwrap_or_else(|_| panic!("Failed to instrument a WAT application"));
+    assert!(Module::parse(&instrumented.wasm, false).is_ok());
+
+    let wat = to_annotated_wat(&app, &instrumented.wasm).unwrap();
+    assert!(wat.starts_with(";; instrumented by whamm"));
+    // Each of the 3 calls has code injected before it
+    assert!(wat.matches(REGION_START).count() >= 3);
+    assert_eq!(
+        wat.matches(REGION_START).count(),
+        wat.matches(REGION_END).count()
+    );
+    // `get_count` is added by the instrumentation
+    assert!(wat.contains(GENERATED));
+    assert!(wat::parse_str(&wat).is_ok());
+
+    // The same goes for the CLI
+    let instrumented_wat_path = "output/integration-handwritten_add.wat";
+    let res = Command::new("target/debug/whamm")
+        .arg("instr")
+        .arg("--script")
+        .arg("tests/scripts/instr.mm")
+        .arg("--app")
+        .arg("tests/apps/handwritten/add.wat")
+        .arg("--emit")
+        .arg("wat")
+        .arg("--output-path")
+        .arg(instrumented_wat_path)
+        .output()
+        .expect("failed to execute process");
+    assert!(res.status.success());
+    let wat = fs::read_to_string(instrumented_wat_path).unwrap();
+    assert!(wat::parse_str(&wat).is_ok());
+
+    // Without an output path, the WAT doesn't go to a `.wasm` file
+    let default_wat_path = "output/output.wat";
+    let _ = fs::remove_file(default_wat_path);
+    let res = Command::new("target/debug/whamm")
+        .arg("instr")
+        .arg("--script")
+        .arg("tests/scripts/instr.mm")
+        .arg("--app")
+        .arg("tests/apps/handwritten/add.wat")
+        .arg("--emit")
+        .arg("wat")
+        .output()
+        .expect("failed to execute process");
+    assert!(res.status.success());
+    let wat = fs::read_to_string(default_wat_path).unwrap();
+    assert!(wat::parse_str(&wat).is_ok());
+}
+
+/// The application's names are kept, what the instrumentation injects is named, and whamm is
+/// recorded as one of the tools that processed the module.
+#[test]
+fn instrument_and_update_custom_sections() {
+    common::setup_logger();
+    let app = wat::parse_str(
+        r#"
+(module
+  (import "env" "log" (func $log (param i32)))
+  (func $main (param $n i32)
+    local.get $n
+    call $log)
+  (memory 1))
+"#,
+    )
+    .unwrap();
+    let script = ScriptSource::new(
+        "names.mm".to_string(),
+        "i32 count; wasm:opcode:call:before { count = count + arg0; }".to_string(),
+    );
+    let instrumented = whamm::instrument(&app, &[script], &Options::default())
+        .unwrap_or_else(|_| panic!("Failed to instrument with a valid script"));
+
+    let mut fn_names = vec![];
+    let mut global_names = vec![];
+    let mut local_names = vec![];
+    let mut processed_by = vec![];
+    for payload in wasmparser::Parser::new(0).parse_all(&instrumented.wasm) {
+        let wasmparser::Payload::CustomSection(reader) = payload.unwrap() else {
+            continue;
+        };
+        match reader.as_known() {
+            wasmparser::KnownCustom::Name(names) => {
+                for name in names {
+                    match name.unwrap() {
+                        wasmparser::Name::Function(map) => {
+                            for naming in map {
+                                let naming = naming.unwrap();
+                                fn_names.push((naming.index, naming.name.to_string()));
+                            }
+                        }
+                        wasmparser::Name::Global(map) => {
+                            for naming in map {
+                                global_names.push(naming.unwrap().name.to_string());
+                            }
+                        }
+                        wasmparser::Name::Local(map) => {
+                            for fn_locals in map {
+                                let fn_locals = fn_locals.unwrap();
+                                for naming in fn_locals.names {
+                                    let naming = naming.unwrap();
+                                    local_names.push((
+                                        fn_locals.index,
+                                        naming.index,
+                                        naming.name.to_string(),
+                                    ));
+                                }
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            wasmparser::KnownCustom::Producers(fields) => {
+                for field in fields {
+                    let field = field.unwrap();
+                    if field.name == "processed-by" {
+                        for value in field.values {
+                            processed_by.push(value.unwrap().name.to_string());
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    assert!(fn_names.contains(&(0, "log".to_string())));
+    assert!(fn_names.contains(&(1, "main".to_string())));
+    assert!(fn_names.iter().any(|(_, name)| name == "get_count"));
+    assert_eq!(global_names, vec!["whamm_count".to_string()]);
+    assert!(local_names.contains(&(1, 0, "n".to_string())));
+    assert!(local_names.contains(&(1, 1, "whamm_arg0".to_string())));
+    assert_eq!(processed_by, vec!["whamm".to_string()]);
+}
+
+/// Append a custom section to the encoded `module`.
+fn append_custom_section(module: &mut Vec<u8>, name: &str, data: &[u8]) {
+    fn write_leb(mut val: usize, bytes: &mut Vec<u8>) {
+        loop {
+            let byte = (val & 0x7f) as u8;
+            val >>= 7;
+            if val == 0 {
+                bytes.push(byte);
+                return;
+            }
+            bytes.push(byte | 0x80);
+        }
+    }
+    let mut contents = vec![];
+    write_leb(name.len(), &mut contents);
+    contents.extend_from_slice(name.as_bytes());
+    contents.extend_from_slice(data);
+    module.push(0);
+    write_leb(contents.len(), module);
+    module.extend(contents);
+}
+
+/// A predicate on the source line of an instruction (from `.debug_line`) folds at each site, so
+/// the probe is only injected where the line matches.
+#[test]
+fn instrument_with_src_line_predicate() {
+    use gimli::write::{
+        Address, DwarfUnit, EndianVec, LineEncoding, LineProgram, LineString, Sections,
+    };
+    common::setup_logger();
+    let mut app = wat::parse_str(
+        r#"
+(module
+  (func $main
+    i32.const 0
+    drop
+    i32.const 1
+    drop))
+"#,
+    )
+    .unwrap();
+    let info = AppInfo::new(&app);
+    let (first_drop, second_drop) = (info.pc(0, 1).unwrap(), info.pc(0, 3).unwrap());
+
+    // the first `drop` is on line 3 of main.rs, the second one on line 7
+    let encoding = gimli::Encoding {
+        format: gimli::Format::Dwarf32,
+        version: 4,
+        address_size: 4,
+    };
+    let mut program = LineProgram::new(
+        encoding,
+        LineEncoding::default(),
+        LineString::String(b"/src".to_vec()),
+        LineString::String(b"main.rs".to_vec()),
+        None,
+    );
+    let dir = program.default_directory();
+    let file = program.add_file(LineString::String(b"main.rs".to_vec()), dir, None);
+    program.begin_sequence(Some(Address::Constant(0)));
+    for (pc, line) in [(first_drop, 3), (second_drop, 7)] {
+        program.row().file = file;
+        program.row().line = line;
+        program.row().address_offset = pc as u64;
+        program.generate_row();
+    }
+    program.end_sequence(second_drop as u64 + 1);
+    let mut dwarf = DwarfUnit::new(encoding);
+    dwarf.unit.line_program = program;
+    let mut sections = Sections::new(EndianVec::new(gimli::LittleEndian));
+    dwarf.write(&mut sections).unwrap();
+    sections
+        .for_each(|id, data| {
+            if !data.slice().is_empty() {
+                append_custom_section(&mut app, id.name(), data.slice());
+            }
+            Ok::<_, ()>(())
+        })
+        .unwrap();
+
+    let script = ScriptSource::new(
+        "src_line.mm".to_string(),
+        "i32 count; wasm:opcode:drop:before / src_line == 7 / { count++; }".to_string(),
+    );
+    let instrumented = whamm::instrument(&app, &[script], &Options::default())
+        .unwrap_or_else(|_| panic!("Failed to instrument with a valid script"));
+    let sites: Vec<(u32, PredicateKind)> = instrumented
+        .report
+        .injections
+        .iter()
+        .map(|injection| (injection.site.instr_idx, injection.predicate))
+        .collect();
+    assert_eq!(sites, vec![(3, PredicateKind::FoldedTrue)]);
+
+    // The line table would be stale for the instrumented code, so it's dropped
+    for payload in wasmparser::Parser::new(0).parse_all(&instrumented.wasm) {
+        if let wasmparser::Payload::CustomSection(reader) = payload.unwrap() {
+            assert!(!reader.name().starts_with(".debug_"));
+        }
+    }
+    assert!(instrumented.diagnostics[0]
+        .warnings
+        .iter()
+        .any(|warning| warning
+            .ty
+            .message()
+            .starts_with("Dropped the DWARF sections")));
+}
+
+/// Functions can be filtered out by name or ID before any probe is matched, as can the calls
+/// to the imports of a module. A predicate on `fn_name` skips whole functions as well.
+#[test]
+fn instrument_with_fn_filters() {
+    common::setup_logger();
+    let app = wat::parse_file("tests/apps/handwritten/basic.wat").unwrap();
+    let injected_fns = |script: &str, fn_filter: FnFilter| -> Vec<String> {
+        let script = ScriptSource::new("filter.mm".to_string(), script.to_string());
+        let opts = Options {
+            fn_filter,
+            ..Options::default()
+        };
+        let instrumented = whamm::instrument(&app, &[script], &opts)
+            .unwrap_or_else(|_| panic!("Failed to instrument with a valid script"));
+        let mut fns: Vec<String> = instrumented
+            .report
+            .injections
+            .iter()
+            .map(|injection| injection.site.fn_name.clone())
+            .collect();
+        fns.dedup();
+        fns
+    };
+    let call_probe = "i32 count; wasm:opcode:call:before { count++; }";
+
+    assert_eq!(
+        injected_fns(call_probe, FnFilter::default()),
+        vec![
+            "should_not_instrument",
+            "should",
+            "inject_synchronous_fault"
+        ]
+    );
+    let exclude = FnFilter::new(&[], &["should*".to_string()], &[]).unwrap();
+    assert_eq!(
+        injected_fns(call_probe, exclude),
+        vec!["inject_synchronous_fault"]
+    );
+    // `should_not_instrument` is fn 2, after the 2 imports
+    let include = FnFilter::new(&["2".to_string(), "should".to_string()], &[], &[]).unwrap();
+    assert_eq!(
+        injected_fns(call_probe, include),
+        vec!["should_not_instrument", "should"]
+    );
+    let skip_imports = FnFilter::new(&[], &[], &["ic0".to_string()]).unwrap();
+    assert!(injected_fns(call_probe, skip_imports).is_empty());
+
+    // A probe whose sites were all filtered out is reported as such
+    let opts = Options {
+        fn_filter: FnFilter::new(&[], &["*".to_string()], &[]).unwrap(),
+        ..Options::default()
+    };
+    let script = ScriptSource::new("filter.mm".to_string(), call_probe.to_string());
+    let instrumented = whamm::instrument(&app, &[script], &opts)
+        .unwrap_or_else(|_| panic!("Failed to instrument with a valid script"));
+    let warnings: Vec<String> = instrumented.diagnostics[0]
+        .warnings
+        .iter()
+        .map(|warning| warning.ty.message().to_string())
+        .collect();
+    assert_eq!(
+        warnings,
+        vec!["This probe was never injected, all 3 matching site(s) are in functions (or calls) that were filtered out"]
+    );
+
+    let by_name = r#"i32 count; wasm:opcode:call:before / fn_name == "should" / { count++; }"#;
+    assert_eq!(injected_fns(by_name, FnFilter::default()), vec!["should"]);
+}
+
 #[test]
 fn instrument_handwritten_wasm_call() {
     common::setup_logger();
@@ -112,3 +980,89 @@ fn instrument_with_replay() {
     // TODO -- change this when you've supported this monitor type
     assert_eq!(processed_scripts.len(), 0);
 }
+
+/// Each core module of a component is instrumented, and the canonical ABI events match the
+/// calls to its imports and the entry of its exports.
+#[test]
+fn instrument_component_core_modules() {
+    common::setup_logger();
+    let app = wat::parse_file("tests/apps/handwritten/component.wat").unwrap();
+    let script = ScriptSource::new(
+        "canon.mm".to_string(),
+        r#"
+i32 count;
+component:canon:lower:before / imp_name == "get-arguments" / { count++; }
+component:canon:lift:before { count++; }
+wasm:opcode:call:before { count++; }
+"#
+        .to_string(),
+    );
+    let instrumented = whamm::instrument(&app, &[script], &Options::default())
+        .unwrap_or_else(|_| panic!("Failed to instrument a component"));
+    assert!(instrumented.diagnostics[0].warnings.is_empty());
+    assert!(
+        wasmparser::Validator::new_with_features(wasmparser::WasmFeatures::all())
+            .validate_all(&instrumented.wasm)
+            .is_ok()
+    );
+
+    let mut injections: Vec<(&str, Option<u32>, u32, u32)> = instrumented
+        .report
+        .injections
+        .iter()
+        .map(|injection| {
+            (
+                injection.probe_spec.as_str(),
+                injection.site.core_module,
+                injection.site.fn_id,
+                injection.site.instr_idx,
+            )
+        })
+        .collect();
+    injections.sort();
+    assert_eq!(
+        injections,
+        vec![
+            ("component:canon:lift:before", Some(0), 1, 0),
+            ("component:canon:lift:before", Some(1), 0, 0),
+            ("component:canon:lower:before", Some(0), 1, 1),
+            ("wasm:opcode:call:before", Some(0), 1, 1),
+            ("wasm:opcode:call:before", Some(0), 1, 3),
+        ]
+    );
+
+    // Only instrument the selected core module
+    let script = ScriptSource::new(
+        "lift.mm".to_string(),
+        "i32 count; component:canon:lift:before { count++; }".to_string(),
+    );
+    let opts = Options {
+        core_module: Some(1),
+        ..Options::default()
+    };
+    let instrumented = whamm::instrument(&app, &[script], &opts)
+        .unwrap_or_else(|_| panic!("Failed to instrument a core module of a component"));
+    assert_eq!(instrumented.report.injections.len(), 1);
+    assert_eq!(instrumented.report.injections[0].site.core_module, Some(1));
+
+    let script = ScriptSource::new(
+        "lift.mm".to_string(),
+        "i32 count; component:canon:lift:before { count++; }".to_string(),
+    );
+    let opts = Options {
+        core_module: Some(2),
+        ..Options::default()
+    };
+    assert!(whamm::instrument(&app, &[script], &opts).is_err());
+
+    // There is no canonical ABI outside of a component
+    let app = wat::parse_file("tests/apps/handwritten/add.wat").unwrap();
+    let script = ScriptSource::new(
+        "lower.mm".to_string(),
+        "i32 count; component:canon:lower:before { count++; }".to_string(),
+    );
+    let instrumented = whamm::instrument(&app, &[script], &Options::default())
+        .unwrap_or_else(|_| panic!("Failed to instrument with a valid script"));
+    assert!(instrumented.report.injections.is_empty());
+    assert_eq!(instrumented.diagnostics[0].warnings.len(), 1);
+}
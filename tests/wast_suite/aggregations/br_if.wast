@@ -0,0 +1,63 @@
+;; Test aggregations (`@name[keys] = fn(args);`) and the exported functions to read them out
+
+;; @instrument
+(module
+    ;; Globals
+    (global $var (mut i32) (i32.const 0))
+
+    ;; Global getters
+    (func $get_global_var (result i32)
+        (global.get $var)
+    )
+
+    ;; Auxiliary definitions
+    (func $branch (param $taken i32) (param $val i32)
+        (block $exit
+            (br_if $exit (local.get $taken))
+            (global.set $var (local.get $val))
+        )
+    )
+
+    ;; Test case functions
+    (func $start
+        (call $branch (i32.const 0) (i32.const 5))
+        (call $branch (i32.const 1) (i32.const 7))
+        (call $branch (i32.const 1) (i32.const 9))
+    )
+
+    (start $start)
+    (export "get_global_var" (func $get_global_var))
+    (memory (;0;) 1)
+)
+
+;; ----------------------
+;; ==== AGGS, `count` ====
+;; WHAMM --> wasm:opcode:br_if:before { @fires = count(); }
+(assert_return (invoke "get_agg_fires_len") (i32.const 1))
+(assert_return (invoke "get_agg_fires" (i32.const 0)) (i32.const 3))
+(assert_return (invoke "get_agg_fires_dropped") (i32.const 0))
+;; WHAMM --> wasm:opcode:br_if:before { @taken[tos] = count(); }
+(assert_return (invoke "get_agg_taken_len") (i32.const 2))
+(assert_return (invoke "get_agg_taken_key0" (i32.const 0)) (i32.const 0)) ;; entries are in order of insertion
+(assert_return (invoke "get_agg_taken" (i32.const 0)) (i32.const 1))
+(assert_return (invoke "get_agg_taken_key0" (i32.const 1)) (i32.const 1))
+(assert_return (invoke "get_agg_taken" (i32.const 1)) (i32.const 2))
+
+;; ---------------------------------------
+;; ==== AGGS, `sum`/`min`/`max`/`avg` ====
+;; WHAMM --> wasm:opcode:br_if:before { @total = sum(local1); @lo = min(local1); @hi = max(local1); @mean = avg(local1); }
+(assert_return (invoke "get_agg_total" (i32.const 0)) (i32.const 21)) ;; 5 + 7 + 9
+(assert_return (invoke "get_agg_lo" (i32.const 0)) (i32.const 5))
+(assert_return (invoke "get_agg_hi" (i32.const 0)) (i32.const 9))
+(assert_return (invoke "get_agg_mean" (i32.const 0)) (i32.const 7))
+
+;; -------------------------
+;; ==== AGGS, histograms ====
+;; WHAMM --> wasm:opcode:br_if:before { @dist = quantize(local1); }
+(assert_return (invoke "get_agg_dist" (i32.const 0)) (i32.const 3))
+(assert_return (invoke "get_agg_dist_bucket" (i32.const 0) (i32.const 4)) (i32.const 2)) ;; [4, 8)
+(assert_return (invoke "get_agg_dist_bucket" (i32.const 0) (i32.const 5)) (i32.const 1)) ;; [8, 16)
+;; WHAMM --> wasm:opcode:br_if:before { @lin = lquantize(local1, 6, 9, 1); }
+(assert_return (invoke "get_agg_lin_bucket" (i32.const 0) (i32.const 0)) (i32.const 1)) ;; < 6
+(assert_return (invoke "get_agg_lin_bucket" (i32.const 0) (i32.const 2)) (i32.const 1)) ;; [7, 8)
+(assert_return (invoke "get_agg_lin_bucket" (i32.const 0) (i32.const 4)) (i32.const 1)) ;; >= 9
@@ -0,0 +1,45 @@
+;; Test `tos` and `local<N>` on `wasm:opcode:br_if`
+
+;; @instrument
+(module
+    ;; Globals
+    (global $var (mut i32) (i32.const 0))
+
+    ;; Global getters
+    (func $get_global_var (result i32)
+        (global.get $var)
+    )
+
+    ;; Auxiliary definitions
+    (func $branch (param $taken i32) (param $val i32)
+        (block $exit
+            (br_if $exit (local.get $taken))
+            (global.set $var (local.get $val))
+        )
+    )
+
+    ;; Test case functions
+    (func $start
+        (call $branch (i32.const 0) (i32.const 5))
+        (call $branch (i32.const 1) (i32.const 7))
+        (call $branch (i32.const 1) (i32.const 9))
+    )
+
+    (start $start)
+    (export "get_global_var" (func $get_global_var))
+    (memory (;0;) 1)
+)
+
+;; -------------------------------------
+;; ==== SITE VARS, predicate, `tos` ====
+;; WHAMM --> i32 count; wasm:opcode:br_if:before / tos != 0 / { count++; }
+(assert_return (invoke "get_count") (i32.const 2)) ;; taken twice
+;; WHAMM --> i32 count; wasm:opcode:br_if:before / tos == 0 / { count++; }
+(assert_return (invoke "get_count") (i32.const 1)) ;; not taken once
+
+;; ------------------------------------
+;; ==== SITE VARS, body, `local<N>` ====
+;; WHAMM --> i32 count; wasm:opcode:br_if:before { count = count + local1; }
+(assert_return (invoke "get_count") (i32.const 21)) ;; 5 + 7 + 9
+;; WHAMM --> i32 count; wasm:opcode:br_if:before { local1 = 0; }
+(assert_return (invoke "get_global_var") (i32.const 0)) ;; the write to the local is visible to the app
@@ -0,0 +1,74 @@
+;; Test chaining `wasm:opcode:call:alt` probes
+
+;; @instrument
+(module
+    ;; Globals
+    (global $var (mut i32) (i32.const 0))
+
+    ;; Global getters
+    (func $get_global_var (result i32)
+        (global.get $var)
+    )
+
+    ;; Auxiliary definitions
+    (func $dummy_five_params (param i32 i32 i32 i32 i32)
+        local.get 0
+        local.get 1
+        i32.add
+        local.get 2
+        i32.add
+        local.get 3
+        i32.add
+        local.get 4
+        i32.add
+        global.set $var
+    )
+
+    ;; Test case functions
+    (func $five_params
+        (call $dummy_five_params (i32.const 0) (i32.const 1) (i32.const 2) (i32.const 3) (i32.const 4))
+    )
+
+    (start $five_params)
+    (export "five_params" (func $five_params))
+    (export "get_global_var" (func $get_global_var))
+    (memory (;0;) 1)
+)
+
+;; ======================================
+;; ---- `CALL`: chained `alt` probes ----
+;; ======================================
+
+;; -------------------------------------
+;; ==== the first alt that holds runs ====
+;; WHAMM --> i32 count; wasm:opcode:call:alt / arg0 == 1 / { count = 1; } wasm:opcode:call:alt / arg0 == 0 / { count = 2; }
+(assert_return (invoke "get_global_var") (i32.const 0)) ;; alt, so global should not change
+(assert_return (invoke "get_count") (i32.const 2))
+;; WHAMM --> i32 count; wasm:opcode:call:alt / arg0 == 0 / { count = 1; } wasm:opcode:call:alt / arg1 == 1 / { count = 2; }
+(assert_return (invoke "get_global_var") (i32.const 0))
+(assert_return (invoke "get_count") (i32.const 1))
+;; WHAMM --> i32 count; wasm:opcode:call:alt / arg0 == 1 / { count = 1; } wasm:opcode:call:alt / arg1 == 2 / { count = 2; } wasm:opcode:call:alt / arg4 == 4 / { count = 3; }
+(assert_return (invoke "get_global_var") (i32.const 0))
+(assert_return (invoke "get_count") (i32.const 3))
+
+;; ------------------------------------------------
+;; ==== none of the alts hold, fall back to the call ====
+;; WHAMM --> i32 count; wasm:opcode:call:alt / arg0 == 1 / { count = 1; } wasm:opcode:call:alt / arg1 == 2 / { count = 2; }
+;; @passes_uninstr
+(assert_return (invoke "get_global_var") (i32.const 10)) ;; preds == false, so global should change
+(assert_return (invoke "get_count") (i32.const 0))
+
+;; -------------------------------------------
+;; ==== an alt without a predicate ends the chain ====
+;; WHAMM --> i32 count; wasm:opcode:call:alt / arg0 == 1 / { count = 1; } wasm:opcode:call:alt { count = 2; }
+(assert_return (invoke "get_global_var") (i32.const 0))
+(assert_return (invoke "get_count") (i32.const 2))
+
+;; ----------------------------------------
+;; ==== a higher priority alt is tried first ====
+;; WHAMM --> i32 count; wasm:opcode:call:alt / arg0 == 0 / { count = 1; } @priority(1) wasm:opcode:call:alt / arg1 == 1 / { count = 2; }
+(assert_return (invoke "get_global_var") (i32.const 0))
+(assert_return (invoke "get_count") (i32.const 2))
+;; WHAMM --> i32 count; @priority(-1) wasm:opcode:call:alt { count = 1; } wasm:opcode:call:alt / arg4 == 4 / { count = 2; }
+(assert_return (invoke "get_global_var") (i32.const 0))
+(assert_return (invoke "get_count") (i32.const 2))
@@ -0,0 +1,45 @@
+;; Test `wasm:opcode:call` event with arguments that aren't all i32's
+
+;; @instrument
+(module
+    ;; Globals
+    (global $var (mut i32) (i32.const 0))
+
+    ;; Global getters
+    (func $get_global_var (result i32)
+        (global.get $var)
+    )
+
+    ;; Auxiliary definitions
+    (func $dummy_mixed_params (param i64 f64 i32)
+        local.get 2
+        global.set $var
+    )
+
+    ;; Test case functions
+    (func $mixed_params
+        (call $dummy_mixed_params (i64.const 7) (f64.const 2.5) (i32.const 3))
+    )
+
+    (start $mixed_params)
+    (export "mixed_params" (func $mixed_params))
+    (export "get_global_var" (func $get_global_var))
+    (memory (;0;) 1)
+)
+
+;; ---------------------------------------
+;; ==== ARGS, predicate, i64 and f64 ====
+;; WHAMM --> i32 count; wasm:opcode:call:before / arg0 == 7 / { count++; }
+(assert_return (invoke "get_count") (i32.const 1))
+;; WHAMM --> i32 count; wasm:opcode:call:before / arg0 != 7 / { count++; }
+(assert_return (invoke "get_count") (i32.const 0))
+;; WHAMM --> i32 count; wasm:opcode:call:before / arg1 > 2 && arg1 < 3 / { count++; }
+(assert_return (invoke "get_count") (i32.const 1))
+;; WHAMM --> i32 count; wasm:opcode:call:alt / arg0 + 1 == 8 / { count = arg2; }
+(assert_return (invoke "get_global_var") (i32.const 0)) ;; alt, so global should not change
+(assert_return (invoke "get_count") (i32.const 3))
+
+;; ----------------------------
+;; ==== ARGS, body, i64 ====
+;; WHAMM --> i64 total; i32 count; wasm:opcode:call:before { total = arg0 * 2; count = total == 14 ? 1 : 0; }
+(assert_return (invoke "get_count") (i32.const 1))
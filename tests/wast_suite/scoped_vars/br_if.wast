@@ -0,0 +1,46 @@
+;; Test variables scoped to a probe firing (`this.x`) or a function activation (`frame.x`)
+
+;; @instrument
+(module
+    ;; Globals
+    (global $var (mut i32) (i32.const 0))
+
+    ;; Global getters
+    (func $get_global_var (result i32)
+        (global.get $var)
+    )
+
+    ;; Auxiliary definitions
+    (func $branch (param $taken i32) (param $val i32)
+        (block $exit
+            (br_if $exit (local.get $taken))
+            (global.set $var (local.get $val))
+        )
+    )
+
+    ;; Test case functions
+    (func $start
+        (call $branch (i32.const 1) (i32.const 7))
+        (call $branch (i32.const 1) (i32.const 9))
+        (call $branch (i32.const 0) (i32.const 5))
+    )
+
+    (start $start)
+    (export "get_global_var" (func $get_global_var))
+    (memory (;0;) 1)
+)
+
+;; -----------------------------------
+;; ==== SCOPED VARS, `this.x` ====
+;; the `after` probe only runs when the branch falls through, on the last call
+;; WHAMM --> i32 this.val; i32 total; wasm:opcode:br_if:before { this.val = local1; } wasm:opcode:br_if:after { total = total + this.val; }
+(assert_return (invoke "get_total") (i32.const 5))
+(assert_return (invoke "get_global_var") (i32.const 5)) ;; sanity check
+;; WHAMM --> bool this.taken; i32 count; wasm:opcode:br_if:before { this.taken = tos != 0; } wasm:opcode:br_if:after / !this.taken / { count++; }
+(assert_return (invoke "get_count") (i32.const 1))
+
+;; -----------------------------------
+;; ==== SCOPED VARS, `frame.x` ====
+;; reset on each call, a global would have counted all 3 branches
+;; WHAMM --> i32 frame.fired; i32 seen; wasm:opcode:br_if:before { frame.fired++; } wasm:opcode:br_if:after { seen = frame.fired; }
+(assert_return (invoke "get_seen") (i32.const 1))